@@ -1,8 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use hgrep::grep::BufReadExt;
 use hgrep_bench::read_package_lock_json;
+use std::env;
 use std::fs;
 use std::path::Path;
+use std::process;
 
 fn prepare() -> Vec<u8> {
     let data_dir = Path::new("..").join("testdata").join("chunk");
@@ -95,5 +97,43 @@ fn large_file(c: &mut Criterion) {
     });
 }
 
-criterion_group!(chunk, testdata_dir, large_file);
+#[inline]
+fn count_chunks_read_ahead(data: &[u8], depth: usize) -> usize {
+    let files = data.grep_lines().chunks_per_file(2, 4, None).unwrap();
+    let mut total = 0;
+    for f in files.read_ahead(depth) {
+        total += f.unwrap().chunks.len();
+    }
+    total
+}
+
+fn many_files(c: &mut Criterion) {
+    let dir = env::temp_dir().join(format!("hgrep-bench-many-files-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut buf = String::new();
+    for i in 0..500 {
+        let path = dir.join(format!("{}.txt", i));
+        let mut contents = String::new();
+        for n in 1..=2000 {
+            let line = format!("this is the line {} of the file {}\n", n, i);
+            if n % 100 == 0 {
+                buf += &format!("{}:{}:{}", path.to_str().unwrap(), n, line);
+            }
+            contents += &line;
+        }
+        fs::write(&path, contents).unwrap();
+    }
+    let data = buf.into_bytes();
+
+    c.bench_function("chunk::many_files_no_read_ahead", |b| {
+        b.iter(|| black_box(count_chunks_read_ahead(&data, 0)))
+    });
+    c.bench_function("chunk::many_files_read_ahead_4", |b| {
+        b.iter(|| black_box(count_chunks_read_ahead(&data, 4)))
+    });
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(chunk, testdata_dir, large_file, many_files);
 criterion_main!(chunk);
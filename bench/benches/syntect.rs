@@ -60,8 +60,12 @@ fn load_assets(c: &mut Criterion) {
 fn print_files(c: &mut Criterion) {
     #[inline]
     fn run(files: Vec<File>, assets: SyntectAssets) {
+        run_with_opts(files, assets, printer_opts())
+    }
+
+    #[inline]
+    fn run_with_opts(files: Vec<File>, assets: SyntectAssets, opts: PrinterOptions<'_>) {
         let sink = Sink(Mutex::new(vec![]));
-        let opts = printer_opts();
         let mut printer = SyntectPrinter::with_assets(assets, sink, opts);
         files
             .into_par_iter()
@@ -92,6 +96,26 @@ fn print_files(c: &mut Criterion) {
     c.bench_function("syntect::print-tiny", |b| {
         b.iter(|| run(files.clone(), assets.clone()))
     });
+
+    // Tree containing many copies of the same file like vendored dependencies
+    let readme = Path::new("..").join("README.md");
+    let contents = fs::read_to_string(readme).unwrap();
+    let files: Vec<_> = (0..20)
+        .flat_map(|i| {
+            let path = Path::new("vendor").join(i.to_string()).join("README.md");
+            create_files_for_contents(contents.clone(), &path, 100)
+        })
+        .collect();
+    c.bench_function("syntect::print-duplicates", |b| {
+        b.iter(|| run(files.clone(), assets.clone()))
+    });
+    c.bench_function("syntect::print-duplicates-cache", |b| {
+        b.iter(|| {
+            let mut opts = printer_opts();
+            opts.render_cache = true;
+            run_with_opts(files.clone(), assets.clone(), opts)
+        })
+    });
 }
 
 fn with_ripgrep(c: &mut Criterion) {
@@ -1,5 +1,8 @@
+use crate::broken_pipe::IgnoreBrokenPipe;
 use crate::chunk::File;
-use crate::printer::{Printer, PrinterOptions, TermColorSupport, TextWrapMode};
+use crate::printer::{
+    display_path, strip_path_prefix, Printer, PrinterOptions, TermColorSupport, TextWrapMode,
+};
 use anyhow::Result;
 use bat::assets::HighlightingAssets;
 use bat::config::{Config, VisibleLines};
@@ -9,6 +12,7 @@ use bat::line_range::{HighlightedLineRanges, LineRange, LineRanges};
 use bat::style::{StyleComponent, StyleComponents};
 use bat::WrappingMode;
 use std::env;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -35,24 +39,22 @@ pub struct BatPrinter<'main> {
     opts: PrinterOptions<'main>,
     config: Config<'main>,
     assets: HighlightingAssets,
+    output: Option<Mutex<Box<dyn Write + Send + 'main>>>,
 }
 
 impl<'main> BatPrinter<'main> {
     pub fn new(opts: PrinterOptions<'main>) -> Self {
-        let styles = if opts.grid {
-            &[
-                StyleComponent::LineNumbers,
-                StyleComponent::Snip,
-                StyleComponent::HeaderFilename,
-                StyleComponent::Grid,
-            ][..]
-        } else {
-            &[
-                StyleComponent::LineNumbers,
-                StyleComponent::Snip,
-                StyleComponent::HeaderFilename,
-            ][..]
-        };
+        let mut styles = vec![
+            StyleComponent::LineNumbers,
+            StyleComponent::Snip,
+            StyleComponent::HeaderFilename,
+        ];
+        if opts.grid {
+            styles.push(StyleComponent::Grid);
+        }
+        if opts.matches_only {
+            styles.retain(|s| *s != StyleComponent::Snip); // Matched lines are printed without separators
+        }
 
         let wrapping_mode = match opts.text_wrap {
             TextWrapMode::Char => WrappingMode::Character,
@@ -62,14 +64,14 @@ impl<'main> BatPrinter<'main> {
         let mut config = Config {
             colored_output: true,
             term_width: opts.term_width as usize,
-            style_components: StyleComponents::new(styles),
-            tab_width: opts.tab_width,
+            style_components: StyleComponents::new(&styles),
+            tab_width: opts.tab.width(),
             true_color: opts.color_support == TermColorSupport::True,
             wrapping_mode,
             ..Default::default()
         };
 
-        if let Some(theme) = &opts.theme {
+        if let Some(theme) = opts.resolved_theme() {
             config.theme = theme.to_string();
         } else if opts.color_support == TermColorSupport::Ansi16 {
             config.theme = "ansi".to_string();
@@ -87,9 +89,17 @@ impl<'main> BatPrinter<'main> {
             opts,
             assets,
             config,
+            output: None,
         }
     }
 
+    // Write the output to the writer instead of stdout. bat renders each file into a buffer and then the buffer is
+    // written to the writer
+    pub fn output<W: Write + Send + 'main>(mut self, out: W) -> Self {
+        self.output = Some(Mutex::new(Box::new(out)));
+        self
+    }
+
     pub fn themes(&self) -> impl Iterator<Item = &str> {
         self.assets.themes()
     }
@@ -107,6 +117,17 @@ impl<'main> BatPrinter<'main> {
         Ok(())
     }
 
+    // Print only the theme names one per line for --theme-names
+    pub fn list_theme_names<W: Write>(&self, mut out: W) -> Result<()> {
+        let mut themes: Vec<_> = self.assets.themes().collect();
+        themes.sort_unstable();
+        themes
+            .into_iter()
+            .try_for_each(|theme| writeln!(out, "{}", theme))
+            .ignore_broken_pipe()?;
+        Ok(())
+    }
+
     pub fn print(&self, file: File) -> Result<()> {
         if file.chunks.is_empty() || file.line_matches.is_empty() {
             return Ok(()); // Ensure to print some match
@@ -137,13 +158,20 @@ impl<'main> BatPrinter<'main> {
             .map(|(s, e)| LineRange::new(*s as usize, *e as usize));
         let ranges = if self.opts.first_only {
             ranges.take(1).collect()
+        } else if self.opts.last_only {
+            ranges.rev().take(1).collect()
         } else {
             ranges.collect()
         };
         config.visible_lines = VisibleLines::Ranges(LineRanges::from(ranges));
 
-        let input =
+        let mut input =
             Input::from_reader(Box::new(file.contents.as_bytes())).with_name(Some(&file.path));
+        if self.opts.path_separator.is_some() || self.opts.strip_prefix.is_some() {
+            let path = strip_path_prefix(&file.path, self.opts.strip_prefix);
+            let title = display_path(path, self.opts.path_separator).into_owned();
+            input.description_mut().set_title(Some(title));
+        }
 
         let ranges = file
             .line_matches
@@ -156,11 +184,24 @@ impl<'main> BatPrinter<'main> {
 
         config.highlighted_lines = HighlightedLineRanges(LineRanges::from(ranges));
 
-        if !self.opts.grid {
-            print!("\n\n"); // Empty lines as files separator
+        let controller = Controller::new(&config, &self.assets);
+
+        if let Some(output) = &self.output {
+            let mut buf = String::new();
+            if !self.opts.grid {
+                buf.push_str(&"\n".repeat(self.opts.file_gap)); // Empty lines as files separator
+            }
+            if !controller.run(vec![input], Some(&mut buf))? {
+                anyhow::bail!("Could not print file {:?} by bat printer", file.path);
+            }
+            let mut output = output.lock().unwrap();
+            output.write_all(buf.as_bytes()).ignore_broken_pipe()?;
+            return Ok(output.flush().ignore_broken_pipe()?);
         }
 
-        let controller = Controller::new(&config, &self.assets);
+        if !self.opts.grid {
+            print!("{}", "\n".repeat(self.opts.file_gap)); // Empty lines as files separator
+        }
 
         // Note: controller.run() returns true when no error
         // Note: `Controller::run_with_error_handler` because it requires `Fn` (not `FnMut`) for the handler type.
@@ -182,6 +223,7 @@ impl<'main> Printer for Mutex<BatPrinter<'main>> {
 mod tests {
     use super::*;
     use crate::chunk::LineMatch;
+    use crate::printer::TabMode;
 
     fn sample_file() -> File {
         let path = PathBuf::from("test.rs");
@@ -201,7 +243,7 @@ mod tests {
     #[test]
     fn test_print_with_flags() {
         let opts = PrinterOptions {
-            tab_width: 2,
+            tab: TabMode::Expand(2),
             theme: Some("Nord"),
             grid: false,
             text_wrap: TextWrapMode::Never,
@@ -212,6 +254,77 @@ mod tests {
         p.print(f).unwrap();
     }
 
+    #[test]
+    fn test_print_to_output() {
+        let mut buf = vec![];
+        let p = BatPrinter::new(PrinterOptions::default()).output(&mut buf);
+        p.print(sample_file()).unwrap();
+        drop(p);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("test.rs"), "{:?}", out);
+        assert!(out.contains("main"), "{:?}", out);
+    }
+
+    #[test]
+    fn test_list_theme_names() {
+        let mut buf = vec![];
+        BatPrinter::new(PrinterOptions::default())
+            .list_theme_names(&mut buf)
+            .unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        let names: Vec<_> = out.lines().collect();
+        let mut sorted = names.clone();
+        sorted.sort_unstable();
+        assert_eq!(names, sorted);
+        assert!(names.contains(&"Nord"), "{:?}", names);
+    }
+
+    #[test]
+    fn test_file_gap() {
+        for (gap, want) in [(2, "\n\n"), (1, "\n"), (0, "")] {
+            let opts = PrinterOptions {
+                grid: false,
+                file_gap: gap,
+                ..Default::default()
+            };
+            let mut buf = vec![];
+            let p = BatPrinter::new(opts).output(&mut buf);
+            p.print(sample_file()).unwrap();
+            drop(p);
+            let out = String::from_utf8(buf).unwrap();
+            let gap = out.len() - out.trim_start_matches('\n').len();
+            assert_eq!(gap, want.len(), "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn test_last_only() {
+        let path = PathBuf::from("test.rs");
+        let lmats = vec![LineMatch::lnum(1), LineMatch::lnum(5)];
+        let contents = "// first\n\n\n\n// last\n".to_string();
+        for (chunks, want, not_want) in [
+            (vec![(1, 1), (5, 5)], "last", Some("first")),
+            (vec![(1, 5)], "last", None), // Single chunk is shown entirely
+        ] {
+            let opts = PrinterOptions {
+                last_only: true,
+                color_support: TermColorSupport::Ansi16,
+                ..Default::default()
+            };
+            let mut buf = vec![];
+            let p = BatPrinter::new(opts).output(&mut buf);
+            let f = File::new(path.clone(), lmats.clone(), chunks, contents.clone());
+            p.print(f).unwrap();
+            drop(p);
+            let out = String::from_utf8(buf).unwrap();
+            assert!(out.contains(want), "{:?}", out);
+            match not_want {
+                Some(s) => assert!(!out.contains(s), "{:?}", out),
+                None => assert!(out.contains("first"), "{:?}", out),
+            }
+        }
+    }
+
     #[test]
     fn test_print_nothing() {
         let p = BatPrinter::new(PrinterOptions::default());
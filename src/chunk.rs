@@ -1,32 +1,97 @@
 use crate::grep::GrepMatch;
 use anyhow::{Context as _, Result};
 use encoding_rs::{Encoding, UTF_8};
-use memchr::{memchr2, memchr_iter, Memchr};
+use memchr::{memchr, memchr2, memchr_iter, Memchr};
 use pathdiff::diff_paths;
 use std::cmp;
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
-use std::iter::Peekable;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+// Warn the file which took longer than the threshold to read and search for --warn-slow
+pub fn warn_slow_file(path: &Path, start: Instant, threshold: Duration) {
+    let elapsed = start.elapsed();
+    if elapsed > threshold {
+        eprintln!(
+            "\x1b[1;93mwarning:\x1b[0m File {:?} took {}ms to read and search",
+            path,
+            elapsed.as_millis(),
+        );
+    }
+}
 
-fn decode_text(mut bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> String {
+pub(crate) fn encoding_for_label(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| anyhow::anyhow!("Unknown encoding name: {label:?}"))
+}
+
+// Encoding used for decoding file contents and whether BOM was found at the head of the contents
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct DecodedAs {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+// Report how the file was decoded for --debug-encoding
+fn debug_encoding(path: &Path, decoded: DecodedAs) {
+    eprintln!(
+        "\x1b[1;96mdebug:\x1b[0m File {:?} was decoded as {} ({})",
+        path,
+        decoded.encoding.name(),
+        if decoded.bom { "BOM found" } else { "no BOM" },
+    );
+}
+
+fn decode_text(mut bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> (String, DecodedAs) {
     if let Some(encoding) = encoding {
-        return encoding.decode_with_bom_removal(&bytes).0.into_owned();
+        // BOM is removed only when it matches the specified encoding
+        let bom = Encoding::for_bom(&bytes).is_some_and(|(e, _)| e == encoding);
+        let text = encoding.decode_with_bom_removal(&bytes).0.into_owned();
+        return (text, DecodedAs { encoding, bom });
     }
 
+    let mut bom = false;
     if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
         if encoding == UTF_8 {
             bytes.drain(..bom_len); // Strip UTF-8 BOM from file (#20)
+            bom = true;
         } else {
-            return encoding
+            let text = encoding
                 .decode_without_bom_handling(&bytes[bom_len..])
                 .0
                 .into_owned();
+            return (
+                text,
+                DecodedAs {
+                    encoding,
+                    bom: true,
+                },
+            );
         }
     }
 
-    String::from_utf8(bytes)
-        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    let text = String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+    let encoding = UTF_8;
+    (text, DecodedAs { encoding, bom })
+}
+
+// Heuristic to detect binary file contents. The file is considered binary when it contains NUL byte in the first 1KB
+// except for UTF-16 text which starts with BOM. Note that this is not applied when an encoding is explicitly specified.
+fn looks_binary(bytes: &[u8]) -> bool {
+    const HEAD_LEN: usize = 1024;
+    if Encoding::for_bom(bytes).is_some() {
+        return false;
+    }
+    let head = &bytes[..cmp::min(bytes.len(), HEAD_LEN)];
+    memchr(0, head).is_some()
 }
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
@@ -35,6 +100,8 @@ pub struct LineMatch {
     pub line_number: u64,
     // Byte offsets of start/end positions within the line. Inherit from GrepMatch
     pub ranges: Vec<(usize, usize)>,
+    // Inherit from GrepMatch
+    pub continued: bool,
 }
 
 impl LineMatch {
@@ -42,6 +109,7 @@ impl LineMatch {
         Self {
             line_number,
             ranges,
+            continued: false,
         }
     }
 
@@ -49,6 +117,36 @@ impl LineMatch {
         Self {
             line_number,
             ranges: vec![],
+            continued: false,
+        }
+    }
+}
+
+// Sort the regions and merge overlapping or adjacent ones so that they are disjoint. Empty regions are removed since
+// they highlight nothing.
+pub fn merge_regions(regions: &mut Vec<(usize, usize)>) {
+    regions.retain(|(s, e)| s < e);
+    regions.sort_unstable();
+    let mut merged = 0;
+    for idx in 0..regions.len() {
+        let (s, e) = regions[idx];
+        match regions[..merged].last_mut() {
+            Some((_, last_end)) if s <= *last_end => *last_end = cmp::max(*last_end, e),
+            _ => {
+                regions[merged] = (s, e);
+                merged += 1;
+            }
+        }
+    }
+    regions.truncate(merged);
+}
+
+impl From<GrepMatch> for LineMatch {
+    fn from(m: GrepMatch) -> Self {
+        Self {
+            line_number: m.line_number,
+            ranges: m.ranges,
+            continued: m.continued,
         }
     }
 }
@@ -60,6 +158,8 @@ pub struct File {
     pub line_matches: Box<[LineMatch]>,
     pub chunks: Box<[(u64, u64)]>, // Start/End line number of the chunk
     pub contents: Box<str>,
+    // Occurrences of the pattern in context lines. Only set with --highlight-matches-in-context-lines
+    pub context_matches: Box<[LineMatch]>,
 }
 
 impl File {
@@ -74,6 +174,7 @@ impl File {
             line_matches: lm.into_boxed_slice(),
             chunks: chunks.into_boxed_slice(),
             contents: contents.into_boxed_str(),
+            context_matches: Box::default(),
         }
     }
 
@@ -100,6 +201,21 @@ fn print_sqrt<S: AsRef<str>>(input: S) {
         )
     }
 
+    // Read the whole file as one chunk without any match. This is used for rendering a file without searching
+    pub fn read_whole(path: PathBuf, encoding: Option<&str>) -> Result<Self> {
+        let encoding = encoding.map(encoding_for_label).transpose()?;
+        let bytes =
+            fs::read(&path).with_context(|| format!("Could not open the file {:?}", path))?;
+        let (contents, _) = decode_text(bytes, encoding);
+        let last_lnum = LinesInclusive::new(&contents).count() as u64;
+        let chunks = if last_lnum > 0 {
+            vec![(1, last_lnum)]
+        } else {
+            vec![]
+        };
+        Ok(Self::new(path, vec![], chunks, contents))
+    }
+
     pub fn first_line(&self) -> &str {
         let mut line = self.contents.as_ref();
         if let Some(idx) = memchr2(b'\n', b'\r', line.as_bytes()) {
@@ -107,8 +223,30 @@ fn print_sqrt<S: AsRef<str>>(input: S) {
         }
         line
     }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortKey {
+    Path,
+    Modified,
 }
 
+// Sort files in place. The sort is stable so files with the same key keep their order even when reversed.
+pub fn sort_files(files: &mut [File], key: SortKey, reverse: bool) {
+    use cmp::Reverse;
+    match (key, reverse) {
+        (SortKey::Path, false) => files.sort_by(|l, r| l.path.cmp(&r.path)),
+        (SortKey::Path, true) => files.sort_by(|l, r| r.path.cmp(&l.path)),
+        (SortKey::Modified, false) => files.sort_by_cached_key(|f| f.modified()),
+        (SortKey::Modified, true) => files.sort_by_cached_key(|f| Reverse(f.modified())),
+    }
+}
+
+#[derive(Clone)]
 pub struct LinesInclusive<'a> {
     lnum: u64,
     prev: usize,
@@ -148,6 +286,7 @@ impl<'a> Iterator for LinesInclusive<'a> {
 }
 
 // Optimized version of str::Lines with line numbers
+#[derive(Clone)]
 struct Lines<'a>(LinesInclusive<'a>);
 
 impl<'a> Lines<'a> {
@@ -167,13 +306,268 @@ impl<'a> Iterator for Lines<'a> {
     }
 }
 
+// Parse size string like "10M". Suffixes K, M and G are supported for kilobytes, megabytes and gigabytes
+pub fn parse_size(input: &str) -> Result<u64> {
+    if input.is_empty() {
+        anyhow::bail!("Size string must not be empty");
+    }
+
+    let i = input.len() - 1;
+    let (input, mag) = match input.as_bytes()[i] {
+        b'k' | b'K' => (&input[..i], 1 << 10),
+        b'm' | b'M' => (&input[..i], 1 << 20),
+        b'g' | b'G' => (&input[..i], 1 << 30),
+        _ => (input, 1),
+    };
+
+    let u: u64 = input
+        .parse()
+        .with_context(|| format!("Could not parse {:?} as unsigned integer", input))?;
+
+    Ok(u * mag)
+}
+
+enum Contents {
+    Read(io::Result<Vec<u8>>),
+    TooLarge(u64), // Size of the file in bytes
+}
+
+impl Contents {
+    fn read(path: &Path, max_filesize: Option<u64>) -> Self {
+        if let Some(max) = max_filesize {
+            // When the metadata is not available, the following `fs::read` reports the error
+            if let Ok(meta) = fs::metadata(path) {
+                if meta.len() > max {
+                    return Self::TooLarge(meta.len());
+                }
+            }
+        }
+        Self::Read(fs::read(path))
+    }
+}
+
+struct ReadJob {
+    path: PathBuf,
+    max_filesize: Option<u64>,
+    canceled: Arc<AtomicBool>,
+    result: Sender<Contents>,
+}
+
+// File being read by `ReadFiles`. The contents are sent via the receiver
+struct Reading {
+    path: PathBuf,
+    canceled: Arc<AtomicBool>,
+    result: Receiver<Contents>,
+}
+
+impl Reading {
+    // The job is skipped when it is not started yet. Otherwise the contents are discarded
+    fn cancel(&self) {
+        self.canceled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Fixed number of threads reading files in the order of the requests. Threads are stopped and joined on drop
+struct ReadFiles {
+    jobs: Option<Sender<ReadJob>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ReadFiles {
+    fn new(num_threads: usize) -> Self {
+        let (tx, rx) = mpsc::channel::<ReadJob>();
+        let rx = Arc::new(Mutex::new(rx));
+        let threads = (0..num_threads)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || loop {
+                    let recv = rx.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                    let Ok(job) = recv else {
+                        return; // Sender was dropped
+                    };
+                    if !job.canceled.load(Ordering::Relaxed) {
+                        let contents = Contents::read(&job.path, job.max_filesize);
+                        let _ = job.result.send(contents); // Receiver is dropped when the job was canceled
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(tx),
+            threads,
+        }
+    }
+
+    fn read(&self, path: PathBuf, max_filesize: Option<u64>) -> Reading {
+        let canceled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let job = ReadJob {
+            path: path.clone(),
+            max_filesize,
+            canceled: canceled.clone(),
+            result: tx,
+        };
+        // Sending never fails since receivers are alive until `self` is dropped
+        self.jobs.as_ref().unwrap().send(job).unwrap();
+        Reading {
+            path,
+            canceled,
+            result: rx,
+        }
+    }
+}
+
+impl Drop for ReadFiles {
+    fn drop(&mut self) {
+        self.jobs = None; // Stop the threads after the jobs in the queue are handled
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+// Peekable iterator of grep matches which reads the matched files ahead on background threads. Reading files is
+// the most expensive part of calculating chunks so the next files are read while the current file is chunked and
+// printed. Files are read in the order of the matches so the results are the same as reading them one by one
+struct ReadAhead<I: Iterator> {
+    iter: I,
+    buf: VecDeque<I::Item>,
+    // Files being read in the order of the matches. The first one is the file of the next matches
+    reading: VecDeque<Reading>,
+    readers: Option<ReadFiles>, // Threads are spawned when the first file is read ahead
+    last_path: Option<PathBuf>,
+    depth: usize,
+    max_filesize: Option<u64>,
+    done: bool,
+}
+
+impl<I: Iterator> ReadAhead<I> {
+    fn new(iter: I) -> Self {
+        Self {
+            iter,
+            buf: VecDeque::new(),
+            reading: VecDeque::new(),
+            readers: None,
+            last_path: None,
+            depth: 0,
+            max_filesize: None,
+            done: false,
+        }
+    }
+}
+
+impl<I: Iterator> Drop for ReadAhead<I> {
+    fn drop(&mut self) {
+        for reading in self.reading.iter() {
+            reading.cancel();
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<GrepMatch>>> ReadAhead<I> {
+    fn pull(&mut self) -> bool {
+        if self.done {
+            return false;
+        }
+        let Some(item) = self.iter.next() else {
+            self.done = true;
+            return false;
+        };
+        match &item {
+            Ok(m) if self.depth > 0 && self.last_path.as_ref() != Some(&m.path) => {
+                let depth = self.depth;
+                let readers = self.readers.get_or_insert_with(|| ReadFiles::new(depth));
+                self.reading
+                    .push_back(readers.read(m.path.clone(), self.max_filesize));
+                self.last_path = Some(m.path.clone());
+            }
+            Ok(_) => {}
+            Err(_) => self.done = true, // Stop reading ahead since the error ends the iteration
+        }
+        self.buf.push_back(item);
+        true
+    }
+
+    // Consume the matches until the files are read ahead as many as the depth. This blocks until the matches of the
+    // files are available
+    fn fill(&mut self) {
+        while self.reading.len() < self.depth && self.pull() {}
+    }
+
+    fn peek(&mut self) -> Option<&I::Item> {
+        if self.buf.is_empty() {
+            self.pull();
+        }
+        self.buf.front()
+    }
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.buf.is_empty() {
+            self.pull();
+        }
+        self.buf.pop_front()
+    }
+
+    // Get the contents of the matched file. When the file was not read ahead, it is read on the current thread
+    fn read(&mut self, path: &Path) -> Contents {
+        while let Some(reading) = self.reading.pop_front() {
+            if reading.path != path {
+                reading.cancel(); // The matches in the file were skipped
+                continue;
+            }
+            if let Ok(contents) = reading.result.recv() {
+                return contents;
+            }
+            break; // The reading thread panicked
+        }
+        Contents::read(path, self.max_filesize)
+    }
+}
+
+// Min and max numbers of context lines before and after matches
+#[derive(Clone, Copy)]
+struct Contexts {
+    before_min: u64,
+    before_max: u64,
+    // Contexts after matches. They are the same as contexts before matches unless ratio is set
+    after_min: u64,
+    after_max: u64,
+}
+
+impl Contexts {
+    // Context lines clamped by the start (or end) of file are added to the contexts after (or before) the matched
+    // lines. `last_line` is the last line number of the file. It is only set when balancing the contexts
+    fn balance(self, match_start: u64, match_end: u64, last_line: Option<u64>) -> Self {
+        let Some(last_line) = last_line else {
+            return self;
+        };
+        let above = match_start.saturating_sub(1);
+        let below = last_line.saturating_sub(match_end);
+        Self {
+            before_min: self.before_min + self.after_min.saturating_sub(below),
+            before_max: self.before_max + self.after_max.saturating_sub(below),
+            after_min: self.after_min + self.before_min.saturating_sub(above),
+            after_max: self.after_max + self.before_max.saturating_sub(above),
+        }
+    }
+}
+
 pub struct Files<I: Iterator> {
-    iter: Peekable<I>,
-    min_context: u64,
-    max_context: u64,
+    iter: ReadAhead<I>,
+    contexts: Contexts,
     saw_error: bool,
     cwd: Option<PathBuf>,
     encoding: Option<&'static Encoding>,
+    skip_binary: bool,
+    first_match_only: bool,
+    min_matches: usize,
+    trim_trailing_blank_lines: bool,
+    per_match_context: bool,
+    match_bounded_context: bool,
+    balance_edge_context: bool,
+    strip_cwd_prefix: bool,
+    warn_slow: Option<Duration>,
+    debug_encoding: bool,
 }
 
 impl<I: Iterator> Files<I> {
@@ -183,23 +577,119 @@ impl<I: Iterator> Files<I> {
         max_context: u64,
         encoding: Option<&str>,
     ) -> Result<Self> {
-        let encoding = if let Some(label) = encoding {
-            let encoding = Encoding::for_label(label.as_bytes())
-                .ok_or_else(|| anyhow::anyhow!("Unknown encoding name: {label:?}"))?;
-            Some(encoding)
-        } else {
-            None
-        };
+        let encoding = encoding.map(encoding_for_label).transpose()?;
 
         Ok(Self {
-            iter: iter.peekable(),
-            min_context,
-            max_context,
+            iter: ReadAhead::new(iter),
+            contexts: Contexts {
+                before_min: min_context,
+                before_max: max_context,
+                after_min: min_context,
+                after_max: max_context,
+            },
             saw_error: false,
             cwd: env::current_dir().ok(),
             encoding,
+            skip_binary: false,
+            first_match_only: false,
+            min_matches: 0,
+            trim_trailing_blank_lines: false,
+            per_match_context: false,
+            match_bounded_context: false,
+            balance_edge_context: false,
+            strip_cwd_prefix: false,
+            warn_slow: None,
+            debug_encoding: false,
         })
     }
+
+    // Scale contexts after matches by the ratio to contexts before matches. The number of lines is rounded to the nearest
+    // integer (half is rounded up). For example, when the ratio is 1.5 and the contexts are 3..6, the contexts after
+    // matches are 5..9. Blank lines shorten the contexts after matches in the same way as before matches
+    pub fn context_ratio(mut self, ratio: f64) -> Self {
+        let scale = |n: u64| (n as f64 * ratio).round() as u64;
+        self.contexts.after_min = scale(self.contexts.before_min);
+        self.contexts.after_max = scale(self.contexts.before_max);
+        self
+    }
+
+    // Skip files whose contents look binary to avoid printing garbage and control sequences to terminal
+    pub fn skip_binary(mut self, yes: bool) -> Self {
+        self.skip_binary = yes;
+        self
+    }
+
+    // Reduce each file to its first matched line and one chunk around it. Lines of a multi-line match are kept
+    pub fn first_match_only(mut self, yes: bool) -> Self {
+        self.first_match_only = yes;
+        self
+    }
+
+    // Filter out files which have fewer matched lines than the number
+    pub fn min_matches(mut self, num: usize) -> Self {
+        self.min_matches = num;
+        self
+    }
+
+    // Remove blank lines at the end of each chunk. Blank lines between matches are kept
+    pub fn trim_trailing_blank_lines(mut self, yes: bool) -> Self {
+        self.trim_trailing_blank_lines = yes;
+        self
+    }
+
+    // Calculate contexts of each match independently instead of merging close matches into one chunk with all lines
+    // between them. Lines out of the contexts of all matches are omitted even if the matches are close
+    pub fn per_match_context(mut self, yes: bool) -> Self {
+        self.per_match_context = yes;
+        self
+    }
+
+    // Determine boundaries of chunks by neighboring matches instead of the number of lines. Lines between matches are
+    // all included in one chunk unless a blank line separates them. Contexts before the first match and after the last
+    // match of each chunk end at a blank line or at the max context. Min context is ignored since blank lines always
+    // end contexts, so trailing blank lines never appear in chunks
+    pub fn match_bounded_context(mut self, yes: bool) -> Self {
+        self.match_bounded_context = yes;
+        self
+    }
+
+    // Keep the total number of context lines of a match near the start or end of file. Context lines which are clamped
+    // by the start (or end) of file are added to the context after (or before) the match
+    pub fn balance_edge_context(mut self, yes: bool) -> Self {
+        self.balance_edge_context = yes;
+        self
+    }
+
+    // Skip files larger than the size in bytes to avoid reading huge files into memory
+    pub fn max_filesize(mut self, size: Option<u64>) -> Self {
+        self.iter.max_filesize = size;
+        self
+    }
+
+    // Read the number of files ahead on background threads while the current file is chunked. 0 means reading each
+    // file when it is chunked
+    pub fn read_ahead(mut self, depth: usize) -> Self {
+        self.iter.depth = depth;
+        self
+    }
+
+    // Remove the leading `./` from paths like ripgrep's --strip-cwd-prefix
+    pub fn strip_cwd_prefix(mut self, yes: bool) -> Self {
+        self.strip_cwd_prefix = yes;
+        self
+    }
+
+    // Warn files which take longer than the threshold to read and calculate chunks
+    pub fn warn_slow(mut self, threshold: Option<Duration>) -> Self {
+        self.warn_slow = threshold;
+        self
+    }
+
+    // Report the encoding and BOM of each file to stderr to diagnose issues of decoding
+    pub fn debug_encoding(mut self, yes: bool) -> Self {
+        self.debug_encoding = yes;
+        self
+    }
 }
 
 impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
@@ -207,19 +697,25 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
         &self,
         match_start: u64,
         match_end: u64,
+        last_line: Option<u64>,
         lines: impl Iterator<Item = (&'contents str, u64)>,
     ) -> (u64, u64) {
-        let before_start = cmp::max(match_start.saturating_sub(self.max_context), 1);
-        let before_end = cmp::max(match_start.saturating_sub(self.min_context), 1);
-        let after_start = match_end + self.min_context;
-        let after_end = match_end + self.max_context;
+        let contexts = self.contexts.balance(match_start, match_end, last_line);
+        let before_start = cmp::max(match_start.saturating_sub(contexts.before_max), 1);
+        let before_end = cmp::max(match_start.saturating_sub(contexts.before_min), 1);
+        let after_start = match_end + contexts.after_min;
+        let after_end = match_end + contexts.after_max;
 
         let mut range_start = before_start;
         let mut range_end = after_end;
         let mut last_lnum = None;
+        let mut last_non_blank = match_end;
 
         for (line, lnum) in lines {
             last_lnum = Some(lnum);
+            if match_end < lnum && !line.is_empty() {
+                last_non_blank = lnum;
+            }
             assert!(lnum <= after_end, "line {} > chunk {}", lnum, after_end);
 
             let in_before = before_start <= lnum && lnum < before_end;
@@ -241,55 +737,211 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
         if let Some(n) = last_lnum {
             range_end = cmp::min(range_end, n); // Make end of chunk fit to end of file
         }
+        if self.trim_trailing_blank_lines {
+            range_end = cmp::min(range_end, last_non_blank);
+        }
 
         (range_start, range_end)
     }
 
+    fn per_match_chunks(
+        &self,
+        lmats: &[LineMatch],
+        contents: &str,
+        last_line: Option<u64>,
+    ) -> Vec<(u64, u64)> {
+        let mut chunks: Vec<(u64, u64)> = vec![];
+        let mut lines = Lines::new(contents);
+        let mut next_lnum = 1; // Line number of the next line of `lines`
+        let mut lmats = lmats.iter();
+        while let Some(m) = lmats.next() {
+            // Lines in a multi-line match share one context
+            let mut end = m;
+            while end.continued {
+                match lmats.next() {
+                    Some(next) => end = next,
+                    None => break,
+                }
+            }
+            let (start, end) = (m.line_number, end.line_number);
+
+            // Contexts of matches may overlap. Clone the lines iterator so that it can be reused for the next match
+            let before_max = self.contexts.balance(start, end, last_line).before_max;
+            let before_start = cmp::max(start.saturating_sub(before_max), 1);
+            while next_lnum < before_start {
+                lines.next();
+                next_lnum += 1;
+            }
+            let (s, e) = self.calculate_chunk_range(start, end, last_line, lines.clone());
+
+            match chunks.last_mut() {
+                Some((_, last)) if s <= *last + 1 => *last = cmp::max(*last, e),
+                _ => chunks.push((s, e)),
+            }
+        }
+        chunks
+    }
+
+    fn match_bounded_chunks(&self, lmats: &[LineMatch], contents: &str) -> Vec<(u64, u64)> {
+        let blanks: Vec<bool> = Lines::new(contents).map(|(l, _)| l.is_empty()).collect();
+        let last_line = blanks.len() as u64;
+        let is_blank = |lnum: u64| blanks.get(lnum as usize - 1).copied().unwrap_or(true);
+
+        // Group matches which are not separated by blank lines. Lines of a multi-line match are always in one group
+        let mut groups: Vec<(u64, u64)> = vec![];
+        let mut continued = false;
+        for m in lmats {
+            let lnum = m.line_number;
+            match groups.last_mut() {
+                Some((_, end)) if continued || (*end + 1..lnum).all(|l| !is_blank(l)) => {
+                    *end = cmp::max(*end, lnum);
+                }
+                _ => groups.push((lnum, lnum)),
+            }
+            continued = m.continued;
+        }
+
+        let mut chunks: Vec<(u64, u64)> = vec![];
+        for (first, last) in groups {
+            let mut start = first;
+            while start > 1 && first - start < self.contexts.before_max && !is_blank(start - 1) {
+                start -= 1;
+            }
+            let mut end = last;
+            while end < last_line && end - last < self.contexts.after_max && !is_blank(end + 1) {
+                end += 1;
+            }
+            match chunks.last_mut() {
+                Some((_, prev)) if start <= *prev + 1 => *prev = cmp::max(*prev, end),
+                _ => chunks.push((start, end)),
+            }
+        }
+        chunks
+    }
+
     fn relative_path(&self, path: PathBuf) -> PathBuf {
-        if !path.is_relative() {
-            if let Some(cwd) = &self.cwd {
-                if let Some(diff) = diff_paths(&path, cwd) {
-                    return diff;
+        let path = match &self.cwd {
+            Some(cwd) if !path.is_relative() => diff_paths(&path, cwd).unwrap_or(path),
+            _ => path,
+        };
+        if self.strip_cwd_prefix {
+            if let Ok(stripped) = path.strip_prefix(".") {
+                if !stripped.as_os_str().is_empty() {
+                    return stripped.to_path_buf();
                 }
             }
         }
         path
     }
 
-    fn error_item(&mut self, e: anyhow::Error) -> Option<Result<File>> {
+    // Returns the number of skipped matches
+    fn skip_matches_in(&mut self, path: &Path) -> usize {
+        let mut skipped = 0;
+        while let Some(Ok(next)) = self.iter.peek() {
+            if next.path != path {
+                break;
+            }
+            self.iter.next();
+            skipped += 1;
+        }
+        skipped
+    }
+
+    fn error_item<T>(&mut self, e: anyhow::Error) -> Option<Result<T>> {
         self.saw_error = true;
         Some(Err(e))
     }
-}
 
-impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
-    type Item = Result<File>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    // Returns `Ok(None)` when the file is filtered out
+    fn next_file(&mut self) -> Option<Result<Option<File>>> {
         if self.saw_error {
             return None;
         }
 
-        let GrepMatch {
-            path,
-            mut line_number,
-            ranges,
-        } = match self.iter.next()? {
-            Ok(m) => m,
-            Err(e) => return self.error_item(e),
-        };
-        let contents = match fs::read(&path)
-            .with_context(|| format!("Could not open the matched file {:?}", path))
-        {
-            Ok(vec) => decode_text(vec, self.encoding),
-            Err(err) => return self.error_item(err),
+        let (
+            GrepMatch {
+                path,
+                mut line_number,
+                ranges,
+                continued,
+            },
+            bytes,
+        ) = loop {
+            self.iter.fill();
+            let m = match self.iter.next()? {
+                Ok(m) => m,
+                Err(e) => return self.error_item(e),
+            };
+            let bytes = match self.iter.read(&m.path) {
+                Contents::Read(Ok(vec)) => vec,
+                Contents::Read(Err(err)) => {
+                    let err = anyhow::Error::new(err)
+                        .context(format!("Could not open the matched file {:?}", m.path));
+                    return self.error_item(err);
+                }
+                Contents::TooLarge(size) => {
+                    eprintln!(
+                        "\x1b[1;93mwarning:\x1b[0m Skipped file {:?} since its size {} bytes exceeds --max-filesize",
+                        m.path, size,
+                    );
+                    self.skip_matches_in(&m.path);
+                    continue;
+                }
+            };
+            if self.skip_binary && self.encoding.is_none() && looks_binary(&bytes) {
+                // Discard the rest of matches in the binary file
+                self.skip_matches_in(&m.path);
+                continue;
+            }
+            break (m, bytes);
         };
+        let (contents, decoded) = decode_text(bytes, self.encoding);
+        if self.debug_encoding {
+            debug_encoding(&path, decoded);
+        }
         // Assumes that matched lines are sorted by source location
         let mut lines = Lines::new(&contents);
+        let last_line = self
+            .balance_edge_context
+            .then(|| Lines::new(&contents).count() as u64);
         let mut lmats = vec![LineMatch {
             line_number,
             ranges,
+            continued,
         }];
+
+        if self.first_match_only {
+            let first_match_line = line_number;
+            while lmats.last().unwrap().continued {
+                match self.iter.peek() {
+                    Some(Ok(m)) if m.path == path && m.line_number > line_number => {
+                        let m = self.iter.next().unwrap().unwrap();
+                        line_number = m.line_number;
+                        lmats.push(LineMatch::from(m));
+                    }
+                    _ => break,
+                }
+            }
+            // Files are filtered by the number of matches before reducing them
+            let num_matches = lmats.len() + self.skip_matches_in(&path);
+            if num_matches < self.min_matches {
+                return Some(Ok(None));
+            }
+            let chunks = if self.match_bounded_context {
+                self.match_bounded_chunks(&lmats, &contents)
+            } else {
+                let chunk = self.calculate_chunk_range(
+                    first_match_line,
+                    line_number,
+                    last_line,
+                    &mut lines,
+                );
+                vec![chunk]
+            };
+            let path = self.relative_path(path);
+            return Some(Ok(Some(File::new(path, lmats, chunks, contents))));
+        }
+
         let mut chunks = Vec::new();
 
         'chunks: loop {
@@ -310,10 +962,23 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
                     Some(Ok(m)) if m.line_number <= line_number => {
                         // When the same line number is reported multiple times, ignore the grep line.
                         // This happens when reading output from `rg --vimgrep` (#13)
+                        if m.continued && m.line_number == line_number {
+                            lmats.last_mut().unwrap().continued = true; // Two multi-line matches may share the same line
+                        }
                         self.iter.next();
                         continue;
                     }
-                    Some(Ok(m)) if m.line_number - line_number >= self.max_context * 2 => {
+                    Some(Ok(m))
+                        if m.line_number - line_number
+                            >= self
+                                .contexts
+                                .balance(m.line_number, m.line_number, last_line)
+                                .before_max
+                                + self
+                                    .contexts
+                                    .balance(first_match_line, line_number, last_line)
+                                    .after_max =>
+                    {
                         State::EndOfChunk
                     }
                     Some(Ok(_)) => State::NextMatch,
@@ -321,9 +986,14 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
 
                 // Actions for each states
                 match peeked {
-                    State::EndOfFile | State::EndOfChunk => chunks.push(
-                        self.calculate_chunk_range(first_match_line, line_number, &mut lines),
-                    ),
+                    State::EndOfFile | State::EndOfChunk => {
+                        chunks.push(self.calculate_chunk_range(
+                            first_match_line,
+                            line_number,
+                            last_line,
+                            &mut lines,
+                        ))
+                    }
                     State::Error => {
                         let err = self.iter.next().unwrap().unwrap_err();
                         return self.error_item(err);
@@ -332,7 +1002,7 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
                         // Next match
                         let m = self.iter.next().unwrap().unwrap();
                         line_number = m.line_number;
-                        lmats.push(LineMatch::new(line_number, m.ranges));
+                        lmats.push(LineMatch::from(m));
                     }
                 }
 
@@ -348,15 +1018,45 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
             let m = self.iter.next().unwrap().unwrap();
             line_number = m.line_number;
             // First match line of next chunk
-            lmats.push(LineMatch::new(line_number, m.ranges));
+            lmats.push(LineMatch::from(m));
         }
 
         if chunks.is_empty() {
             return None;
         }
 
+        if lmats.len() < self.min_matches {
+            return Some(Ok(None));
+        }
+
+        if self.per_match_context {
+            chunks = self.per_match_chunks(&lmats, &contents, last_line);
+        } else if self.match_bounded_context {
+            chunks = self.match_bounded_chunks(&lmats, &contents);
+        }
+
         let path = self.relative_path(path);
-        Some(Ok(File::new(path, lmats, chunks, contents)))
+        Some(Ok(Some(File::new(path, lmats, chunks, contents))))
+    }
+}
+
+impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
+    type Item = Result<File>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = Instant::now();
+            match self.next_file()? {
+                Ok(Some(file)) => {
+                    if let Some(threshold) = self.warn_slow {
+                        warn_slow_file(&file.path, start, threshold);
+                    }
+                    return Some(Ok(file));
+                }
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
     }
 }
 
@@ -370,17 +1070,44 @@ mod tests {
     use std::iter;
     use std::path::Path;
 
+    #[test]
+    fn test_merge_regions() {
+        for (input, want) in [
+            (vec![], vec![]),
+            (vec![(1, 3)], vec![(1, 3)]),
+            (vec![(1, 3), (5, 7)], vec![(1, 3), (5, 7)]),
+            (vec![(5, 7), (1, 3)], vec![(1, 3), (5, 7)]),
+            (vec![(1, 5), (3, 7)], vec![(1, 7)]),
+            (vec![(1, 7), (3, 5)], vec![(1, 7)]),
+            (vec![(1, 3), (3, 5)], vec![(1, 5)]),
+            (vec![(3, 5), (1, 4), (0, 2), (8, 9)], vec![(0, 5), (8, 9)]),
+            (vec![(1, 3), (1, 3)], vec![(1, 3)]),
+            (vec![(2, 2), (4, 6), (5, 5)], vec![(4, 6)]),
+        ] {
+            let mut regions = input.clone();
+            merge_regions(&mut regions);
+            assert_eq!(regions, want, "input={:?}", input);
+            assert!(
+                regions.windows(2).all(|w| w[0].1 < w[1].0),
+                "not disjoint: {:?}",
+                regions,
+            );
+        }
+    }
+
     fn test_success_case(inputs: &[&str]) {
         let dir = Path::new("testdata").join("chunk");
 
-        let matches = test::read_all_matches(&dir, inputs);
-        let got: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
-            .unwrap()
-            .collect::<Result<_>>()
-            .unwrap();
         let expected = test::read_all_expected_chunks(&dir, inputs);
-
-        assert_eq!(got, expected);
+        for depth in [0, 1, 4] {
+            let matches = test::read_all_matches(&dir, inputs);
+            let got: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
+                .unwrap()
+                .read_ahead(depth)
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(got, expected, "depth={depth}");
+        }
     }
 
     macro_rules! success_case_tests {
@@ -408,6 +1135,8 @@ mod tests {
         test_blank_max_bottom(["blank_max_bottom"]);
         test_blank_max_top(["blank_max_top"]);
         test_all_blank(["all_blank"]);
+        test_trailing_blank_lines(["trailing_blank_lines"]);
+        test_per_match_context(["per_match_context"]);
         test_top_file_edge(["top_file_edge"]);
         test_top_inner_file_edge(["top_inner_file_edge"]);
         test_min_file_edge(["min_file_edge"]);
@@ -454,6 +1183,29 @@ mod tests {
             line_matches: vec![LineMatch::lnum(8)].into_boxed_slice(),
             chunks: vec![(5, 11)].into_boxed_slice(),
             contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
+            context_matches: Box::default(),
+            path,
+        };
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0], expected);
+    }
+
+    #[test]
+    fn test_fixed_context_ignores_blank_lines() {
+        let dir = Path::new("testdata").join("chunk");
+        let matches = test::read_matches(&dir, "blank_min_max");
+        let got: Vec<_> = Files::new(matches.into_iter(), 3, 3, None)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let path = dir.join("blank_min_max.in");
+        let expected = File {
+            line_matches: vec![LineMatch::lnum(8)].into_boxed_slice(),
+            chunks: vec![(5, 11)].into_boxed_slice(), // Blank lines at line 6 and 10 don't shorten the chunk
+            contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
+            context_matches: Box::default(),
             path,
         };
 
@@ -475,6 +1227,7 @@ mod tests {
             line_matches: vec![LineMatch::lnum(8)].into_boxed_slice(),
             chunks: vec![(8, 8)].into_boxed_slice(),
             contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
+            context_matches: Box::default(),
             path,
         };
 
@@ -491,6 +1244,7 @@ mod tests {
                 path: "Cargo.toml".into(),
                 line_number: lnum,
                 ranges: vec![],
+                continued: false,
             })
         };
         let matches = [mat(1), mat(1), mat(1), mat(2), mat(2), mat(2)];
@@ -527,6 +1281,7 @@ mod tests {
                     path: "Cargo.toml".into(),
                     line_number: 1,
                     ranges: vec![],
+                    continued: false,
                 }),
                 Err(Error::new(DummyError)), // Error at second match
             ],
@@ -584,6 +1339,7 @@ mod tests {
                 path: path.clone(),
                 line_number: 4,
                 ranges: ranges.clone(),
+                continued: false,
             });
             let files = Files::new(iter::once(item), 1, 3, enc)
                 .unwrap()
@@ -595,10 +1351,12 @@ mod tests {
                 line_matches: vec![LineMatch {
                     line_number: 4,
                     ranges,
+                    continued: false,
                 }]
                 .into_boxed_slice(),
                 chunks: vec![(3, 5)].into_boxed_slice(), // Line 3 to 5 should be a chunk because line 2 and line 4 are empty
                 contents: contents.clone().into_boxed_str(),
+                context_matches: Box::default(),
             }];
 
             assert_eq!(files, expected, "read file {file:?} with encoding {enc:?}");
@@ -611,6 +1369,7 @@ mod tests {
             path: PathBuf::from("this-file-does-not-exist"),
             line_number: 1,
             ranges: vec![],
+            continued: false,
         });
         let result = Files::new(iter::once(item), 1, 1, None)
             .unwrap()
@@ -623,6 +1382,495 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_files_min_matches() {
+        let dir = Path::new("testdata").join("chunk");
+        let item = |file, lnum| {
+            Ok(GrepMatch {
+                path: dir.join(file),
+                line_number: lnum,
+                ranges: vec![],
+                continued: false,
+            })
+        };
+        let items = || {
+            [
+                item("so_many_neighbors.in", 3),
+                item("so_many_neighbors.in", 5),
+                item("so_many_neighbors.in", 14),
+                item("single_max.in", 1),
+                item("two_chunks.in", 8),
+                item("two_chunks.in", 23),
+            ]
+            .into_iter()
+        };
+
+        for (min, first_match_only, want) in [
+            (
+                0,
+                false,
+                &["so_many_neighbors.in", "single_max.in", "two_chunks.in"][..],
+            ),
+            (
+                1,
+                false,
+                &["so_many_neighbors.in", "single_max.in", "two_chunks.in"][..],
+            ),
+            (2, false, &["so_many_neighbors.in", "two_chunks.in"][..]),
+            (3, false, &["so_many_neighbors.in"][..]),
+            (4, false, &[][..]),
+            // Matches are counted before reducing files to their first matches
+            (3, true, &["so_many_neighbors.in"][..]),
+        ] {
+            let paths: Vec<_> = Files::new(items(), 3, 6, None)
+                .unwrap()
+                .min_matches(min)
+                .first_match_only(first_match_only)
+                .map(|f| f.unwrap().path)
+                .collect();
+            let want: Vec<_> = want.iter().map(|p| dir.join(p)).collect();
+            assert_eq!(
+                paths, want,
+                "min={min}, first_match_only={first_match_only}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_files_max_filesize() {
+        let dir = Path::new("testdata").join("chunk");
+        let item = |file, lnum| {
+            Ok(GrepMatch {
+                path: dir.join(file),
+                line_number: lnum,
+                ranges: vec![],
+                continued: false,
+            })
+        };
+        // Sizes of the files are 51, 49, and 107 bytes respectively
+        let items = || {
+            [
+                item("so_many_neighbors.in", 3),
+                item("so_many_neighbors.in", 5),
+                item("single_max.in", 1),
+                item("two_chunks.in", 8),
+                item("two_chunks.in", 23),
+            ]
+            .into_iter()
+        };
+
+        for (max, want) in [
+            (
+                None,
+                &["so_many_neighbors.in", "single_max.in", "two_chunks.in"][..],
+            ),
+            (
+                Some(107),
+                &["so_many_neighbors.in", "single_max.in", "two_chunks.in"][..],
+            ),
+            (Some(100), &["so_many_neighbors.in", "single_max.in"][..]),
+            (Some(50), &["single_max.in"][..]),
+            (Some(0), &[][..]),
+        ] {
+            let want: Vec<_> = want.iter().map(|p| dir.join(p)).collect();
+            for depth in [0, 2] {
+                let paths: Vec<_> = Files::new(items(), 3, 6, None)
+                    .unwrap()
+                    .max_filesize(max)
+                    .read_ahead(depth)
+                    .map(|f| f.unwrap().path)
+                    .collect();
+                assert_eq!(paths, want, "max={max:?}, depth={depth}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_files_read_ahead() {
+        let dir = Path::new("testdata").join("chunk");
+        let item = |file, lnum| {
+            Ok(GrepMatch {
+                path: dir.join(file),
+                line_number: lnum,
+                ranges: vec![],
+                continued: false,
+            })
+        };
+        // The same file appears twice and the file which does not exist stops the iteration
+        let items = || {
+            [
+                item("single_max.in", 1),
+                item("two_chunks.in", 8),
+                item("two_chunks.in", 23),
+                item("single_max.in", 1),
+                item("before.in", 5),
+                item("this-file-does-not-exist.in", 1),
+                item("after.in", 1),
+            ]
+            .into_iter()
+        };
+        let collect = |depth| {
+            Files::new(items(), 3, 6, None)
+                .unwrap()
+                .read_ahead(depth)
+                .map(|f| f.map_err(|e| format!("{e}")))
+                .collect::<Vec<_>>()
+        };
+
+        let want = collect(0);
+        assert_eq!(want.len(), 5, "{want:?}");
+        assert!(want[4].is_err(), "{want:?}");
+        for depth in [1, 2, 3, 100] {
+            assert_eq!(collect(depth), want, "depth={depth}");
+        }
+    }
+
+    #[test]
+    fn test_trim_trailing_blank_lines() {
+        let dir = Path::new("testdata").join("chunk");
+        for (trim, chunks) in [(false, vec![(1, 9)]), (true, vec![(1, 7)])] {
+            let matches = test::read_matches(&dir, "trailing_blank_lines");
+            let got: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
+                .unwrap()
+                .trim_trailing_blank_lines(trim)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got, [chunks], "trim={trim}");
+        }
+
+        // Blank lines in the fixed contexts are also trimmed
+        let matches = test::read_matches(&dir, "trailing_blank_lines");
+        let got: Vec<_> = Files::new(matches.into_iter(), 4, 4, None)
+            .unwrap()
+            .trim_trailing_blank_lines(true)
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(1, 7)]]);
+    }
+
+    #[test]
+    fn test_files_per_match_context() {
+        let dir = Path::new("testdata").join("chunk");
+        for (per_match, min, max, chunks) in [
+            (false, 3, 6, vec![(1, 14)]),
+            // Blank lines at line 7 and 10 limit the contexts of the matches at line 3 and 12
+            (true, 3, 6, vec![(1, 6), (8, 14)]),
+            (true, 1, 2, vec![(1, 5), (11, 14)]),
+            // Overlapping contexts are merged into one chunk
+            (true, 6, 6, vec![(1, 14)]),
+        ] {
+            let matches = test::read_matches(&dir, "per_match_context");
+            let got: Vec<_> = Files::new(matches.into_iter(), min, max, None)
+                .unwrap()
+                .per_match_context(per_match)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got, [chunks], "per_match={per_match}, min={min}, max={max}");
+        }
+    }
+
+    #[test]
+    fn test_files_match_bounded_context() {
+        let dir = Path::new("testdata").join("chunk");
+        // Matches at line 3 and 12 are clustered. Matches at line 28 and 37 are isolated by blank lines at line 14, 20,
+        // 36, and 39
+        for (bounded, min, max, chunks) in [
+            (false, 3, 6, vec![(1, 18), (22, 40)]),
+            (true, 3, 6, vec![(1, 13), (22, 34), (37, 38)]),
+            (false, 1, 2, vec![(1, 5), (10, 13), (26, 30), (35, 38)]),
+            // Lines between the clustered matches are not limited by the max context
+            (true, 1, 2, vec![(1, 13), (26, 30), (37, 38)]),
+            (true, 0, 0, vec![(3, 12), (28, 28), (37, 37)]),
+            // Contexts end at blank lines even if the min context is larger
+            (true, 6, 6, vec![(1, 13), (22, 34), (37, 38)]),
+        ] {
+            let matches = test::read_matches(&dir, "match_bounded_context");
+            let got: Vec<_> = Files::new(matches.into_iter(), min, max, None)
+                .unwrap()
+                .match_bounded_context(bounded)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got, [chunks], "bounded={bounded}, min={min}, max={max}");
+        }
+
+        // Other matches don't affect the chunk with --first-match-only
+        let matches = test::read_matches(&dir, "match_bounded_context");
+        let got: Vec<_> = Files::new(matches.into_iter(), 0, 0, None)
+            .unwrap()
+            .match_bounded_context(true)
+            .first_match_only(true)
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(3, 3)]]);
+    }
+
+    #[test]
+    fn test_files_balance_edge_context() {
+        let dir = Path::new("testdata").join("chunk");
+        let item = |lnum| {
+            Ok(GrepMatch {
+                path: dir.join("so_many_neighbors.in"), // 15 lines without blank line
+                line_number: lnum,
+                ranges: vec![],
+                continued: false,
+            })
+        };
+        for (lnums, balance, chunks) in [
+            // Match at the first line
+            (vec![1], false, vec![(1, 3)]),
+            (vec![1], true, vec![(1, 5)]),
+            (vec![2], true, vec![(1, 5)]),
+            // Match at the last line
+            (vec![15], false, vec![(13, 15)]),
+            (vec![15], true, vec![(11, 15)]),
+            (vec![14], true, vec![(11, 15)]),
+            // Matches apart from edges are not affected
+            (vec![8], true, vec![(6, 10)]),
+            (vec![1, 15], true, vec![(1, 5), (11, 15)]),
+            (vec![1, 8], true, vec![(1, 5), (6, 10)]),
+            // Chunks are merged when the borrowed contexts overlap. The merged chunk borrows the contexts as a whole
+            (vec![1, 6], false, vec![(1, 3), (4, 8)]),
+            (vec![1, 6], true, vec![(1, 10)]),
+            (vec![10, 15], false, vec![(8, 12), (13, 15)]),
+            (vec![10, 15], true, vec![(6, 15)]),
+        ] {
+            let got: Vec<_> = Files::new(lnums.iter().map(|&l| item(l)), 2, 2, None)
+                .unwrap()
+                .balance_edge_context(balance)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got, [chunks], "lnums={lnums:?}, balance={balance}");
+        }
+
+        // Contexts are balanced in the same way with --first-match-only and --per-match-context
+        let got: Vec<_> = Files::new([item(15)].into_iter(), 2, 2, None)
+            .unwrap()
+            .balance_edge_context(true)
+            .first_match_only(true)
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(11, 15)]]);
+        let got: Vec<_> = Files::new([item(1), item(15)].into_iter(), 2, 2, None)
+            .unwrap()
+            .balance_edge_context(true)
+            .per_match_context(true)
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(1, 5), (11, 15)]]);
+    }
+
+    #[test]
+    fn test_files_strip_cwd_prefix() {
+        let dir = Path::new(".").join("testdata").join("chunk");
+        for (strip, want) in [
+            (false, dir.join("single_max.in")),
+            (
+                true,
+                Path::new("testdata").join("chunk").join("single_max.in"),
+            ),
+        ] {
+            let matches = test::read_matches(&dir, "single_max");
+            let got: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
+                .unwrap()
+                .strip_cwd_prefix(strip)
+                .map(|f| f.unwrap().path)
+                .collect();
+            assert_eq!(got, [want], "strip={strip}");
+        }
+    }
+
+    #[test]
+    fn test_files_warn_slow() {
+        let dir = Path::new("testdata").join("chunk");
+        // Timing is not predictable. Check that warning files does not affect the results
+        for threshold in [None, Some(Duration::ZERO), Some(Duration::from_secs(60))] {
+            let matches = test::read_matches(&dir, "single_max");
+            let got: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
+                .unwrap()
+                .warn_slow(threshold)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got.len(), 1, "threshold={threshold:?}");
+        }
+    }
+
+    #[test]
+    fn test_context_ratio() {
+        let dir = Path::new("testdata").join("chunk");
+        let tests = [
+            // (min, max, ratio, chunks)
+            (3, 6, 1.0, vec![(4, 12)]),
+            (3, 6, 0.5, vec![(4, 11)]),  // After contexts are 2..3
+            (3, 6, 1.5, vec![(4, 15)]), // After contexts are 5..9. Blank line at line 13 is within the min context
+            (3, 6, 0.0, vec![(4, 8)]),  // No context after the match
+            (2, 2, 1.5, vec![(6, 11)]), // Fixed contexts (2 before and 3 after) ignore blank lines
+            (2, 2, 1.25, vec![(6, 11)]), // 2.5 is rounded up to 3
+            (1, 1, 1.4, vec![(7, 9)]),  // 1.4 is rounded down to 1
+        ];
+        for (min, max, ratio, chunks) in tests {
+            let matches = test::read_matches(&dir, "blank_min_max");
+            let got: Vec<_> = Files::new(matches.into_iter(), min, max, None)
+                .unwrap()
+                .context_ratio(ratio)
+                .map(|f| f.unwrap().chunks.to_vec())
+                .collect();
+            assert_eq!(got, [chunks], "min={min}, max={max}, ratio={ratio}");
+        }
+
+        // Chunks are split based on the sum of before and after contexts
+        let item = |lnum| {
+            Ok(GrepMatch {
+                path: dir.join("so_many_neighbors.in"),
+                line_number: lnum,
+                ranges: vec![],
+                continued: false,
+            })
+        };
+        let got: Vec<_> = Files::new([item(3), item(8)].into_iter(), 2, 2, None)
+            .unwrap()
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(1, 5), (6, 10)]]);
+        let got: Vec<_> = Files::new([item(3), item(8)].into_iter(), 2, 2, None)
+            .unwrap()
+            .context_ratio(2.0)
+            .map(|f| f.unwrap().chunks.to_vec())
+            .collect();
+        assert_eq!(got, [vec![(1, 12)]]);
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary(b"hello\nworld\n"));
+        assert!(looks_binary(b"hello\0world\n"));
+        assert!(looks_binary(b"\0"));
+        assert!(!looks_binary(b"\xff\xfeh\0e\0l\0l\0o\0")); // UTF-16LE with BOM
+        let mut bytes = vec![b'a'; 1024];
+        bytes.push(0);
+        assert!(!looks_binary(&bytes)); // NUL byte after the first 1KB
+    }
+
+    #[test]
+    fn test_files_skip_binary() {
+        let dir = Path::new("testdata").join("chunk");
+        let items = || {
+            [
+                ("binary.bin", 1),
+                ("binary.bin", 3),
+                ("binary.bin", 5),
+                ("single_max.in", 1),
+                ("binary.bin", 2),
+                ("encoding/utf16le_bom.txt", 4),
+            ]
+            .into_iter()
+            .map(|(file, lnum)| {
+                Ok(GrepMatch {
+                    path: dir.join(file),
+                    line_number: lnum,
+                    ranges: vec![],
+                    continued: false,
+                })
+            })
+        };
+
+        let paths = Files::new(items(), 0, 0, None)
+            .unwrap()
+            .skip_binary(true)
+            .map(|f| f.unwrap().path)
+            .collect::<Vec<_>>();
+        let want = [
+            dir.join("single_max.in"),
+            dir.join("encoding/utf16le_bom.txt"),
+        ];
+        assert_eq!(paths, want);
+
+        let paths = Files::new(items(), 0, 0, None)
+            .unwrap()
+            .map(|f| f.unwrap().path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths.len(), 4, "{paths:?}");
+        assert_eq!(paths[0], dir.join("binary.bin"));
+    }
+
+    #[test]
+    fn test_files_first_match_only() {
+        let dir = Path::new("testdata").join("chunk");
+        let item = |file, lnum, continued| {
+            Ok(GrepMatch {
+                path: dir.join(file),
+                line_number: lnum,
+                ranges: vec![],
+                continued,
+            })
+        };
+        let items = || {
+            [
+                item("so_many_neighbors.in", 3, false),
+                item("so_many_neighbors.in", 5, false),
+                item("so_many_neighbors.in", 8, false),
+                item("so_many_neighbors.in", 14, false),
+                item("two_chunks.in", 10, true),
+                item("two_chunks.in", 11, false),
+                item("two_chunks.in", 12, false),
+            ]
+            .into_iter()
+        };
+
+        // The first chunk contains multiple matches
+        let got: Vec<_> = Files::new(items(), 2, 2, None)
+            .unwrap()
+            .map(|f| {
+                let f = f.unwrap();
+                let lnums: Vec<_> = f.line_matches.iter().map(|m| m.line_number).collect();
+                (lnums, f.chunks.to_vec())
+            })
+            .collect();
+        let want = [
+            (vec![3, 5, 8, 14], vec![(1, 10), (12, 15)]),
+            (vec![10, 11, 12], vec![(8, 14)]),
+        ];
+        assert_eq!(got, want);
+
+        let got: Vec<_> = Files::new(items(), 2, 2, None)
+            .unwrap()
+            .first_match_only(true)
+            .map(|f| {
+                let f = f.unwrap();
+                let lnums: Vec<_> = f.line_matches.iter().map(|m| m.line_number).collect();
+                (lnums, f.chunks.to_vec())
+            })
+            .collect();
+        let want = [
+            (vec![3], vec![(1, 5)]),
+            (vec![10, 11], vec![(8, 13)]), // Lines of the first multi-line match are kept
+        ];
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_file_read_whole() {
+        let dir = Path::new("testdata").join("chunk");
+        let path = dir.join("blank_min_max.in");
+        let file = File::read_whole(path.clone(), None).unwrap();
+        let want = File::new(
+            path,
+            vec![],
+            vec![(1, 15)],
+            fs::read_to_string(dir.join("blank_min_max.in")).unwrap(),
+        );
+        assert_eq!(file, want);
+
+        let path = dir.join("encoding").join("sjis.txt");
+        let file = File::read_whole(path, Some("sjis")).unwrap();
+        let want = fs::read_to_string(dir.join("encoding").join("utf8.txt")).unwrap();
+        assert_eq!(file.contents.as_ref(), want);
+
+        let err = File::read_whole(PathBuf::from("this-file-does-not-exist"), None).unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Could not open the file"), "message={msg:?}");
+    }
+
     #[test]
     fn test_file_get_first_line() {
         let tests = [
@@ -669,23 +1917,55 @@ mod tests {
         ];
 
         for (encoding, contents) in tests {
-            let text = decode_text(contents.to_vec(), Some(encoding));
+            let (text, decoded) = decode_text(contents.to_vec(), Some(encoding));
             assert_eq!(text, "こんにちは\r\n", "encoding={encoding:?}");
+            assert_eq!(decoded.encoding, encoding);
         }
     }
 
     #[test]
     fn test_decode_content_with_encoding_detected_from_bom() {
-        let tests = [HELLO_UTF_16BE_BOM, HELLO_UTF_16LE_BOM, HELLO_UTF_8_BOM];
-        for contents in tests {
-            let text = decode_text(contents.to_vec(), None);
+        let tests = [
+            (UTF_16BE, HELLO_UTF_16BE_BOM),
+            (UTF_16LE, HELLO_UTF_16LE_BOM),
+            (UTF_8, HELLO_UTF_8_BOM),
+        ];
+        for (encoding, contents) in tests {
+            let (text, decoded) = decode_text(contents.to_vec(), None);
             assert_eq!(text, "こんにちは\r\n", "input={contents:?}");
+            assert_eq!(
+                decoded,
+                DecodedAs {
+                    encoding,
+                    bom: true
+                },
+                "input={contents:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decoded_encoding_and_bom() {
+        let tests = [
+            (None, HELLO_UTF_8, UTF_8, false),
+            (None, HELLO_UTF_8_BOM, UTF_8, true),
+            (None, HELLO_UTF_16LE_BOM, UTF_16LE, true),
+            (None, HELLO_UTF_16LE, UTF_8, false), // UTF-16 cannot be detected without BOM
+            (Some(UTF_16LE), HELLO_UTF_16LE, UTF_16LE, false),
+            (Some(UTF_16LE), HELLO_UTF_16LE_BOM, UTF_16LE, true),
+            (Some(SHIFT_JIS), HELLO_SJIS, SHIFT_JIS, false),
+            (Some(SHIFT_JIS), HELLO_UTF_8_BOM, SHIFT_JIS, false), // BOM does not match the encoding
+        ];
+        for (specified, contents, encoding, bom) in tests {
+            let (_, decoded) = decode_text(contents.to_vec(), specified);
+            let want = DecodedAs { encoding, bom };
+            assert_eq!(decoded, want, "specified={specified:?}, input={contents:?}");
         }
     }
 
     #[test]
     fn test_decode_with_replacement_char_for_malformed_utf8_file() {
-        let text = decode_text(vec![0xff], Some(UTF_8));
+        let (text, _) = decode_text(vec![0xff], Some(UTF_8));
         assert_eq!(text, "\u{fffd}");
     }
 }
@@ -1,6 +1,7 @@
 use crate::chunk::Files;
 use anyhow::{Error, Result};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fmt;
 use std::io::BufRead;
@@ -57,13 +58,40 @@ pub struct GrepMatch {
     pub line_number: u64,
     // Byte offsets of start/end positions within the line
     pub ranges: Vec<(usize, usize)>,
+    // True when some match in this line continues to the next line. This is set only for multi-line matches
+    pub continued: bool,
 }
 
 pub struct GrepLines<R: BufRead> {
     reader: R,
+    skip_unparsable: bool,
+    null: bool,
+    columns: bool,
+    peeked: Option<Result<GrepMatch>>,
 }
 
 impl<R: BufRead> GrepLines<R> {
+    // Skip lines which cannot be parsed with warnings instead of returning errors. This is useful when the input
+    // contains other lines such as summary lines
+    pub fn skip_unparsable(mut self, yes: bool) -> Self {
+        self.skip_unparsable = yes;
+        self
+    }
+
+    // Read paths terminated with NUL byte like the output of `grep -Z` or `rg --null`. Paths can contain any characters
+    // including newlines and colons
+    pub fn null(mut self, yes: bool) -> Self {
+        self.null = yes;
+        self
+    }
+
+    // Read 1-based column numbers after line numbers like the output of `rg --vimgrep` or `grep --column`. The
+    // character at each column is set as a matched region. Consecutive lines reporting the same line are merged
+    pub fn columns(mut self, yes: bool) -> Self {
+        self.columns = yes;
+        self
+    }
+
     pub fn chunks_per_file(
         self,
         min: u64,
@@ -74,36 +102,148 @@ impl<R: BufRead> GrepLines<R> {
     }
 }
 
-fn parse_line(line: Vec<u8>) -> Result<GrepMatch> {
-    // {path}:{lnum}:{line}...
-    let mut split = line.splitn(3, |&b| b == b':');
-    let (path, lnum) = match (split.next(), split.next(), split.next()) {
+// Convert the 1-based byte column into the range of the character at the column within the text
+fn parse_column(col: &[u8], text: &[u8]) -> Option<Vec<(usize, usize)>> {
+    let col: usize = str::from_utf8(col).ok()?.parse().ok()?;
+    let start = col.checked_sub(1)?;
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    let Some(rest) = text.get(start..).filter(|r| !r.is_empty()) else {
+        return Some(vec![]); // The column points to the end of line
+    };
+    let len = match str::from_utf8(&rest[..rest.len().min(4)]) {
+        Ok(s) => s.chars().next().map(char::len_utf8).unwrap_or(1),
+        Err(e) if e.valid_up_to() > 0 => {
+            let s = str::from_utf8(&rest[..e.valid_up_to()]).unwrap();
+            s.chars().next().map(char::len_utf8).unwrap_or(1)
+        }
+        Err(_) => 1,
+    };
+    Some(vec![(start, start + len)])
+}
+
+fn parse_line(line: Vec<u8>, columns: bool) -> Result<GrepMatch> {
+    // {path}:{lnum}:{line}... or {path}:{lnum}:{col}:{line}... with columns
+    let mut split = line.splitn(if columns { 4 } else { 3 }, |&b| b == b':');
+    let (path, lnum, first) = match (split.next(), split.next(), split.next()) {
         (Some(p), Some(l), Some(_)) if p.is_empty() || l.is_empty() => {
             return ParseError::err(line, "Path or line number is empty")
         }
-        (Some(p), Some(l), Some(_)) => (p, l),
+        (Some(p), Some(l), Some(f)) => (p, l, f),
         _ => return ParseError::err(line, "Path or line number is missing"),
     };
+    let ranges = match (columns, split.next()) {
+        (false, _) => vec![], // Regions are not supported
+        (true, Some(text)) => match parse_column(first, text) {
+            Some(ranges) => ranges,
+            None => return ParseError::err(line, "Could not parse column as positive integer"),
+        },
+        (true, None) => return ParseError::err(line, "Column is missing"),
+    };
     match str::from_utf8(lnum).ok().and_then(|s| s.parse().ok()) {
         Some(lnum) => Ok(GrepMatch {
             path: PathBuf::from(bytes_to_os_string(path)),
             line_number: lnum,
-            ranges: vec![], // Regions are not supported
+            ranges,
+            continued: false,
         }),
         None => ParseError::err(line, "Could not parse line number as unsigned integer"),
     }
 }
 
+fn parse_null_line(path: Vec<u8>, rest: Vec<u8>, columns: bool) -> Result<GrepMatch> {
+    // {path}\0{lnum}:{line}... or {path}\0{lnum}:{col}:{line}... with columns
+    let mut split = rest.splitn(if columns { 3 } else { 2 }, |&b| b == b':');
+    let (Some(p), Some(l), Some(first)) = (path.strip_suffix(b"\0"), split.next(), split.next())
+    else {
+        return ParseError::err([path, rest].concat(), "Path or line number is missing");
+    };
+    if p.is_empty() || l.is_empty() {
+        return ParseError::err([path, rest].concat(), "Path or line number is empty");
+    }
+    let ranges = if !columns {
+        vec![] // Regions are not supported
+    } else if let Some(text) = split.next() {
+        match parse_column(first, text) {
+            Some(ranges) => ranges,
+            None => {
+                return ParseError::err(
+                    [path, rest].concat(),
+                    "Could not parse column as positive integer",
+                )
+            }
+        }
+    } else {
+        return ParseError::err([path, rest].concat(), "Column is missing");
+    };
+    match str::from_utf8(l).ok().and_then(|s| s.parse().ok()) {
+        Some(lnum) => Ok(GrepMatch {
+            path: PathBuf::from(bytes_to_os_string(p)),
+            line_number: lnum,
+            ranges,
+            continued: false,
+        }),
+        None => ParseError::err(
+            [path, rest].concat(),
+            "Could not parse line number as unsigned integer",
+        ),
+    }
+}
+
+impl<R: BufRead> GrepLines<R> {
+    fn next_line(&mut self) -> Option<Result<GrepMatch>> {
+        loop {
+            let mut buf = Vec::new();
+            let delim = if self.null { b'\0' } else { b'\n' };
+            self.reader.read_until(delim, &mut buf).unwrap();
+            if buf.is_empty() {
+                return None;
+            }
+            let parsed = if self.null {
+                let mut rest = Vec::new();
+                self.reader.read_until(b'\n', &mut rest).unwrap();
+                parse_null_line(buf, rest, self.columns)
+            } else {
+                parse_line(buf, self.columns)
+            };
+            match parsed {
+                Err(err) if self.skip_unparsable => {
+                    eprintln!("\x1b[1;93mwarning:\x1b[0m Skipped line: {}", err);
+                }
+                ret => return Some(ret),
+            }
+        }
+    }
+}
+
 impl<R: BufRead> Iterator for GrepLines<R> {
     type Item = Result<GrepMatch>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut buf = Vec::new();
-        self.reader.read_until(b'\n', &mut buf).unwrap();
-        if buf.is_empty() {
-            return None;
+        let mut m = match self.peeked.take() {
+            Some(m) => m,
+            None => self.next_line()?,
+        };
+        if !self.columns {
+            return Some(m);
+        }
+        // `rg --vimgrep` reports the same line once per match. Merge their columns into one match
+        while let Ok(cur) = &mut m {
+            match self.next_line() {
+                Some(Ok(next)) if next.path == cur.path && next.line_number == cur.line_number => {
+                    cur.ranges.extend(next.ranges);
+                }
+                next => {
+                    self.peeked = next;
+                    break;
+                }
+            }
+        }
+        if let Ok(m) = &mut m {
+            m.ranges.sort_unstable();
+            m.ranges.dedup();
         }
-        Some(parse_line(buf))
+        Some(m)
     }
 }
 
@@ -113,10 +253,38 @@ pub trait BufReadExt: BufRead + Sized {
 
 impl<R: BufRead> BufReadExt for R {
     fn grep_lines(self) -> GrepLines<Self> {
-        GrepLines { reader: self }
+        GrepLines {
+            reader: self,
+            skip_unparsable: false,
+            null: false,
+            columns: false,
+            peeked: None,
+        }
     }
 }
 
+// Merge matches read from multiple grep outputs so that `Files` can consume them. Matches are grouped per file in the
+// order of the first appearance of the file, and sorted by line number in each file. When the same line appears in
+// multiple outputs, the first one is used by `Files`
+pub fn merge_grep_matches(
+    matches: impl Iterator<Item = Result<GrepMatch>>,
+) -> Result<Vec<GrepMatch>> {
+    let mut indices = HashMap::new();
+    let mut files: Vec<Vec<GrepMatch>> = vec![];
+    for m in matches {
+        let m = m?;
+        let idx = *indices.entry(m.path.clone()).or_insert_with(|| {
+            files.push(vec![]);
+            files.len() - 1
+        });
+        files[idx].push(m);
+    }
+    for matches in files.iter_mut() {
+        matches.sort_by_key(|m| m.line_number); // Stable sort keeps the order of the same line
+    }
+    Ok(files.into_iter().flatten().collect())
+}
+
 #[test]
 fn test_read_ok() {
     let input = [
@@ -134,22 +302,117 @@ fn test_read_ok() {
             path: PathBuf::from("/path/to/foo.txt"),
             line_number: 1,
             ranges: vec![],
+            continued: false,
         },
         GrepMatch {
             path: PathBuf::from("/path/to/bar.txt"),
             line_number: 100,
             ranges: vec![],
+            continued: false,
         },
         GrepMatch {
             path: PathBuf::from("/path/to/bar.txt"),
             line_number: 110,
             ranges: vec![],
+            continued: false,
         },
     ];
 
     assert_eq!(&output, expected);
 }
 
+#[test]
+fn test_merge_grep_matches() {
+    let inputs = [
+        "a.txt:10:foo\nb.txt:3:foo\na.txt:12:foo\n",
+        "c.txt:1:foo\na.txt:2:foo\nb.txt:3:bar\na.txt:11:foo\n",
+    ];
+    let matches = inputs.iter().flat_map(|i| i.as_bytes().grep_lines());
+    let merged: Vec<_> = merge_grep_matches(matches)
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.path.to_string_lossy().into_owned(), m.line_number))
+        .collect();
+    let expected = [
+        ("a.txt", 2),
+        ("a.txt", 10),
+        ("a.txt", 11),
+        ("a.txt", 12),
+        ("b.txt", 3),
+        ("b.txt", 3),
+        ("c.txt", 1),
+    ]
+    .map(|(p, l)| (p.to_string(), l));
+    assert_eq!(merged, expected);
+
+    let matches = ["a.txt:1:foo\n", "broken\n"]
+        .into_iter()
+        .flat_map(|i| i.as_bytes().grep_lines());
+    merge_grep_matches(matches).unwrap_err();
+}
+
+#[test]
+fn test_skip_unparsable() {
+    let input = [
+        "/path/to/foo.txt:1:    hello",
+        "/path/to/foo.txt:   foo",
+        "/path/to/bar.txt:100:    bye",
+        "3 files matched",
+        "",
+        "/path/to/bar.txt:110:    this : line : include : colon",
+    ]
+    .join("\n")
+    .into_bytes();
+
+    let output: Vec<_> = input
+        .grep_lines()
+        .skip_unparsable(true)
+        .map(|m| m.map(|m| (m.path.to_string_lossy().into_owned(), m.line_number)))
+        .collect::<Result<_>>()
+        .unwrap();
+
+    let expected = [
+        ("/path/to/foo.txt", 1),
+        ("/path/to/bar.txt", 100),
+        ("/path/to/bar.txt", 110),
+    ]
+    .map(|(p, l)| (p.to_string(), l));
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_read_null() {
+    let input = b"/path/to/foo.txt\x001:    hello\n/path/with\nnewline:and colon.txt\x0010:foo : bar\n/path/to/bar.txt\x00100:bye";
+
+    let output: Vec<_> = input
+        .grep_lines()
+        .null(true)
+        .map(|m| m.map(|m| (m.path.to_string_lossy().into_owned(), m.line_number)))
+        .collect::<Result<_>>()
+        .unwrap();
+
+    let expected = [
+        ("/path/to/foo.txt", 1),
+        ("/path/with\nnewline:and colon.txt", 10),
+        ("/path/to/bar.txt", 100),
+    ]
+    .map(|(p, l)| (p.to_string(), l));
+
+    assert_eq!(output, expected);
+
+    for input in [
+        &b"/path/to/foo.txt:1:    hello\n"[..],
+        b"\x001:foo\n",
+        b"foo.txt\x00:foo\n",
+        b"foo.txt\x00foo\n",
+        b"foo.txt\x00x:foo\n",
+    ] {
+        let mut lines = input.grep_lines().null(true);
+        lines.next().unwrap().unwrap_err();
+    }
+}
+
 #[test]
 fn test_read_error() {
     let input = [
@@ -188,3 +451,69 @@ fn test_read_error() {
         );
     }
 }
+
+#[test]
+fn test_read_columns() {
+    let input = [
+        "/path/to/foo.txt:1:5:    hello",
+        "/path/to/foo.txt:3:1:foo : foo",
+        "/path/to/foo.txt:3:7:foo : foo",
+        "/path/to/foo.txt:4:4:あいう",
+        "/path/to/foo.txt:5:4:bye\r",
+        "/path/to/bar.txt:3:2:foo",
+    ]
+    .join("\n")
+    .into_bytes();
+
+    let output: Vec<_> = input
+        .grep_lines()
+        .columns(true)
+        .map(|m| {
+            m.map(|m| {
+                (
+                    m.path.to_string_lossy().into_owned(),
+                    m.line_number,
+                    m.ranges,
+                )
+            })
+        })
+        .collect::<Result<_>>()
+        .unwrap();
+
+    let expected = [
+        ("/path/to/foo.txt", 1, vec![(4, 5)]),
+        ("/path/to/foo.txt", 3, vec![(0, 1), (6, 7)]),
+        ("/path/to/foo.txt", 4, vec![(3, 6)]),
+        ("/path/to/foo.txt", 5, vec![]),
+        ("/path/to/bar.txt", 3, vec![(1, 2)]),
+    ]
+    .map(|(p, l, r)| (p.to_string(), l, r));
+
+    assert_eq!(output, expected);
+
+    let input = b"/path/to/foo.txt\x001:2:foo\n/path/to/foo.txt\x001:3:foo\n";
+    let output: Vec<_> = input
+        .grep_lines()
+        .null(true)
+        .columns(true)
+        .map(|m| m.map(|m| (m.line_number, m.ranges)))
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(output, [(1, vec![(1, 2), (2, 3)])]);
+
+    for (input, msg) in [
+        (
+            "foo.txt:1:0:foo",
+            "Could not parse column as positive integer",
+        ),
+        (
+            "foo.txt:1:x:foo",
+            "Could not parse column as positive integer",
+        ),
+        ("foo.txt:1:foo", "Column is missing"),
+    ] {
+        let err = input.as_bytes().grep_lines().columns(true).next().unwrap();
+        let err = format!("{}", err.unwrap_err());
+        assert!(err.contains(msg), "{msg:?} is included in {err:?}");
+    }
+}
@@ -0,0 +1,380 @@
+use crate::broken_pipe::IgnoreBrokenPipe;
+use crate::chunk::{File, LinesInclusive};
+use crate::printer::Printer;
+use anyhow::Result;
+use std::cmp;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub(crate) fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_json_ranges<T: std::fmt::Display>(out: &mut String, ranges: impl Iterator<Item = (T, T)>) {
+    out.push('[');
+    for (i, (start, end)) in ranges.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "[{},{}]", start, end).unwrap();
+    }
+    out.push(']');
+}
+
+// Printer to output one JSON object per file in JSON Lines format (https://jsonlines.org/). Each line is written and
+// flushed as soon as the file is printed so that the output can be consumed in streaming.
+pub struct JsonLinesPrinter<W: Write> {
+    out: Mutex<W>,
+    text: bool,
+}
+
+impl<W: Write> JsonLinesPrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+            text: false,
+        }
+    }
+
+    // Include text of each chunk in the output
+    pub fn text(mut self, yes: bool) -> Self {
+        self.text = yes;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+
+    fn encode(&self, file: &File) -> String {
+        let mut out = String::new();
+        out.push_str("{\"path\":");
+        write_json_str(&mut out, &file.path.to_string_lossy());
+
+        out.push_str(",\"chunks\":");
+        write_json_ranges(&mut out, file.chunks.iter().copied());
+
+        out.push_str(",\"line_matches\":[");
+        for (i, lmat) in file.line_matches.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"line_number\":{},\"ranges\":", lmat.line_number).unwrap();
+            write_json_ranges(&mut out, lmat.ranges.iter().copied());
+            out.push('}');
+        }
+        out.push(']');
+
+        if self.text {
+            out.push_str(",\"texts\":[");
+            let mut lines = LinesInclusive::new(&file.contents).peekable();
+            for (i, &(start, end)) in file.chunks.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let mut text = String::new();
+                while let Some(&(line, lnum)) = lines.peek() {
+                    if lnum > end {
+                        break;
+                    }
+                    if start <= lnum {
+                        text.push_str(line);
+                    }
+                    lines.next();
+                }
+                write_json_str(&mut out, &text);
+            }
+            out.push(']');
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl<W: Write> Printer for JsonLinesPrinter<W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return Ok(());
+        }
+
+        let line = self.encode(&file);
+
+        // Take lock here to print files in serial from multiple threads
+        let mut out = self.out.lock().unwrap();
+        out.write_all(line.as_bytes()).ignore_broken_pipe()?;
+        Ok(out.flush().ignore_broken_pipe()?)
+    }
+}
+
+// Summary of printed files written as one JSON object at the end of the run for --summary-file. Files are recorded in
+// the order of printing from multiple threads. The schema is:
+//
+//   {"files":[{"path":"foo.rs","chunks":[[1,5],[8,12]],"matches":3}],"total_files":1,"total_matches":3}
+#[derive(Default)]
+pub struct JsonSummary(Mutex<Vec<(String, usize)>>); // Encoded file object and its number of matched lines
+
+impl JsonSummary {
+    pub fn record(&self, file: &File) {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return;
+        }
+        let mut out = String::new();
+        out.push_str("{\"path\":");
+        write_json_str(&mut out, &file.path.to_string_lossy());
+        out.push_str(",\"chunks\":");
+        write_json_ranges(&mut out, file.chunks.iter().copied());
+        write!(out, ",\"matches\":{}}}", file.line_matches.len()).unwrap();
+        self.0.lock().unwrap().push((out, file.line_matches.len()));
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let files = self.0.lock().unwrap();
+        out.write_all(b"{\"files\":[")?;
+        for (i, (file, _)) in files.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            out.write_all(file.as_bytes())?;
+        }
+        let total: usize = files.iter().map(|(_, n)| n).sum();
+        writeln!(
+            out,
+            "],\"total_files\":{},\"total_matches\":{}}}",
+            files.len(),
+            total,
+        )?;
+        out.flush()
+    }
+}
+
+// Statistics of the run written as one JSON object at the end for --stats-json. Counters are atomic so that they can be
+// updated by multiple threads printing files in parallel. The schema is:
+//
+//   {"matched_files":2,"matched_lines":5,"matches":7,"bytes":1234,"elapsed_secs":0.012}
+//
+// "matches" counts each matched region in a line like --count-matches, and "bytes" is the total size of matched files
+#[derive(Default)]
+pub struct JsonStats {
+    files: AtomicU64,
+    lines: AtomicU64,
+    matches: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl JsonStats {
+    pub fn record(&self, file: &File) {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return;
+        }
+        let matches: usize = file
+            .line_matches
+            .iter()
+            .map(|m| cmp::max(m.ranges.len(), 1))
+            .sum();
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.lines
+            .fetch_add(file.line_matches.len() as u64, Ordering::Relaxed);
+        self.matches.fetch_add(matches as u64, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(file.contents.len() as u64, Ordering::Relaxed);
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W, elapsed: Duration) -> io::Result<()> {
+        writeln!(
+            out,
+            "{{\"matched_files\":{},\"matched_lines\":{},\"matches\":{},\"bytes\":{},\"elapsed_secs\":{:.6}}}",
+            self.files.load(Ordering::Relaxed),
+            self.lines.load(Ordering::Relaxed),
+            self.matches.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+            elapsed.as_secs_f64(),
+        )?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::LineMatch;
+    use std::path::PathBuf;
+
+    fn sample_files() -> Vec<File> {
+        vec![
+            File::new(
+                PathBuf::from("foo.rs"),
+                vec![
+                    LineMatch::new(2, vec![(4, 7)]),
+                    LineMatch::new(5, vec![(0, 3), (8, 11)]),
+                ],
+                vec![(1, 3), (4, 6)],
+                "line 1\nfoo \"bar\"\n\tline 3\nline 4\nfoo bar foo\nline 6\nline 7\n".to_string(),
+            ),
+            File::new(
+                PathBuf::from("dir\\with\\backslash.txt"),
+                vec![LineMatch::new(1, vec![(0, 1)])],
+                vec![(1, 1)],
+                "\u{1b}[31m\r\n".to_string(),
+            ),
+        ]
+    }
+
+    fn print_all(text: bool) -> String {
+        let printer = JsonLinesPrinter::new(vec![]).text(text);
+        for file in sample_files() {
+            printer.print(file).unwrap();
+        }
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_output_is_valid_json_lines() {
+        let output = print_all(false);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2, "{:?}", output);
+
+        let files = sample_files();
+        for (line, file) in lines.iter().zip(files.iter()) {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(json["path"], file.path.to_str().unwrap(), "{}", line);
+            let chunks: Vec<(u64, u64)> = serde_json::from_value(json["chunks"].clone()).unwrap();
+            assert_eq!(chunks, file.chunks.to_vec(), "{}", line);
+            let lmats = json["line_matches"].as_array().unwrap();
+            assert_eq!(lmats.len(), file.line_matches.len(), "{}", line);
+            for (json, lmat) in lmats.iter().zip(file.line_matches.iter()) {
+                assert_eq!(json["line_number"], lmat.line_number, "{}", line);
+                let ranges: Vec<(usize, usize)> =
+                    serde_json::from_value(json["ranges"].clone()).unwrap();
+                assert_eq!(ranges, lmat.ranges, "{}", line);
+            }
+            assert!(json.get("texts").is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_output_chunk_texts() {
+        let output = print_all(true);
+        let texts: Vec<Vec<String>> = output
+            .lines()
+            .map(|line| {
+                let json: serde_json::Value = serde_json::from_str(line).unwrap();
+                serde_json::from_value(json["texts"].clone()).unwrap()
+            })
+            .collect();
+        let want = vec![
+            vec![
+                "line 1\nfoo \"bar\"\n\tline 3\n".to_string(),
+                "line 4\nfoo bar foo\nline 6\n".to_string(),
+            ],
+            vec!["\u{1b}[31m\r\n".to_string()],
+        ];
+        assert_eq!(texts, want);
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = JsonSummary::default();
+        std::thread::scope(|s| {
+            for file in sample_files() {
+                let summary = &summary;
+                s.spawn(move || summary.record(&file));
+            }
+        });
+        summary.record(&File::new(
+            PathBuf::from("no_match.rs"),
+            vec![],
+            vec![],
+            String::new(),
+        ));
+
+        let mut out = vec![];
+        summary.write_to(&mut out).unwrap();
+        let mut json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        // Order of files depends on the order of threads
+        json["files"]
+            .as_array_mut()
+            .unwrap()
+            .sort_by_key(|f| f["path"].as_str().unwrap().to_string());
+        let want = serde_json::json!({
+            "files": [
+                {"path": "dir\\with\\backslash.txt", "chunks": [[1, 1]], "matches": 1},
+                {"path": "foo.rs", "chunks": [[1, 3], [4, 6]], "matches": 2},
+            ],
+            "total_files": 2,
+            "total_matches": 3,
+        });
+        assert_eq!(json, want);
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = JsonStats::default();
+        std::thread::scope(|s| {
+            for file in sample_files() {
+                let stats = &stats;
+                s.spawn(move || stats.record(&file));
+            }
+        });
+        stats.record(&File::new(
+            PathBuf::from("no_match.rs"),
+            vec![],
+            vec![],
+            "foo\n".to_string(),
+        ));
+
+        let mut out = vec![];
+        stats
+            .write_to(&mut out, Duration::from_millis(1500))
+            .unwrap();
+        assert!(out.ends_with(b"}\n"), "{:?}", String::from_utf8_lossy(&out));
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let want = serde_json::json!({
+            "matched_files": 2,
+            "matched_lines": 3,
+            "matches": 4,
+            "bytes": 65,
+            "elapsed_secs": 1.5,
+        });
+        assert_eq!(json, want);
+    }
+
+    #[test]
+    fn test_stats_without_matches() {
+        let stats = JsonStats::default();
+        let mut out = vec![];
+        stats.write_to(&mut out, Duration::ZERO).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let want = serde_json::json!({
+            "matched_files": 0,
+            "matched_lines": 0,
+            "matches": 0,
+            "bytes": 0,
+            "elapsed_secs": 0.0,
+        });
+        assert_eq!(json, want);
+    }
+
+    #[test]
+    fn test_skip_file_without_matches() {
+        let printer = JsonLinesPrinter::new(vec![]);
+        let file = File::new(PathBuf::from("foo.rs"), vec![], vec![], "foo\n".to_string());
+        printer.print(file).unwrap();
+        assert!(printer.into_inner().is_empty());
+    }
+}
@@ -5,6 +5,7 @@ compile_error!("Either feature \"bat-printer\" or \"syntect-printer\" must be en
 
 pub mod chunk;
 pub mod grep;
+pub mod json;
 pub mod printer;
 
 mod broken_pipe;
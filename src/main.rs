@@ -2,13 +2,21 @@
 
 use anyhow::{Context, Result};
 use clap::{Arg, ArgAction, ArgMatches, Command};
-use hgrep::grep::BufReadExt;
-use hgrep::printer::{PrinterOptions, TextWrapMode};
+use hgrep::chunk::{parse_size, sort_files, File, Files, SortKey};
+use hgrep::grep::{merge_grep_matches, BufReadExt, GrepMatch};
+use hgrep::json::{JsonLinesPrinter, JsonStats, JsonSummary};
+use hgrep::printer::{
+    CountMatchesPrinter, CountingPrinter, EncodeWriter, Printer, PrinterOptions, RawPrinter,
+    SummaryPrinter, TabMode, TermBackground, TermColorSupport, TextWrapMode,
+};
 use std::cmp;
 use std::env;
 use std::ffi::OsString;
 use std::io;
 use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -19,8 +27,12 @@ use hgrep::ripgrep;
 #[cfg(feature = "bat-printer")]
 use hgrep::bat::BatPrinter;
 
+#[cfg(feature = "syntect-printer")]
+use hgrep::printer::{RegionStyle, SeparatorStyle};
 #[cfg(feature = "syntect-printer")]
 use hgrep::syntect::SyntectPrinter;
+#[cfg(feature = "syntect-printer")]
+use syntect::highlighting::ThemeSet;
 
 const COMPLETION_SHELLS: [&str; 6] = ["bash", "zsh", "powershell", "fish", "elvish", "nushell"];
 const OPTS_ENV_VAR: &str = "HGREP_DEFAULT_OPTS";
@@ -28,29 +40,48 @@ const OPTS_ENV_VAR: &str = "HGREP_DEFAULT_OPTS";
 #[derive(Debug)]
 struct Args {
     env: Vec<String>,
-    args: env::ArgsOs,
+    args: std::vec::IntoIter<OsString>,
 }
 
 impl Args {
     fn new() -> Result<Self> {
-        let env = match env::var(OPTS_ENV_VAR) {
+        let mut args = env::args_os();
+        args.next(); // Skip the executable name at the first item
+        Self::with_args(args.collect())
+    }
+
+    fn with_args(args: Vec<OsString>) -> Result<Self> {
+        // The default options are prepended before parsing the arguments with clap. So --no-default-opts needs to be
+        // checked here. Arguments after `--` are not options.
+        let no_default_opts = args
+            .iter()
+            .take_while(|a| *a != "--")
+            .any(|a| a == "--no-default-opts");
+        let env = if no_default_opts {
+            vec![]
+        } else {
+            Self::default_opts()?
+        };
+        Ok(Self {
+            env,
+            args: args.into_iter(),
+        })
+    }
+
+    fn default_opts() -> Result<Vec<String>> {
+        match env::var(OPTS_ENV_VAR) {
             Ok(var) => {
                 let Some(mut opts) = shlex::split(&var) else {
                     anyhow::bail!("String in `{OPTS_ENV_VAR}` environment variable cannot be parsed as a shell command: {var:?}");
                 };
                 opts.reverse();
-                opts
+                Ok(opts)
             }
-            Err(env::VarError::NotPresent) => vec![],
+            Err(env::VarError::NotPresent) => Ok(vec![]),
             Err(env::VarError::NotUnicode(invalid)) => {
                 anyhow::bail!("String in `{OPTS_ENV_VAR}` environment variable is not a valid UTF-8 sequence: {invalid:?}");
             }
-        };
-
-        let mut args = env::args_os();
-        args.next(); // Skip the executable name at the first item
-
-        Ok(Self { env, args })
+        }
     }
 }
 
@@ -114,6 +145,20 @@ fn command() -> Command {
                 .default_value("6")
                 .help("Maximum lines of leading and trailing context surrounding each match"),
         )
+        .arg(
+            Arg::new("context")
+                .long("context")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Exactly NUM lines of leading and trailing context surrounding each match. Unlike --min-context and --max-context, blank lines don't shorten the context. This option takes precedence over --min-context and --max-context"),
+        )
+        .arg(
+            Arg::new("context-ratio")
+                .long("context-ratio")
+                .num_args(1)
+                .value_name("RATIO")
+                .help("Ratio of trailing context to leading context. For example, 1.5 shows 50% more lines after each match than before it. The number of lines is rounded to the nearest integer"),
+        )
         .arg(
             Arg::new("no-grid")
                 .short('G')
@@ -135,12 +180,30 @@ fn command() -> Command {
                 .default_value("4")
                 .help("Number of spaces for tab character. Set 0 to pass tabs through directly"),
         )
+        .arg(
+            Arg::new("keep-tabs")
+                .long("keep-tabs")
+                .action(ArgAction::SetTrue)
+                .help("Pass tab characters through directly so that copied code contains them. This is the same as --tab 0"),
+        )
+        .arg(
+            Arg::new("expand-tabs")
+                .long("expand-tabs")
+                .action(ArgAction::SetTrue)
+                .help("Replace tab characters with spaces of the width specified by --tab. This is the default behavior and an opposite of --keep-tabs"),
+        )
         .arg(
             Arg::new("theme")
                 .long("theme")
                 .num_args(1)
                 .value_name("THEME")
-                .help("Theme for syntax highlighting. Use --list-themes flag to print the theme list"),
+                .help("Theme for syntax highlighting. 'auto' selects a theme fitting to the color support of the terminal: 'ansi' for 16 colors, 'zenburn' for 256 colors, and 'Monokai Extended' for 24-bit colors. Use --list-themes flag to print the theme list"),
+        )
+        .arg(
+            Arg::new("theme-background-detect")
+                .long("theme-background-detect")
+                .action(ArgAction::SetTrue)
+                .help("Query the background color of the terminal with OSC 11 escape sequence and use a light theme when the background is light. A dark theme is used when the terminal does not respond. This flag is ignored when a theme is specified"),
         )
         .arg(
             Arg::new("list-themes")
@@ -148,6 +211,19 @@ fn command() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("List all available theme names and their samples. Samples show the output where 'let' is searched. The names can be used at --theme option"),
         )
+        .arg(
+            Arg::new("theme-names")
+                .long("theme-names")
+                .action(ArgAction::SetTrue)
+                .help("Print only the sorted names of all available themes one per line. This is faster than --list-themes and useful for scripting"),
+        )
+        .arg(
+            Arg::new("theme-preview")
+                .long("theme-preview")
+                .num_args(1)
+                .value_name("THEME")
+                .help("Print only the sample code of --list-themes highlighted with the THEME. This is useful to quickly try a theme. This option is only for syntect printer"),
+        )
         .arg(
             Arg::new("printer")
                 .short('p')
@@ -183,6 +259,86 @@ fn command() -> Command {
                 .long("first-only")
                 .action(ArgAction::SetTrue)
                 .help("Show only the first code snippet per file")
+        ).arg(
+            Arg::new("last-only")
+                .long("last-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("first-only")
+                .help("Show only the last code snippet per file. This is useful when the most relevant match is near the end of file such as logs")
+        ).arg(
+            Arg::new("first-match-only")
+                .long("first-match-only")
+                .action(ArgAction::SetTrue)
+                .help("Show only the first matched line and its context per file. Unlike --first-only, other matched lines in the first code snippet are not shown")
+        ).arg(
+            Arg::new("trim-trailing-newlines")
+                .long("trim-trailing-newlines")
+                .action(ArgAction::SetTrue)
+                .help("Trim blank lines at the end of each code snippet. Blank lines between matched lines are kept")
+        ).arg(
+            Arg::new("per-match-context")
+                .long("per-match-context")
+                .action(ArgAction::SetTrue)
+                .help("Calculate context lines of each matched line independently. By default, lines between close matches are all shown in one code snippet. With this flag, lines out of the contexts of the matches are omitted and the code snippet is split")
+        ).arg(
+            Arg::new("match-bounded-context")
+                .long("match-bounded-context")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("per-match-context")
+                .help("Determine code snippets by neighboring matches instead of the number of lines. Lines between matched lines are shown in one code snippet unless a blank line separates them. Context lines before and after them end at a blank line or at the max context. The min context is ignored")
+        ).arg(
+            Arg::new("balance-edge-context")
+                .long("balance-edge-context")
+                .action(ArgAction::SetTrue)
+                .help("Keep the number of context lines of a code snippet near the start or end of file. Context lines which are cut by the start (or end) of file are added after (or before) the matched lines")
+        ).arg(
+            Arg::new("strip-cwd-prefix")
+                .long("strip-cwd-prefix")
+                .action(ArgAction::SetTrue)
+                .help("Remove the leading './' from file paths like ripgrep's --strip-cwd-prefix flag")
+        ).arg(
+            Arg::new("max-results")
+                .long("max-results")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Stop after printing NUM files. NUM counts files, not matches. Remaining files are not searched unless --sort is specified")
+        ).arg(
+            Arg::new("min-matches")
+                .long("min-matches")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Show only files which have at least NUM matched lines. Other files are not printed at all")
+        ).arg(
+            Arg::new("max-filesize")
+                .long("max-filesize")
+                .num_args(1)
+                .value_name("NUM+SUFFIX?")
+                .help("Ignore files larger than NUM in size. This does not apply to directories. The input format accepts suffixes of K, M or G which correspond to kilobytes, megabytes and gigabytes, respectively. If no suffix is provided the input is treated as bytes. This option also applies to files read from grep output via stdin")
+        ).arg(
+            Arg::new("warn-slow")
+                .long("warn-slow")
+                .num_args(1)
+                .value_name("MS")
+                .help("Print a warning to stderr for each file which took longer than MS milliseconds to read and search. This is useful to find pathological files which dominate the running time")
+        ).arg(
+            Arg::new("matches-only")
+                .long("matches-only")
+                .action(ArgAction::SetTrue)
+                .help("Show only the matched lines with no context lines and no separators between them. Each line is printed once even if it contains multiple matches. --min-context and --max-context are ignored")
+        ).arg(
+            Arg::new("sort")
+                .long("sort")
+                .num_args(1)
+                .value_name("SORTBY")
+                .value_parser(["path", "modified"])
+                .ignore_case(true)
+                .help("Sort the results by SORTBY in ascending order. 'path' sorts by file path and 'modified' sorts by last modified time. Note that sorting the results requires collecting all matches before printing them")
+        ).arg(
+            Arg::new("sort-reverse")
+                .long("sort-reverse")
+                .action(ArgAction::SetTrue)
+                .requires("sort")
+                .help("Sort the results in descending order. This flag must be used with --sort option")
         ).arg(
             Arg::new("encoding")
                 .short('E')
@@ -190,6 +346,123 @@ fn command() -> Command {
                 .num_args(1)
                 .value_name("ENCODING")
                 .help("Specify the text encoding that hgrep will use on all files printed like 'sjis'")
+        ).arg(
+            Arg::new("no-parallel")
+                .long("no-parallel")
+                .action(ArgAction::SetTrue)
+                .help("Search and print files one by one on a single thread. This is useful for debugging since errors and panics are easier to attribute to a file")
+        ).arg(
+            Arg::new("debug-encoding")
+                .long("debug-encoding")
+                .action(ArgAction::SetTrue)
+                .help("Report the text encoding used for decoding each file and whether BOM was found to stderr. This is useful to diagnose why text is rendered oddly")
+        ).arg(
+            Arg::new("no-default-opts")
+                .long("no-default-opts")
+                .action(ArgAction::SetTrue)
+                .help("Ignore the default options set in HGREP_DEFAULT_OPTS environment variable")
+        ).arg(
+            Arg::new("print-total")
+                .long("print-total")
+                .action(ArgAction::SetTrue)
+                .help("Print the total number of matched lines across all files to stderr after printing the results")
+        ).arg(
+            Arg::new("input")
+                .long("input")
+                .action(ArgAction::Append)
+                .num_args(1)
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("Read grep output from the file at PATH instead of stdin. This option can be specified multiple times. Matches in the same file across the inputs are merged into one result")
+        ).arg(
+            Arg::new("skip-unparsable")
+                .long("skip-unparsable")
+                .action(ArgAction::SetTrue)
+                .help("Skip lines of grep output which cannot be parsed with warnings instead of stopping with an error. This is useful when the output contains other lines such as summaries")
+        ).arg(
+            Arg::new("input-null")
+                .long("input-null")
+                .action(ArgAction::SetTrue)
+                .help("Read grep output whose file paths are terminated with NUL byte like the output of 'grep -Z' or 'rg --null'. Paths can contain any characters including newlines")
+        ).arg(
+            Arg::new("columns")
+                .long("columns")
+                .action(ArgAction::SetTrue)
+                .help("Read grep output with 1-based column numbers after line numbers like the output of 'rg --vimgrep' or 'grep --column'. The character at each column is highlighted as a matched region")
+        ).arg(
+            Arg::new("summary-file")
+                .long("summary-file")
+                .num_args(1)
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("Write the summary of the results (matched files, their code snippet ranges, and the numbers of matched lines) to the file at PATH in JSON format while printing the results as usual")
+        ).arg(
+            Arg::new("stats-json")
+                .long("stats-json")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help(r#"Write the statistics of the run as one JSON object like {"matched_files":2,"matched_lines":5,"matches":7,"bytes":1234,"elapsed_secs":0.012} to stderr after printing the results. When PATH is given like --stats-json=stats.json, the statistics are written to the file instead. The statistics are written even if nothing is matched"#)
+        ).arg(
+            Arg::new("json-lines")
+                .long("json-lines")
+                .action(ArgAction::SetTrue)
+                .help("Print results in JSON Lines format. One JSON object is printed and flushed per file"),
+        ).arg(
+            Arg::new("json-text")
+                .long("json-text")
+                .action(ArgAction::SetTrue)
+                .requires("json-lines")
+                .help("Include text of each chunk in the JSON objects printed with --json-lines"),
+        ).arg(
+            Arg::new("summary-only")
+                .long("summary-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("json-lines")
+                .help("Print only one line per matched file like 'path: C chunks, M matches' instead of printing the chunks"),
+        ).arg(
+            Arg::new("count-matches")
+                .long("count-matches")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["json-lines", "summary-only"])
+                .help("Print only the number of matches per matched file like 'path:N' instead of printing the chunks. Unlike the number of matched lines, all matches in each line are counted. With --max-count, only matches in the first NUM matched lines are counted. Each line of grep output read from stdin is counted as one match unless --highlight is given"),
+        ).arg(
+            Arg::new("raw")
+                .long("raw")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["json-lines", "summary-only", "count-matches"])
+                .help("Print the chunks as plain text without syntax highlighting and colors. Each line is prefixed with its line number followed by ':' for matched lines and '-' for context lines like grep. This is useful for piping the chunks to other tools"),
+        ).arg(
+            Arg::new("null")
+                .short('0')
+                .long("null")
+                .action(ArgAction::SetTrue)
+                .requires("summary-only")
+                .help("Follow each file path with NUL byte instead of ': ' in the output of --summary-only. This is useful for piping the paths to 'xargs -0'. Code snippets are not affected"),
+        ).arg(
+            Arg::new("color-scheme-256")
+                .long("color-scheme-256")
+                .action(ArgAction::SetTrue)
+                .help("Force 256 colors output by converting 24-bit colors regardless of terminal color support"),
+        ).arg(
+            Arg::new("path-separator")
+                .long("path-separator")
+                .num_args(1)
+                .value_name("SEPARATOR")
+                .help("Path separator used for displaying file paths in headers. For example, '/' can be used on Windows. This does not affect opening files"),
+        ).arg(
+            Arg::new("strip-prefix")
+                .long("strip-prefix")
+                .num_args(1)
+                .value_name("PREFIX")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("Remove the leading PREFIX from file paths displayed in headers. Paths which don't start with PREFIX are displayed as-is. This does not affect opening files"),
+        ).arg(
+            Arg::new("skip-binary")
+                .long("skip-binary")
+                .action(ArgAction::SetTrue)
+                .help("Skip files which look binary (containing NUL byte in the first 1KB) while reading grep output from stdin. Files searched by the builtin ripgrep are always skipped when they are binary"),
         )
         .arg(
             Arg::new("generate-completion-script")
@@ -205,16 +478,57 @@ fn command() -> Command {
                 .long("generate-man-page")
                 .action(ArgAction::SetTrue)
                 .help("Print man page to stdout"),
+        )
+        .arg(
+            Arg::new("pcre2-version")
+                .long("pcre2-version")
+                .action(ArgAction::SetTrue)
+                .help("Print the version of PCRE2 library used by --pcre2 and whether JIT compilation is available"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .num_args(1)
+                .value_name("PATH")
+                .value_hint(clap::ValueHint::FilePath)
+                .value_parser(clap::builder::ValueParser::path_buf())
+                .help("Write results to the file at PATH instead of stdout. The file is truncated when it already exists. Colors are written as they are printed to terminal"),
+        )
+        .arg(
+            Arg::new("output-encoding")
+                .long("output-encoding")
+                .num_args(1)
+                .value_name("ENCODING")
+                .help("Encode the text of results such as code and file paths in ENCODING like 'sjis' instead of UTF-8. Escape sequences for colors are written as-is. Characters which cannot be represented in the encoding are replaced with '?'"),
         );
 
-    #[cfg(feature = "bat-printer")]
+    #[cfg(unix)]
     let cmd = cmd.arg(
-        Arg::new("custom-assets")
-            .long("custom-assets")
-            .action(ArgAction::SetTrue)
-            .help("Load bat's custom assets. Note that this flag may not work with some version of `bat` command. This flag is only for bat printer"),
+        Arg::new("socket")
+            .long("socket")
+            .num_args(1)
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(std::path::PathBuf))
+            .conflicts_with("output")
+            .help("Write results to the Unix domain socket at PATH instead of stdout. This is useful for integrating hgrep with a long-running process such as editor"),
     );
 
+    #[cfg(feature = "bat-printer")]
+    let cmd = cmd
+        .arg(
+            Arg::new("custom-assets")
+                .long("custom-assets")
+                .action(ArgAction::SetTrue)
+                .help("Load bat's custom assets. Note that this flag may not work with some version of `bat` command. This flag is only for bat printer"),
+        )
+        .arg(
+            Arg::new("no-blank-line-separator")
+                .long("no-blank-line-separator")
+                .action(ArgAction::SetTrue)
+                .help("Do not print empty lines between files when grid is disabled. This flag is only for bat printer"),
+        );
+
     #[cfg(feature = "syntect-printer")]
     let cmd = cmd
         .arg(
@@ -223,6 +537,13 @@ fn command() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("Paint background colors. This flag is only for syntect printer"),
         )
+        .arg(
+            Arg::new("background-color")
+                .long("background-color")
+                .num_args(1)
+                .value_name("HEX")
+                .help("Paint background with the color in hex notation like '#1e1e1e' instead of the theme's background color. Other colors are adjusted to the background. This option implies --background. This option is only for syntect printer"),
+        )
         .arg(
             Arg::new("ascii-lines")
                 .long("ascii-lines")
@@ -230,6 +551,188 @@ fn command() -> Command {
                 .help(
                     "Use ASCII characters for drawing border lines instead of Unicode characters",
                 ),
+        )
+        .arg(
+            Arg::new("respect-modeline")
+                .long("respect-modeline")
+                .action(ArgAction::SetTrue)
+                .help("Detect syntax from Vim modeline such as 'vim: set ft=ruby:' in the first or last 5 lines of each file. The modeline takes precedence over file extension, file name, and first line. When the filetype in the modeline is unknown, syntax is detected as usual. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("replace-newline")
+                .long("replace-newline")
+                .action(ArgAction::SetTrue)
+                .help("Print each multi-line match as one line by replacing newlines in the match with '\u{2424}'. The joined line is wrapped as one line with the first line number. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("gutter-char")
+                .long("gutter-char")
+                .num_args(1)
+                .value_name("CHAR")
+                .help("Character to draw the vertical line between line numbers and code in grid mode. The character must occupy single column in terminal. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("match-marker")
+                .long("match-marker")
+                .num_args(1)
+                .value_name("CHAR")
+                .help("Character to mark matched lines in gutter like '▶' or '*'. It is drawn before the line number. The character must occupy single column in terminal. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("context-fold")
+                .long("context-fold")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Fold context lines between two matches in the same code snippet into one marker line when they are more than NUM lines away from both matches. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("numbers-only-on-match")
+                .long("numbers-only-on-match")
+                .action(ArgAction::SetTrue)
+                .help("Show line numbers only on matched lines. The gutter of context lines is left blank. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("dedup-lines")
+                .long("dedup-lines")
+                .action(ArgAction::SetTrue)
+                .help("Collapse code snippets whose matched lines are identical to the ones of a previous snippet in the same file. Only the first occurrence is shown with a note of the number of collapsed snippets. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("no-match-highlight")
+                .long("no-match-highlight")
+                .action(ArgAction::SetTrue)
+                .help("Do not highlight matched lines and regions. Code snippets are shown only with syntax highlighting. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("show-control-chars")
+                .long("show-control-chars")
+                .action(ArgAction::SetTrue)
+                .help("Show control characters such as stray carriage return as Unicode control pictures like '␍'. This is useful to diagnose issues of line endings. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("chunk-headers")
+                .long("chunk-headers")
+                .action(ArgAction::SetTrue)
+                .help("Show a header with the range of line numbers before each code snippet instead of '...' separator lines. This is useful to navigate files with many code snippets. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("separator-style")
+                .long("separator-style")
+                .num_args(1)
+                .value_name("STYLE")
+                .value_parser(["dashed", "blank", "dots"])
+                .ignore_case(true)
+                .help("Style of separator lines between code snippets. 'dashed' draws '...' with a dashed rule (default). 'blank' draws a blank line with only gutter. 'dots' draws '...' without a rule. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("separator-width")
+                .long("separator-width")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Max width (number of characters) of the dashed rule in separator lines between code snippets. The rest of the line is left blank. The rule is drawn to the right edge of the terminal by default. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("line-numbers-right")
+                .long("line-numbers-right")
+                .action(ArgAction::SetTrue)
+                .help("Draw the gutter with line numbers at the right of code instead of the left. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("compact-gutter")
+                .long("compact-gutter")
+                .action(ArgAction::SetTrue)
+                .help("Drop the margins around line numbers in the gutter like '12│code' for ultra-compact output. This flag takes effect only when no context line is printed such as -c0 -C0 or --matches-only. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("space-after-match")
+                .long("space-after-match")
+                .action(ArgAction::SetTrue)
+                .help("Insert a blank line after each matched line to separate dense matches. When the matched line is wrapped, the blank line is inserted after the last row. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("wrap-marker")
+                .long("wrap-marker")
+                .action(ArgAction::SetTrue)
+                .help("Draw '↪' at the start of each continuation row when a long line is wrapped with --wrap char. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("indent-guides")
+                .long("indent-guides")
+                .action(ArgAction::SetTrue)
+                .help("Draw vertical guides at each indent level in leading whitespaces of code like editors. The width of indent level is the same as --tab. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("minimap")
+                .long("minimap")
+                .action(ArgAction::SetTrue)
+                .help("Show density of matched lines in the whole file at the right margin of code snippets like a scrollbar. This is useful with a large -C or --context-fold to see where matches are concentrated in a large file. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("blame")
+                .long("blame")
+                .action(ArgAction::SetTrue)
+                .help("Annotate matched lines with the short commit hash, the author date, and the author name from `git blame` at the right margin of code snippets. Files which are not tracked by Git are printed without annotations. This requires `git` command. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("render-cache")
+                .long("render-cache")
+                .action(ArgAction::SetTrue)
+                .help("Reuse rendered code snippets for files which have the same contents such as vendored copies. Only headers are rendered again. This is useful to search trees with many duplicate files. This flag is ignored with --blame. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("max-chunk-width")
+                .long("max-chunk-width")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Max width (number of characters) of code in each code snippet. Lines are wrapped at the width even if the terminal is wider. Unlike --term-width, this does not affect the widths of headers and grid lines. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("hyperlink")
+                .long("hyperlink")
+                .action(ArgAction::SetTrue)
+                .help("Make file paths in headers clickable links to the files with OSC 8 escape sequence. Relative paths are resolved against the current directory. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("first-region-per-line")
+                .long("first-region-per-line")
+                .action(ArgAction::SetTrue)
+                .help("Highlight only the first matched region in each line. This is useful when lines have many matches. Matched lines are still highlighted as a whole. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("region-style")
+                .long("region-style")
+                .num_args(1)
+                .value_name("STYLE")
+                .value_parser(["color", "reverse"])
+                .ignore_case(true)
+                .help("Style of matched regions. 'color' uses colors of the theme (default). 'reverse' uses reverse video attribute of terminal which fits to any terminal colors. This option is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("highlight-word")
+                .long("highlight-word")
+                .action(ArgAction::SetTrue)
+                .help("Expand highlighted regions of matches to the boundaries of words so that whole words are highlighted. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("monospace-width")
+                .long("monospace-width")
+                .action(ArgAction::SetTrue)
+                .help("Treat every character as occupying single column in terminal when calculating layout. This is a workaround for terminals which render wide characters such as CJK characters in single column. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("legend")
+                .long("legend")
+                .action(ArgAction::SetTrue)
+                .help("Print the key explaining colors of matched lines, matched regions, line numbers, and gutter before printing the results. This flag is only for syntect printer"),
+        )
+        .arg(
+            Arg::new("render-only")
+                .long("render-only")
+                .num_args(1)
+                .value_name("PATH")
+                .value_hint(clap::ValueHint::FilePath)
+                .value_parser(clap::builder::ValueParser::path_buf())
+                .help("Render the whole file at PATH with syntax highlighting without searching. --first-only is ignored since the whole file is one chunk. This option is only for syntect printer"),
         );
 
     #[cfg(feature = "ripgrep")]
@@ -240,21 +743,65 @@ fn command() -> Command {
                     .action(ArgAction::SetTrue)
                     .help("Don't respect ignore files (.gitignore, .ignore, etc.)"),
             )
+            .arg(
+                Arg::new("no-ignore-vcs")
+                    .long("no-ignore-vcs")
+                    .action(ArgAction::SetTrue)
+                    .help("Don't respect ignore files of version control systems such as .gitignore, global gitignore, and .git/info/exclude. .ignore and .rgignore files are still respected"),
+            )
+            .arg(
+                Arg::new("no-ignore-dot")
+                    .long("no-ignore-dot")
+                    .action(ArgAction::SetTrue)
+                    .help("Don't respect .ignore and .rgignore files. Ignore files of version control systems such as .gitignore are still respected"),
+            )
+            .arg(
+                Arg::new("highlight-matches-in-context-lines")
+                    .long("highlight-matches-in-context-lines")
+                    .action(ArgAction::SetTrue)
+                    .help("Highlight occurrences of the pattern in context lines as well as in matched lines. By default, only the matches reported by the search are highlighted. For example, matches after --max-count are not highlighted. This flag is only for syntect printer"),
+            )
+            .arg(
+                Arg::new("search-binary-as-text")
+                    .long("search-binary-as-text")
+                    .action(ArgAction::SetTrue)
+                    .help("Search binary files as if they were text files. Binary files are skipped by default. With syntect printer, control characters in the output are shown as Unicode control pictures like '␀' so that they don't corrupt the terminal"),
+            )
             .arg(
                 Arg::new("ignore-case")
                     .short('i')
                     .long("ignore-case")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("smart-case")
-                    .help("When this flag is provided, the given pattern will be searched case insensitively. This flag overrides --smart-case"),
+                    .overrides_with_all(["smart-case", "case-sensitive"])
+                    .help("When this flag is provided, the given pattern will be searched case insensitively. This flag overrides --smart-case and --case-sensitive"),
             )
             .arg(
                 Arg::new("smart-case")
                     .short('S')
                     .long("smart-case")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("ignore-case")
-                    .help("Search case insensitively if the pattern is all lowercase. Search case sensitively otherwise. This flag overrides --ignore-case"),
+                    .overrides_with_all(["ignore-case", "case-sensitive"])
+                    .help("Search case insensitively if the pattern is all lowercase. Search case sensitively otherwise. This flag overrides --ignore-case and --case-sensitive"),
+            )
+            .arg(
+                Arg::new("case-sensitive")
+                    .short('s')
+                    .long("case-sensitive")
+                    .action(ArgAction::SetTrue)
+                    .overrides_with_all(["ignore-case", "smart-case"])
+                    .help("Search case sensitively. This is the default behavior. This flag is useful to override --ignore-case and --smart-case given in HGREP_DEFAULT_OPTS environment variable"),
+            )
+            .arg(
+                Arg::new("hidden-top-only")
+                    .long("hidden-top-only")
+                    .action(ArgAction::SetTrue)
+                    .help("Search hidden files directly under the search paths such as .env, but skip hidden directories such as .git and hidden files in subdirectories"),
+            )
+            .arg(
+                Arg::new("ignore-dot")
+                    .long("ignore-dot")
+                    .action(ArgAction::SetTrue)
+                    .help("Skip files and directories whose names start with '.' even if they are searched with --hidden or --glob. Paths explicitly given as arguments are still searched"),
             )
             .arg(
                 Arg::new("hidden")
@@ -273,6 +820,23 @@ fn command() -> Command {
                     .allow_hyphen_values(true)
                     .help("Include or exclude files and directories for searching that match the given glob"),
             )
+            .arg(
+                Arg::new("iglob")
+                    .long("iglob")
+                    .action(ArgAction::Append)
+                    .num_args(1)
+                    .value_name("GLOB")
+                    .allow_hyphen_values(true)
+                    .help("Include or exclude files and directories for searching that match the given glob case insensitively. Globs given with this option take precedence over --glob"),
+            )
+            .arg(
+                Arg::new("ext")
+                    .long("ext")
+                    .action(ArgAction::Append)
+                    .num_args(1)
+                    .value_name("EXT")
+                    .help("Only search files which have the extension EXT. Multiple extensions can be separated by comma like 'rs,go'. This option is repeatable. This is a shorthand of --glob '*.EXT' and --glob takes precedence over this option. --glob-case-insensitive is also applied"),
+            )
             .arg(
                 Arg::new("glob-case-insensitive")
                     .long("glob-case-insensitive")
@@ -354,7 +918,18 @@ fn command() -> Command {
                     .short('P')
                     .long("pcre2")
                     .action(ArgAction::SetTrue)
-                    .help("When this flag is present, hgrep will use the PCRE2 regex engine instead of its default regex engine"),
+                    .overrides_with("engine")
+                    .help("When this flag is present, hgrep will use the PCRE2 regex engine instead of its default regex engine. This is an alias of '--engine pcre2'"),
+            )
+            .arg(
+                Arg::new("engine")
+                    .long("engine")
+                    .num_args(1)
+                    .value_name("ENGINE")
+                    .value_parser(["default", "pcre2"])
+                    .ignore_case(true)
+                    .overrides_with("pcre2")
+                    .help("Regex engine to search files. 'default' is the Rust regex engine and 'pcre2' is the PCRE2 regex engine. --fixed-strings always uses the default engine"),
             )
             .arg(
                 Arg::new("type")
@@ -375,17 +950,28 @@ fn command() -> Command {
                     .help("Do not search files matching TYPE. Inverse of --type. This option is repeatable. --type-list can print the list of types"),
             )
             .arg(
-                Arg::new("type-list")
+                Arg::new("type-case-insensitive")
+                    .long("type-case-insensitive")
+                    .action(ArgAction::SetTrue)
+                    .help("Match file names with globs of file types given with -t/--type and -T/--type-not case insensitively"),
+            )
+            .arg(
+                Arg::new("ignore-case-paths")
+                    .long("ignore-case-paths")
+                    .action(ArgAction::SetTrue)
+                    .help("Match file paths case insensitively with both globs given with -g/--glob and file types given with -t/--type and -T/--type-not. This is a shorthand of --glob-case-insensitive and --type-case-insensitive, which are enabled by this flag regardless of whether they are given. This is useful on case-insensitive file systems such as macOS and Windows"),
+            )
+            .arg(
+                Arg::new("type-list")
                     .long("type-list")
                     .action(ArgAction::SetTrue)
                     .help("Show all supported file types and their corresponding globs"),
             )
             .arg(
-                Arg::new("max-filesize")
-                    .long("max-filesize")
-                    .num_args(1)
-                    .value_name("NUM+SUFFIX?")
-                    .help("Ignore files larger than NUM in size. This does not apply to directories.The input format accepts suffixes of K, M or G which correspond to kilobytes, megabytes and gigabytes, respectively. If no suffix is provided the input is treated as bytes"),
+                Arg::new("type-list-json")
+                    .long("type-list-json")
+                    .action(ArgAction::SetTrue)
+                    .help("Show all supported file types and their corresponding globs in one JSON array like '[{\"name\":\"rust\",\"globs\":[\"*.rs\"]}]'. This is useful for tools such as editor integrations"),
             )
             .arg(
                 Arg::new("invert-match")
@@ -394,12 +980,25 @@ fn command() -> Command {
                     .action(ArgAction::SetTrue)
                     .help("Invert matching. Show lines that do not match the given pattern"),
             )
+            .arg(
+                Arg::new("exclude-match")
+                    .long("exclude-match")
+                    .num_args(1)
+                    .value_name("REGEX")
+                    .help("Drop matched lines which also match REGEX. Unlike --invert-match, lines which don't match the pattern are still not shown. REGEX is always compiled with the default regex engine. With --multiline, a match spanning multiple lines is dropped as a whole when any of its lines matches REGEX. Dropped lines are not counted for --max-count"),
+            )
             .arg(
                 Arg::new("one-file-system")
                     .long("one-file-system")
                     .action(ArgAction::SetTrue)
                     .help("When enabled, the search will not cross file system boundaries relative to where it started from"),
             )
+            .arg(
+                Arg::new("require-git")
+                    .long("require-git")
+                    .action(ArgAction::SetTrue)
+                    .help("Respect .gitignore files only within git repositories like ripgrep. By default, hgrep respects .gitignore files even outside git repositories"),
+            )
             .arg(
                 Arg::new("no-unicode")
                     .long("no-unicode")
@@ -427,6 +1026,21 @@ fn command() -> Command {
                     .action(ArgAction::Count)
                     .help(r#"Reduce the level of "smart" filtering by repeated uses (up to 2). A single flag is equivalent to --no-ignore. Two flags are equivalent to --no-ignore --hidden. Unlike ripgrep, three flags are not supported since hgrep doesn't support --binary flag"#)
             )
+            .arg(
+                Arg::new("highlight")
+                    .long("highlight")
+                    .num_args(1)
+                    .value_name("PATTERN")
+                    .conflicts_with("PATTERN")
+                    .help("Highlight matched regions of PATTERN in the lines read from stdin. Search options such as -i and -S are also applied to PATTERN")
+            )
+            .arg(
+                Arg::new("pattern-from-stdin")
+                    .long("pattern-from-stdin")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["highlight", "input"])
+                    .help("Read the pattern to search from the first line of stdin. All positional arguments are treated as paths to search. Without this flag, stdin is read as grep output when no pattern is given")
+            )
             .arg(
                 Arg::new("PATTERN")
                     .help("Pattern to search. Regular expression is available"),
@@ -439,6 +1053,12 @@ fn command() -> Command {
                     .value_parser(clap::builder::ValueParser::path_buf()),
             );
 
+    // --render-only is only defined with syntect printer and PATTERN is only defined with ripgrep
+    #[cfg(all(feature = "ripgrep", feature = "syntect-printer"))]
+    let cmd = cmd
+        .mut_arg("pattern-from-stdin", |a| a.conflicts_with("render-only"))
+        .mut_arg("render-only", |a| a.conflicts_with("PATTERN"));
+
     cmd
 }
 
@@ -465,6 +1085,45 @@ fn generate_completion_script<W: io::Write>(shell: &str, out: &mut W) {
     }
 }
 
+// Read the first line of the input as a pattern for --pattern-from-stdin
+#[cfg(feature = "ripgrep")]
+fn read_pattern<R: io::BufRead>(mut input: R) -> Result<String> {
+    let mut line = String::new();
+    if input
+        .read_line(&mut line)
+        .context("Could not read pattern from stdin")?
+        == 0
+    {
+        anyhow::bail!("No pattern was given via stdin for --pattern-from-stdin flag");
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+// Paths to search. When the pattern is read from stdin, the first positional argument is also a path
+#[cfg(feature = "ripgrep")]
+fn search_paths(matches: &ArgMatches, pattern_from_stdin: bool) -> Option<Vec<&std::path::Path>> {
+    use std::path::{Path, PathBuf};
+
+    let first = matches
+        .get_one::<String>("PATTERN")
+        .filter(|_| pattern_from_stdin)
+        .map(Path::new);
+    let rest = matches
+        .get_many::<PathBuf>("PATH")
+        .map(|p| p.map(PathBuf::as_path));
+    if first.is_none() && rest.is_none() {
+        return None;
+    }
+    Some(
+        first
+            .into_iter()
+            .chain(rest.into_iter().flatten())
+            .collect(),
+    )
+}
+
 #[cfg(feature = "ripgrep")]
 fn build_ripgrep_config(
     min_context: u64,
@@ -476,11 +1135,18 @@ fn build_ripgrep_config(
         .min_context(min_context)
         .max_context(max_context)
         .no_ignore(matches.get_flag("no-ignore"))
+        .no_ignore_vcs(matches.get_flag("no-ignore-vcs"))
+        .no_ignore_dot(matches.get_flag("no-ignore-dot"))
+        .search_binary_as_text(matches.get_flag("search-binary-as-text"))
+        .highlight_context_matches(matches.get_flag("highlight-matches-in-context-lines"))
         .hidden(matches.get_flag("hidden"))
+        .hidden_top_only(matches.get_flag("hidden-top-only"))
+        .ignore_dot(matches.get_flag("ignore-dot"))
         .case_insensitive(matches.get_flag("ignore-case"))
         .smart_case(matches.get_flag("smart-case"))
         .glob_case_insensitive(matches.get_flag("glob-case-insensitive"))
-        .pcre2(matches.get_flag("pcre2")) // must be before fixed_string
+        .ignore_case_paths(matches.get_flag("ignore-case-paths"))
+        .engine(engine(matches)) // must be before fixed_string
         .fixed_strings(matches.get_flag("fixed-strings"))
         .word_regexp(matches.get_flag("word-regexp"))
         .follow_symlink(matches.get_flag("follow-symlink"))
@@ -491,12 +1157,25 @@ fn build_ripgrep_config(
         .line_regexp(matches.get_flag("line-regexp"))
         .invert_match(matches.get_flag("invert-match"))
         .one_file_system(matches.get_flag("one-file-system"))
+        .require_git(matches.get_flag("require-git"))
         .no_unicode(matches.get_flag("no-unicode"));
 
     if let Some(globs) = matches.get_many::<String>("glob") {
         config.globs(globs.map(String::as_str));
     }
 
+    if let Some(pat) = matches.get_one::<String>("exclude-match") {
+        config.exclude_match(pat);
+    }
+
+    if let Some(globs) = matches.get_many::<String>("iglob") {
+        config.iglobs(globs.map(String::as_str));
+    }
+
+    if let Some(exts) = matches.get_many::<String>("ext") {
+        config.exts(exts.flat_map(|e| e.split(',')));
+    }
+
     if let Some(num) = matches.get_one::<String>("max-count") {
         let num = num
             .parse()
@@ -539,6 +1218,29 @@ fn build_ripgrep_config(
         config.types_not(types_not.map(String::as_str));
     }
 
+    config.type_case_insensitive(matches.get_flag("type-case-insensitive"));
+    config.first_match_only(matches.get_flag("first-match-only"));
+    config.trim_trailing_blank_lines(matches.get_flag("trim-trailing-newlines"));
+    config.per_match_context(matches.get_flag("per-match-context"));
+    config.match_bounded_context(matches.get_flag("match-bounded-context"));
+    config.balance_edge_context(matches.get_flag("balance-edge-context"));
+    config.strip_cwd_prefix(matches.get_flag("strip-cwd-prefix"));
+    config.debug_encoding(matches.get_flag("debug-encoding"));
+    config.no_parallel(matches.get_flag("no-parallel"));
+    config.min_matches(min_matches(matches)?);
+
+    if let Some(num) = max_results(matches)? {
+        config.max_results(num);
+    }
+
+    if let Some(threshold) = warn_slow(matches)? {
+        config.warn_slow(threshold);
+    }
+
+    if let Some(ratio) = context_ratio(matches)? {
+        config.context_ratio(ratio);
+    }
+
     match matches.get_count("unrestricted") {
         0 => {}
         1 => {
@@ -554,9 +1256,276 @@ fn build_ripgrep_config(
         config.encoding(encoding);
     }
 
+    if let Some(key) = sort_key(matches) {
+        config
+            .sort(key)
+            .sort_reverse(matches.get_flag("sort-reverse"));
+    }
+
     Ok(config)
 }
 
+#[cfg(feature = "ripgrep")]
+fn engine(matches: &ArgMatches) -> ripgrep::Engine {
+    if matches.get_flag("pcre2") {
+        return ripgrep::Engine::Pcre2;
+    }
+    match matches.get_one::<String>("engine") {
+        Some(e) if e.eq_ignore_ascii_case("pcre2") => ripgrep::Engine::Pcre2,
+        Some(e) if e.eq_ignore_ascii_case("default") => ripgrep::Engine::Default,
+        None => ripgrep::Engine::Default,
+        Some(_) => unreachable!(), // Option value was validated by clap
+    }
+}
+
+fn sort_key(matches: &ArgMatches) -> Option<SortKey> {
+    let key = matches.get_one::<String>("sort")?;
+    if key.eq_ignore_ascii_case("path") {
+        Some(SortKey::Path)
+    } else if key.eq_ignore_ascii_case("modified") {
+        Some(SortKey::Modified)
+    } else {
+        unreachable!(); // Option value was validated by clap
+    }
+}
+
+#[cfg(feature = "ripgrep")]
+fn find_regions(file: Result<File>, matcher: Option<&ripgrep::RegionMatcher>) -> Result<File> {
+    let mut file = file?;
+    if let Some(matcher) = matcher {
+        matcher.find_regions(&mut file)?;
+    }
+    Ok(file)
+}
+
+fn context_ratio(matches: &ArgMatches) -> Result<Option<f64>> {
+    let ratio = match matches.get_one::<String>("context-ratio") {
+        Some(ratio) => ratio,
+        None => return Ok(None),
+    };
+    let ratio: f64 = ratio
+        .parse()
+        .context("Could not parse \"context-ratio\" option value as number")?;
+    if !ratio.is_finite() || ratio < 0.0 {
+        anyhow::bail!("--context-ratio option value must be non-negative number but got {ratio}");
+    }
+    Ok(Some(ratio))
+}
+
+// Destination of printed results. Results are written to stdout unless `--socket` or `--output` is specified
+enum Output {
+    Stdout(io::Stdout),
+    File(std::fs::File),
+    #[cfg(unix)]
+    Socket(std::os::unix::net::UnixStream),
+    Encoded(Box<EncodeWriter<Output>>), // Text is encoded for --output-encoding
+}
+
+impl Output {
+    fn new(matches: &ArgMatches) -> Result<Self> {
+        let output = Self::destination(matches)?;
+        if let Some(label) = matches.get_one::<String>("output-encoding") {
+            return Ok(Self::Encoded(Box::new(EncodeWriter::new(output, label)?)));
+        }
+        Ok(output)
+    }
+
+    fn destination(matches: &ArgMatches) -> Result<Self> {
+        if let Some(path) = matches.get_one::<std::path::PathBuf>("output") {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Could not create output file {:?}", path))?;
+            return Ok(Self::File(file));
+        }
+        #[cfg(unix)]
+        if let Some(path) = matches.get_one::<std::path::PathBuf>("socket") {
+            let stream = std::os::unix::net::UnixStream::connect(path)
+                .with_context(|| format!("Could not connect to socket {:?}", path))?;
+            return Ok(Self::Socket(stream));
+        }
+        Ok(Self::Stdout(io::stdout()))
+    }
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(w) => w.write(buf),
+            Self::File(w) => w.write(buf),
+            #[cfg(unix)]
+            Self::Socket(w) => w.write(buf),
+            Self::Encoded(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(w) => w.flush(),
+            Self::File(w) => w.flush(),
+            #[cfg(unix)]
+            Self::Socket(w) => w.flush(),
+            Self::Encoded(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "bat-printer")]
+fn bat_printer(opts: PrinterOptions<'_>, output: Output) -> Mutex<BatPrinter<'_>> {
+    let printer = BatPrinter::new(opts);
+    match output {
+        Output::Stdout(_) => Mutex::new(printer), // bat writes to stdout by itself
+        output => Mutex::new(printer.output(output)),
+    }
+}
+
+fn min_matches(matches: &ArgMatches) -> Result<usize> {
+    match matches.get_one::<String>("min-matches") {
+        Some(num) => num
+            .parse()
+            .context("Could not parse --min-matches option value as unsigned integer"),
+        None => Ok(0),
+    }
+}
+
+fn max_results(matches: &ArgMatches) -> Result<Option<usize>> {
+    matches
+        .get_one::<String>("max-results")
+        .map(|num| {
+            num.parse()
+                .context("Could not parse --max-results option value as unsigned integer")
+        })
+        .transpose()
+}
+
+// Matches read from stdin or the files given by --input. Matches in multiple files are merged per file
+fn grep_input(matches: &ArgMatches) -> Result<Box<dyn Iterator<Item = Result<GrepMatch>> + Send>> {
+    let skip_unparsable = matches.get_flag("skip-unparsable");
+    let null = matches.get_flag("input-null");
+    let columns = matches.get_flag("columns");
+    let Some(paths) = matches.get_many::<std::path::PathBuf>("input") else {
+        let lines = io::BufReader::new(io::stdin()).grep_lines();
+        let lines = lines
+            .skip_unparsable(skip_unparsable)
+            .null(null)
+            .columns(columns);
+        return Ok(Box::new(lines));
+    };
+    let mut inputs = vec![];
+    for path in paths {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Could not open input file {:?}", path))?;
+        inputs.push(
+            io::BufReader::new(file)
+                .grep_lines()
+                .skip_unparsable(skip_unparsable)
+                .null(null)
+                .columns(columns),
+        );
+    }
+    let merged = merge_grep_matches(inputs.into_iter().flatten())?;
+    Ok(Box::new(merged.into_iter().map(Ok)))
+}
+
+// Files with chunks calculated from the grep output given via stdin or --input
+fn grep_files(
+    matches: &ArgMatches,
+    min_context: u64,
+    max_context: u64,
+) -> Result<impl Iterator<Item = Result<File>> + Send + '_> {
+    let encoding = matches.get_one::<String>("encoding").map(String::as_str);
+    // Read the matched files ahead on background threads while printing the previous files
+    let read_ahead = if matches.get_flag("no-parallel") {
+        0
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    };
+    let files = Files::new(grep_input(matches)?, min_context, max_context, encoding)?
+        .skip_binary(matches.get_flag("skip-binary"))
+        .context_ratio(context_ratio(matches)?.unwrap_or(1.0))
+        .first_match_only(matches.get_flag("first-match-only"))
+        .trim_trailing_blank_lines(matches.get_flag("trim-trailing-newlines"))
+        .per_match_context(matches.get_flag("per-match-context"))
+        .match_bounded_context(matches.get_flag("match-bounded-context"))
+        .balance_edge_context(matches.get_flag("balance-edge-context"))
+        .strip_cwd_prefix(matches.get_flag("strip-cwd-prefix"))
+        .min_matches(min_matches(matches)?)
+        .max_filesize(max_filesize(matches)?)
+        .warn_slow(warn_slow(matches)?)
+        .debug_encoding(matches.get_flag("debug-encoding"))
+        .read_ahead(read_ahead);
+
+    #[cfg(feature = "ripgrep")]
+    let files = {
+        let region_matcher = match matches.get_one::<String>("highlight") {
+            Some(pat) => {
+                let config = build_ripgrep_config(min_context, max_context, matches)?;
+                Some(ripgrep::RegionMatcher::new(pat, &config)?)
+            }
+            None => None,
+        };
+        files.map(move |f| find_regions(f, region_matcher.as_ref()))
+    };
+
+    Ok(files)
+}
+
+fn max_filesize(matches: &ArgMatches) -> Result<Option<u64>> {
+    matches
+        .get_one::<String>("max-filesize")
+        .map(|size| {
+            parse_size(size)
+                .context("Could not parse --max-filesize option value as file size string")
+        })
+        .transpose()
+}
+
+fn warn_slow(matches: &ArgMatches) -> Result<Option<Duration>> {
+    matches
+        .get_one::<String>("warn-slow")
+        .map(|ms| {
+            ms.parse()
+                .map(Duration::from_millis)
+                .context("Could not parse --warn-slow option value as unsigned integer")
+        })
+        .transpose()
+}
+
+// Only the first `max_results` files after sorting are returned for --max-results
+fn collect_sorted_files(
+    files: impl Iterator<Item = Result<File>>,
+    key: SortKey,
+    reverse: bool,
+    max_results: usize,
+) -> Result<Vec<File>> {
+    let mut files = files.collect::<Result<Vec<_>>>()?;
+    sort_files(&mut files, key, reverse);
+    files.truncate(max_results);
+    Ok(files)
+}
+
+fn print_files_in_serial<P: Printer>(
+    printer: P,
+    files: impl Iterator<Item = Result<File>>,
+    sort: Option<SortKey>,
+    sort_reverse: bool,
+    max_results: usize,
+) -> Result<bool> {
+    let mut found = false;
+    if let Some(key) = sort {
+        for f in collect_sorted_files(files, key, sort_reverse, max_results)? {
+            printer.print(f)?;
+            found = true;
+        }
+        return Ok(found);
+    }
+
+    // Stop consuming the files after the limit so that the rest of files are not read
+    for f in files.take(max_results) {
+        printer.print(f?)?;
+        found = true;
+    }
+    Ok(found)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum PrinterKind {
     #[cfg(feature = "bat-printer")]
@@ -579,6 +1548,21 @@ fn run(matches: ArgMatches) -> Result<bool> {
         return Ok(true);
     }
 
+    if matches.get_flag("pcre2-version") {
+        #[cfg(feature = "ripgrep")]
+        {
+            ripgrep::print_pcre2_version(io::stdout().lock())?;
+            return Ok(true);
+        }
+        #[cfg(not(feature = "ripgrep"))]
+        {
+            eprintln!(
+                "PCRE2 is not available because 'ripgrep' feature was disabled at compilation"
+            );
+            return Ok(false);
+        }
+    }
+
     #[allow(unused_variables)] // printer_kind is unused when syntect-printer is disabled for now
     let printer_kind = match matches.get_one::<String>("printer").unwrap().as_str() {
         #[cfg(feature = "bat-printer")]
@@ -603,12 +1587,31 @@ fn run(matches: ArgMatches) -> Result<bool> {
         .parse()
         .context("Could not parse \"max-context\" option value as unsigned integer")?;
     let max_context = cmp::max(min_context, max_context);
+    // When min and max contexts are the same, chunks are not shortened at blank lines
+    let (min_context, max_context) = if matches.get_flag("matches-only") {
+        (0, 0)
+    } else if let Some(num) = matches.get_one::<String>("context") {
+        let num = num
+            .parse()
+            .context("Could not parse \"context\" option value as unsigned integer")?;
+        (num, num)
+    } else {
+        (min_context, max_context)
+    };
 
     let mut printer_opts = PrinterOptions::default();
     if let Some(width) = matches.get_one::<String>("tab") {
-        printer_opts.tab_width = width
+        let width = width
             .parse()
             .context("Could not parse \"tab\" option value as unsigned integer")?;
+        let expand = matches.get_flag("expand-tabs");
+        printer_opts.tab = if expand && width == 0 {
+            anyhow::bail!("--expand-tabs flag requires --tab option value greater than 0");
+        } else if width == 0 || matches.get_flag("keep-tabs") && !expand {
+            TabMode::Keep
+        } else {
+            TabMode::Expand(width)
+        };
     }
 
     #[cfg(feature = "bat-printer")]
@@ -662,6 +1665,41 @@ fn run(matches: ArgMatches) -> Result<bool> {
         printer_opts.first_only = true;
     }
 
+    if matches.get_flag("last-only") {
+        printer_opts.last_only = true;
+    }
+
+    if matches.get_flag("matches-only") {
+        printer_opts.matches_only = true;
+    }
+
+    if let Some(sep) = matches.get_one::<String>("path-separator") {
+        let mut chars = sep.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => printer_opts.path_separator = Some(c),
+            _ => anyhow::bail!(
+                "--path-separator option value must be single character but got {:?}",
+                sep,
+            ),
+        }
+    }
+
+    if let Some(prefix) = matches.get_one::<std::path::PathBuf>("strip-prefix") {
+        printer_opts.strip_prefix = Some(prefix.as_path());
+    }
+
+    if matches.get_flag("color-scheme-256") {
+        printer_opts.color_support = TermColorSupport::Ansi256;
+    }
+
+    if matches.get_flag("theme-background-detect")
+        && printer_opts.theme.is_none()
+        && printer_opts.color_support != TermColorSupport::Ansi16
+        && TermBackground::detect(Duration::from_millis(100)) == TermBackground::Light
+    {
+        printer_opts.theme = Some("Monokai Extended Light");
+    }
+
     #[cfg(feature = "syntect-printer")]
     {
         if matches.get_flag("background") {
@@ -672,6 +1710,15 @@ fn run(matches: ArgMatches) -> Result<bool> {
             }
         }
 
+        if let Some(color) = matches.get_one::<String>("background-color") {
+            printer_opts.background_rgb = Some(hgrep::printer::parse_hex_color(color)?);
+            printer_opts.background_color = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--background-color option is only available for syntect printer since bat does not support painting background colors");
+            }
+        }
+
         if matches.get_flag("ascii-lines") {
             printer_opts.ascii_lines = true;
             #[cfg(feature = "bat-printer")]
@@ -679,6 +1726,282 @@ fn run(matches: ArgMatches) -> Result<bool> {
                 anyhow::bail!("--ascii-lines flag is only available for syntect printer since bat does not support this feature");
             }
         }
+
+        if matches.get_flag("respect-modeline") {
+            printer_opts.respect_modeline = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--respect-modeline flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("replace-newline") {
+            printer_opts.replace_newline = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--replace-newline flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.contains_id("render-only") {
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--render-only option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("legend") {
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--legend flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("highlight-word") {
+            printer_opts.highlight_word = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--highlight-word flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("first-region-per-line") {
+            printer_opts.first_region_per_line = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--first-region-per-line flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(style) = matches.get_one::<String>("region-style") {
+            printer_opts.region_style = if style.eq_ignore_ascii_case("color") {
+                RegionStyle::Color
+            } else if style.eq_ignore_ascii_case("reverse") {
+                RegionStyle::Reverse
+            } else {
+                unreachable!(); // Option value was validated by clap
+            };
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--region-style option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("monospace-width") {
+            printer_opts.monospace_width = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--monospace-width flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(c) = matches.get_one::<String>("gutter-char") {
+            use unicode_width::UnicodeWidthStr;
+            if c.chars().count() != 1 || c.width() != 1 {
+                anyhow::bail!(
+                    "--gutter-char option value must be single character which occupies single column but got {:?}",
+                    c,
+                );
+            }
+            printer_opts.gutter_char = Some(c);
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--gutter-char option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(c) = matches.get_one::<String>("match-marker") {
+            use unicode_width::UnicodeWidthStr;
+            if c.chars().count() != 1 || c.width() != 1 {
+                anyhow::bail!(
+                    "--match-marker option value must be single character which occupies single column but got {:?}",
+                    c,
+                );
+            }
+            printer_opts.match_marker = Some(c);
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--match-marker option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("hyperlink") {
+            printer_opts.hyperlink = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--hyperlink flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("numbers-only-on-match") {
+            printer_opts.line_numbers_on_match_only = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--numbers-only-on-match flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("no-match-highlight") {
+            printer_opts.match_highlight = false;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--no-match-highlight flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("show-control-chars") {
+            printer_opts.show_control_chars = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--show-control-chars flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        // Raw control characters in binary files would break the terminal. Bat handles binary contents by itself
+        #[cfg(feature = "ripgrep")]
+        if matches.get_flag("search-binary-as-text") {
+            printer_opts.show_control_chars = true;
+        }
+
+        #[cfg(all(feature = "ripgrep", feature = "bat-printer"))]
+        if matches.get_flag("highlight-matches-in-context-lines")
+            && printer_kind == PrinterKind::Bat
+        {
+            anyhow::bail!("--highlight-matches-in-context-lines flag is only available for syntect printer since bat does not support this feature");
+        }
+
+        if matches.get_flag("chunk-headers") {
+            printer_opts.chunk_headers = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--chunk-headers flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(style) = matches.get_one::<String>("separator-style") {
+            printer_opts.separator_style = if style.eq_ignore_ascii_case("dashed") {
+                SeparatorStyle::Dashed
+            } else if style.eq_ignore_ascii_case("blank") {
+                SeparatorStyle::Blank
+            } else if style.eq_ignore_ascii_case("dots") {
+                SeparatorStyle::Dots
+            } else {
+                unreachable!(); // Option value was validated by clap
+            };
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--separator-style option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(width) = matches.get_one::<String>("separator-width") {
+            let width = width
+                .parse()
+                .context("Could not parse \"separator-width\" option value as unsigned integer")?;
+            printer_opts.separator_width = Some(width);
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--separator-width option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("line-numbers-right") {
+            printer_opts.line_numbers_right = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--line-numbers-right flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("compact-gutter") {
+            // Margins in the gutter are necessary to distinguish line numbers from code when context lines are printed
+            printer_opts.compact_gutter = max_context == 0;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--compact-gutter flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("space-after-match") {
+            printer_opts.space_after_match = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--space-after-match flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("wrap-marker") {
+            printer_opts.wrap_marker = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--wrap-marker flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("indent-guides") {
+            printer_opts.indent_guides = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--indent-guides flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("minimap") {
+            printer_opts.minimap = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--minimap flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("blame") {
+            printer_opts.blame = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--blame flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("render-cache") {
+            printer_opts.render_cache = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--render-cache flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if matches.get_flag("dedup-lines") {
+            printer_opts.dedup_lines = true;
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--dedup-lines flag is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(width) = matches.get_one::<String>("max-chunk-width") {
+            let width = width
+                .parse()
+                .context("Could not parse \"max-chunk-width\" option value as unsigned integer")?;
+            if width == 0 {
+                anyhow::bail!("--max-chunk-width option value must be greater than 0");
+            }
+            printer_opts.max_chunk_width = Some(width);
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--max-chunk-width option is only available for syntect printer since bat does not support this feature");
+            }
+        }
+
+        if let Some(num) = matches.get_one::<String>("context-fold") {
+            let num = num
+                .parse()
+                .context("Could not parse \"context-fold\" option value as unsigned integer")?;
+            printer_opts.context_fold = Some(num);
+            #[cfg(feature = "bat-printer")]
+            if printer_kind == PrinterKind::Bat {
+                anyhow::bail!("--context-fold option is only available for syntect printer since bat does not support this feature");
+            }
+        }
     }
 
     #[cfg(feature = "bat-printer")]
@@ -690,17 +2013,58 @@ fn run(matches: ArgMatches) -> Result<bool> {
         }
     }
 
-    if matches.get_flag("list-themes") {
+    #[cfg(feature = "bat-printer")]
+    if matches.get_flag("no-blank-line-separator") {
+        printer_opts.file_gap = 0;
+        #[cfg(feature = "syntect-printer")]
+        if printer_kind == PrinterKind::Syntect {
+            anyhow::bail!("--no-blank-line-separator flag is only available for bat printer");
+        }
+    }
+
+    if matches.get_flag("list-themes") {
+        #[cfg(feature = "syntect-printer")]
+        if printer_kind == PrinterKind::Syntect {
+            hgrep::syntect::list_themes(io::stdout().lock(), &printer_opts)?;
+            return Ok(true);
+        }
+
+        #[cfg(feature = "bat-printer")]
+        if printer_kind == PrinterKind::Bat {
+            BatPrinter::new(printer_opts).list_themes()?;
+            return Ok(true);
+        }
+
+        unreachable!();
+    }
+
+    if matches.get_flag("theme-names") {
+        #[cfg(feature = "syntect-printer")]
+        if printer_kind == PrinterKind::Syntect {
+            hgrep::syntect::list_theme_names(io::stdout().lock())?;
+            return Ok(true);
+        }
+
+        #[cfg(feature = "bat-printer")]
+        if printer_kind == PrinterKind::Bat {
+            BatPrinter::new(printer_opts).list_theme_names(io::stdout().lock())?;
+            return Ok(true);
+        }
+
+        unreachable!();
+    }
+
+    if matches.contains_id("theme-preview") {
         #[cfg(feature = "syntect-printer")]
         if printer_kind == PrinterKind::Syntect {
-            hgrep::syntect::list_themes(io::stdout().lock(), &printer_opts)?;
+            let theme = matches.get_one::<String>("theme-preview").unwrap();
+            hgrep::syntect::preview_theme(io::stdout().lock(), theme, &printer_opts)?;
             return Ok(true);
         }
 
         #[cfg(feature = "bat-printer")]
         if printer_kind == PrinterKind::Bat {
-            BatPrinter::new(printer_opts).list_themes()?;
-            return Ok(true);
+            anyhow::bail!("--theme-preview option is only available for syntect printer since bat does not support this feature");
         }
 
         unreachable!();
@@ -714,39 +2078,244 @@ fn run(matches: ArgMatches) -> Result<bool> {
     }
 
     #[cfg(feature = "ripgrep")]
-    if let Some(pattern) = matches.get_one::<String>("PATTERN") {
-        use std::path::PathBuf;
-
-        let paths = matches
-            .get_many::<PathBuf>("PATH")
-            .map(|p| p.map(PathBuf::as_path));
+    if matches.get_flag("type-list-json") {
         let config = build_ripgrep_config(min_context, max_context, &matches)?;
+        config.print_types_json(io::stdout().lock())?;
+        return Ok(true);
+    }
+
+    // Load the theme before starting search to fail fast when it is unknown
+    let assets = Assets {
+        #[cfg(feature = "syntect-printer")]
+        themes: match printer_opts.resolved_theme() {
+            Some(theme) if printer_kind == PrinterKind::Syntect => {
+                Some(hgrep::syntect::load_themes(Some(theme))?)
+            }
+            _ => None,
+        },
+    };
+
+    #[cfg(feature = "syntect-printer")]
+    if let Some(path) = matches.get_one::<std::path::PathBuf>("render-only") {
+        let encoding = matches.get_one::<String>("encoding").map(String::as_str);
+        let file = File::read_whole(path.clone(), encoding)?;
+        let output = Mutex::new(Output::new(&matches)?);
+        assets
+            .syntect_printer(output, printer_opts)?
+            .render(&file)?;
+        return Ok(true);
+    }
+
+    let start = Instant::now();
+    let total = AtomicU64::new(0);
+    let count = matches.get_flag("print-total").then_some(&total);
+    let summary_file = matches.get_one::<std::path::PathBuf>("summary-file");
+    let summary = summary_file.map(|_| JsonSummary::default());
+    let stats = matches.contains_id("stats-json").then(JsonStats::default);
+    let output = Output::new(&matches)?;
+    let found = search(
+        &matches,
+        printer_kind,
+        printer_opts,
+        assets,
+        output,
+        min_context,
+        max_context,
+        Tally {
+            total: count,
+            summary: summary.as_ref(),
+            stats: stats.as_ref(),
+        },
+    )?;
+    if count.is_some() {
+        eprintln!("{}", total.load(Ordering::Relaxed));
+    }
+    if let (Some(path), Some(summary)) = (summary_file, &summary) {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Could not create summary file {:?}", path))?;
+        summary
+            .write_to(io::BufWriter::new(file))
+            .with_context(|| format!("Could not write summary to file {:?}", path))?;
+    }
+    if let Some(stats) = &stats {
+        let elapsed = start.elapsed();
+        if let Some(path) = matches.get_one::<std::path::PathBuf>("stats-json") {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Could not create statistics file {:?}", path))?;
+            stats
+                .write_to(io::BufWriter::new(file), elapsed)
+                .with_context(|| format!("Could not write statistics to file {:?}", path))?;
+        } else {
+            stats
+                .write_to(io::stderr().lock(), elapsed)
+                .context("Could not write statistics to stderr")?;
+        }
+    }
+    Ok(found)
+}
+
+// Assets of printers loaded before starting search. Themes are loaded in advance to validate --theme and they are
+// passed to syntect printer so that they are not loaded again
+struct Assets {
+    #[cfg(feature = "syntect-printer")]
+    themes: Option<ThemeSet>,
+}
+
+impl Assets {
+    #[cfg(feature = "syntect-printer")]
+    fn syntect_printer<'main, W>(
+        self,
+        writer: W,
+        opts: PrinterOptions<'main>,
+    ) -> Result<SyntectPrinter<'main, W>> {
+        match self.themes {
+            Some(themes) => SyntectPrinter::with_themes(writer, themes, opts),
+            None => SyntectPrinter::new(writer, opts),
+        }
+    }
+}
+
+// Counters updated by every printed file for --print-total, --summary-file, and --stats-json
+struct Tally<'a> {
+    total: Option<&'a AtomicU64>,
+    summary: Option<&'a JsonSummary>,
+    stats: Option<&'a JsonStats>,
+}
+
+impl<'a> Tally<'a> {
+    fn printer<P: Printer>(&self, printer: P) -> CountingPrinter<'a, P> {
+        CountingPrinter::new(printer, self.total)
+            .summary(self.summary)
+            .stats(self.stats)
+    }
+}
+
+#[allow(unused_variables)] // printer_kind is unused when syntect-printer is disabled for now
+#[allow(clippy::too_many_arguments)]
+fn search(
+    matches: &ArgMatches,
+    printer_kind: PrinterKind,
+    printer_opts: PrinterOptions<'_>,
+    assets: Assets,
+    output: Output,
+    min_context: u64,
+    max_context: u64,
+    tally: Tally<'_>,
+) -> Result<bool> {
+    // When --pattern-from-stdin is given, stdin is used for the pattern instead of grep output
+    #[cfg(feature = "ripgrep")]
+    let stdin_pattern = if matches.get_flag("pattern-from-stdin") {
+        Some(read_pattern(io::stdin().lock())?)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "ripgrep")]
+    if let Some(pattern) = stdin_pattern
+        .as_ref()
+        .or_else(|| matches.get_one::<String>("PATTERN"))
+    {
+        let paths = search_paths(matches, stdin_pattern.is_some()).map(Vec::into_iter);
+        let config = build_ripgrep_config(min_context, max_context, matches)?;
+
+        if matches.get_flag("json-lines") {
+            let printer = JsonLinesPrinter::new(output).text(matches.get_flag("json-text"));
+            let printer = tally.printer(printer);
+            return ripgrep::grep(printer, pattern, paths, config);
+        }
+
+        if matches.get_flag("summary-only") {
+            let printer = tally.printer(SummaryPrinter::new(output).null(matches.get_flag("null")));
+            return ripgrep::grep(printer, pattern, paths, config);
+        }
+
+        if matches.get_flag("count-matches") {
+            let printer = tally.printer(CountMatchesPrinter::new(output));
+            return ripgrep::grep(printer, pattern, paths, config);
+        }
+
+        if matches.get_flag("raw") {
+            let printer = tally.printer(RawPrinter::new(output));
+            return ripgrep::grep(printer, pattern, paths, config);
+        }
 
         #[cfg(feature = "syntect-printer")]
         if printer_kind == PrinterKind::Syntect {
-            let printer = SyntectPrinter::with_stdout(printer_opts)?;
+            let printer = assets.syntect_printer(Mutex::new(output), printer_opts)?;
+            if matches.get_flag("legend") {
+                printer.print_legend()?;
+            }
+            let printer = tally.printer(printer);
             return ripgrep::grep(printer, pattern, paths, config);
         }
 
         #[cfg(feature = "bat-printer")]
         if printer_kind == PrinterKind::Bat {
-            let printer = std::sync::Mutex::new(BatPrinter::new(printer_opts));
+            let printer = bat_printer(printer_opts, output);
+            let printer = tally.printer(printer);
             return ripgrep::grep(printer, pattern, paths, config);
         }
 
         unreachable!();
     }
 
-    let encoding = matches.get_one::<String>("encoding").map(String::as_str);
+    let sort = sort_key(matches);
+    let sort_reverse = matches.get_flag("sort-reverse");
+    let max_results = max_results(matches)?.unwrap_or(usize::MAX);
+    let files = grep_files(matches, min_context, max_context)?;
+
+    if matches.get_flag("json-lines") {
+        let printer = JsonLinesPrinter::new(output).text(matches.get_flag("json-text"));
+        let printer = tally.printer(printer);
+        return print_files_in_serial(printer, files, sort, sort_reverse, max_results);
+    }
+
+    if matches.get_flag("summary-only") {
+        let printer = tally.printer(SummaryPrinter::new(output).null(matches.get_flag("null")));
+        return print_files_in_serial(printer, files, sort, sort_reverse, max_results);
+    }
+
+    if matches.get_flag("count-matches") {
+        let printer = tally.printer(CountMatchesPrinter::new(output));
+        return print_files_in_serial(printer, files, sort, sort_reverse, max_results);
+    }
+
+    if matches.get_flag("raw") {
+        let printer = tally.printer(RawPrinter::new(output));
+        return print_files_in_serial(printer, files, sort, sort_reverse, max_results);
+    }
 
     #[cfg(feature = "syntect-printer")]
     if printer_kind == PrinterKind::Syntect {
-        use hgrep::printer::Printer;
         use rayon::prelude::*;
-        let printer = SyntectPrinter::with_stdout(printer_opts)?;
-        return io::BufReader::new(io::stdin())
-            .grep_lines()
-            .chunks_per_file(min_context, max_context, encoding)?
+        let printer = assets.syntect_printer(Mutex::new(output), printer_opts)?;
+        if matches.get_flag("legend") {
+            printer.print_legend()?;
+        }
+        let printer = tally.printer(printer);
+
+        if let Some(key) = sort {
+            let files = collect_sorted_files(files, key, sort_reverse, max_results)?;
+            let found = !files.is_empty();
+            for file in files {
+                printer.print(file)?;
+            }
+            return Ok(found);
+        }
+
+        // Stop consuming the files after the limit so that the rest of files are not read
+        let files = files.take(max_results);
+
+        if matches.get_flag("no-parallel") {
+            let mut found = false;
+            for file in files {
+                printer.print(file?)?;
+                found = true;
+            }
+            return Ok(found);
+        }
+
+        return files
             .par_bridge()
             .map(|file| {
                 printer.print(file?)?;
@@ -757,17 +2326,9 @@ fn run(matches: ArgMatches) -> Result<bool> {
 
     #[cfg(feature = "bat-printer")]
     if printer_kind == PrinterKind::Bat {
-        let mut found = false;
-        let printer = BatPrinter::new(printer_opts);
-        let stdin = io::stdin();
-        for f in io::BufReader::new(stdin.lock())
-            .grep_lines()
-            .chunks_per_file(min_context, max_context, encoding)?
-        {
-            printer.print(f?)?;
-            found = true;
-        }
-        return Ok(found);
+        let printer = bat_printer(printer_opts, output);
+        let printer = tally.printer(printer);
+        return print_files_in_serial(printer, files, sort, sort_reverse, max_results);
     }
 
     unreachable!();
@@ -849,27 +2410,110 @@ mod tests {
         snapshot_test!(grid, ["--grid"]);
         snapshot_test!(no_grid, ["--no-grid"]);
         snapshot_test!(theme, ["--theme", "Nord"]);
+        snapshot_test!(theme_background_detect, ["--theme-background-detect"]);
         snapshot_test!(tab, ["--tab", "8"]);
+        snapshot_test!(keep_tabs, ["--keep-tabs"]);
+        snapshot_test!(expand_tabs, ["--keep-tabs", "--expand-tabs"]);
         snapshot_test!(bat_printer_long, ["--printer", "bat"]);
         snapshot_test!(bat_printer_short, ["-p", "bat"]);
         snapshot_test!(term_width, ["--term-width", "200"]);
         snapshot_test!(wrap_mode, ["--wrap", "never"]);
         snapshot_test!(first_only, ["--first-only"]);
+        snapshot_test!(last_only, ["--last-only"]);
         snapshot_test!(background, ["--background"]);
         snapshot_test!(ascii_lines, ["--ascii-lines"]);
         snapshot_test!(custom_assets, ["--printer", "bat", "--custom-assets"]);
+        snapshot_test!(
+            no_blank_line_separator,
+            ["--printer", "bat", "--no-grid", "--no-blank-line-separator"]
+        );
         snapshot_test!(list_themes, ["--list-themes"]);
+        snapshot_test!(theme_names, ["--theme-names"]);
+        snapshot_test!(theme_preview, ["--theme-preview", "Nord"]);
         snapshot_test!(type_list, ["--type-list"]);
+        snapshot_test!(type_list_json, ["--type-list-json"]);
         snapshot_test!(
             generate_completion_script,
             ["--generate-completion-script", "bash"]
         );
         snapshot_test!(generate_man_page, ["--generate-man-page"]);
+        snapshot_test!(pcre2_version, ["--pcre2-version"]);
         snapshot_test!(max_filesize, ["--max-filesize", "100M"]);
         snapshot_test!(unrestricted_once, ["-u"]);
         snapshot_test!(unrestricted_twice, ["-u", "-u"]);
         snapshot_test!(unrestricted_twice_in_single_flag, ["-uu"]);
         snapshot_test!(encoding, ["--encoding", "sjis"]);
+        snapshot_test!(debug_encoding, ["--debug-encoding"]);
+        snapshot_test!(no_parallel, ["--no-parallel"]);
+        snapshot_test!(max_results, ["--max-results", "3"]);
+        snapshot_test!(no_default_opts, ["--no-default-opts"]);
+        snapshot_test!(sort, ["--sort", "path"]);
+        snapshot_test!(matches_only, ["--matches-only"]);
+        snapshot_test!(replace_newline, ["--replace-newline"]);
+        snapshot_test!(gutter_char, ["--gutter-char", "|"]);
+        snapshot_test!(match_marker, ["--match-marker", "*"]);
+        snapshot_test!(highlight_word, ["--highlight-word"]);
+        snapshot_test!(first_region_per_line, ["--first-region-per-line"]);
+        snapshot_test!(region_style, ["--region-style", "reverse"]);
+        snapshot_test!(monospace_width, ["--monospace-width"]);
+        snapshot_test!(context_fold, ["--context-fold", "2"]);
+        snapshot_test!(max_chunk_width, ["--max-chunk-width", "40"]);
+        snapshot_test!(warn_slow, ["--warn-slow", "100"]);
+        snapshot_test!(numbers_only_on_match, ["--numbers-only-on-match"]);
+        snapshot_test!(dedup_lines, ["--dedup-lines"]);
+        snapshot_test!(no_match_highlight, ["--no-match-highlight"]);
+        snapshot_test!(show_control_chars, ["--show-control-chars"]);
+        snapshot_test!(minimap, ["--minimap"]);
+        snapshot_test!(blame, ["--blame"]);
+        snapshot_test!(render_cache, ["--render-cache"]);
+        snapshot_test!(indent_guides, ["--indent-guides"]);
+        snapshot_test!(wrap_marker, ["--wrap-marker"]);
+        snapshot_test!(chunk_headers, ["--chunk-headers"]);
+        snapshot_test!(separator_style, ["--separator-style", "blank"]);
+        snapshot_test!(separator_width, ["--separator-width", "20"]);
+        snapshot_test!(compact_gutter, ["--compact-gutter", "-c0", "-C0"]);
+        snapshot_test!(line_numbers_right, ["--line-numbers-right"]);
+        snapshot_test!(space_after_match, ["--space-after-match"]);
+        snapshot_test!(hyperlink, ["--hyperlink"]);
+        snapshot_test!(background_color, ["--background-color", "#1e1e1e"]);
+        snapshot_test!(legend, ["--legend"]);
+        snapshot_test!(render_only, ["--render-only", "foo.rs"]);
+        snapshot_test!(print_total, ["--print-total"]);
+        snapshot_test!(summary_file, ["--summary-file", "summary.json"]);
+        snapshot_test!(stats_json, ["--stats-json", "pat"]);
+        snapshot_test!(stats_json_path, ["--stats-json=stats.json", "pat"]);
+        snapshot_test!(input, ["--input", "a.txt", "--input", "b.txt"]);
+        snapshot_test!(skip_unparsable, ["--skip-unparsable"]);
+        snapshot_test!(input_null, ["--input-null"]);
+        snapshot_test!(columns, ["--columns"]);
+        snapshot_test!(json_lines, ["--json-lines", "--json-text"]);
+        snapshot_test!(summary_only, ["--summary-only"]);
+        snapshot_test!(null, ["--summary-only", "--null"]);
+        snapshot_test!(count_matches, ["--count-matches"]);
+        snapshot_test!(raw, ["--raw"]);
+        snapshot_test!(path_separator, ["--path-separator", "/"]);
+        snapshot_test!(strip_prefix, ["--strip-prefix", "src/"]);
+        snapshot_test!(skip_binary, ["--skip-binary"]);
+        snapshot_test!(first_match_only, ["--first-match-only"]);
+        snapshot_test!(trim_trailing_newlines, ["--trim-trailing-newlines"]);
+        snapshot_test!(per_match_context, ["--per-match-context"]);
+        snapshot_test!(match_bounded_context, ["--match-bounded-context"]);
+        snapshot_test!(balance_edge_context, ["--balance-edge-context"]);
+        snapshot_test!(strip_cwd_prefix, ["--strip-cwd-prefix"]);
+        snapshot_test!(min_matches, ["--min-matches", "3"]);
+        snapshot_test!(context_ratio, ["--context-ratio", "1.5"]);
+        #[cfg(unix)]
+        snapshot_test!(socket, ["--socket", "/path/to/hgrep.sock"]);
+        snapshot_test!(output, ["--output", "/path/to/out.txt"]);
+        snapshot_test!(output_encoding, ["--output-encoding", "sjis"]);
+        snapshot_test!(highlight, ["--highlight", "foo", "-i"]);
+        snapshot_test!(pattern_from_stdin, ["--pattern-from-stdin", "dir"]);
+        snapshot_test!(context, ["--context", "4"]);
+        snapshot_test!(respect_modeline, ["--respect-modeline"]);
+        snapshot_test!(hidden_top_only, ["--hidden-top-only"]);
+        snapshot_test!(ignore_dot, ["--ignore-dot", "--hidden"]);
+        snapshot_test!(require_git, ["--require-git"]);
+        snapshot_test!(sort_reverse, ["--sort", "modified", "--sort-reverse"]);
         snapshot_test!(
             all_printer_opts_before_args,
             [
@@ -956,13 +2600,27 @@ mod tests {
 
         snapshot_error_test!(invalid_min_context, ["--min-context", "foo"]);
         snapshot_error_test!(invalid_max_context, ["--max-context", "foo"]);
+        snapshot_error_test!(invalid_context, ["--context", "foo"]);
+        snapshot_error_test!(invalid_context_ratio, ["--context-ratio", "foo"]);
+        snapshot_error_test!(negative_context_ratio, ["--context-ratio=-1"]);
+        snapshot_error_test!(invalid_min_matches, ["--min-matches", "foo"]);
+        #[cfg(unix)]
+        snapshot_error_test!(
+            socket_not_found,
+            ["--socket", "/path/to/not/exist/hgrep.sock"]
+        );
         snapshot_error_test!(invalid_term_width, ["--term-width", "foo"]);
         snapshot_error_test!(term_width_too_small, ["--term-width", "1"]);
         snapshot_error_test!(invalid_tab_width, ["--tab", "foo"]);
+        snapshot_error_test!(expand_tabs_with_zero_width, ["--expand-tabs", "--tab", "0"]);
         snapshot_error_test!(
             invalid_opt_for_syntect,
             ["--printer", "syntect", "--custom-assets"]
         );
+        snapshot_error_test!(
+            syntect_doesnt_support_no_blank_line_separator,
+            ["--printer", "syntect", "--no-blank-line-separator"]
+        );
         snapshot_error_test!(
             bat_doesnt_support_background,
             ["--printer", "bat", "--background"]
@@ -971,6 +2629,158 @@ mod tests {
             bat_doesnt_support_ascii_lines,
             ["--printer", "bat", "--ascii-lines"]
         );
+        snapshot_error_test!(
+            bat_doesnt_support_respect_modeline,
+            ["--printer", "bat", "--respect-modeline"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_replace_newline,
+            ["--printer", "bat", "--replace-newline"]
+        );
+        snapshot_error_test!(bat_doesnt_support_legend, ["--printer", "bat", "--legend"]);
+        snapshot_error_test!(
+            bat_doesnt_support_render_only,
+            ["--printer", "bat", "--render-only", "foo.rs"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_first_region_per_line,
+            ["--printer", "bat", "--first-region-per-line"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_region_style,
+            ["--printer", "bat", "--region-style", "reverse"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_highlight_word,
+            ["--printer", "bat", "--highlight-word"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_monospace_width,
+            ["--printer", "bat", "--monospace-width"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_context_fold,
+            ["--printer", "bat", "--context-fold", "2"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_max_chunk_width,
+            ["--printer", "bat", "--max-chunk-width", "40"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_numbers_only_on_match,
+            ["--printer", "bat", "--numbers-only-on-match"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_dedup_lines,
+            ["--printer", "bat", "--dedup-lines"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_no_match_highlight,
+            ["--printer", "bat", "--no-match-highlight"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_show_control_chars,
+            ["--printer", "bat", "--show-control-chars"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_chunk_headers,
+            ["--printer", "bat", "--chunk-headers"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_separator_style,
+            ["--printer", "bat", "--separator-style", "dots"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_separator_width,
+            ["--printer", "bat", "--separator-width", "20"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_line_numbers_right,
+            ["--printer", "bat", "--line-numbers-right"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_compact_gutter,
+            ["--printer", "bat", "--compact-gutter", "-C0"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_highlight_matches_in_context_lines,
+            ["--printer", "bat", "--highlight-matches-in-context-lines"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_space_after_match,
+            ["--printer", "bat", "--space-after-match"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_theme_preview,
+            ["--printer", "bat", "--theme-preview", "Nord"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_wrap_marker,
+            ["--printer", "bat", "--wrap-marker"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_indent_guides,
+            ["--printer", "bat", "--indent-guides"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_minimap,
+            ["--printer", "bat", "--minimap"]
+        );
+        snapshot_error_test!(bat_doesnt_support_blame, ["--printer", "bat", "--blame"]);
+        snapshot_error_test!(
+            bat_doesnt_support_render_cache,
+            ["--printer", "bat", "--render-cache"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_hyperlink,
+            ["--printer", "bat", "--hyperlink"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_background_color,
+            ["--printer", "bat", "--background-color", "#1e1e1e"]
+        );
+        snapshot_error_test!(invalid_background_color, ["--background-color", "#zzz"]);
+        snapshot_error_test!(invalid_context_fold, ["--context-fold", "foo"]);
+        snapshot_error_test!(invalid_max_chunk_width, ["--max-chunk-width", "foo"]);
+        snapshot_error_test!(invalid_warn_slow, ["--warn-slow", "foo"]);
+        snapshot_error_test!(zero_max_chunk_width, ["--max-chunk-width", "0"]);
+        snapshot_error_test!(invalid_separator_width, ["--separator-width", "foo"]);
+        snapshot_error_test!(
+            bat_doesnt_support_gutter_char,
+            ["--printer", "bat", "--gutter-char", "|"]
+        );
+        snapshot_error_test!(gutter_char_multiple_chars, ["--gutter-char", "||"]);
+        snapshot_error_test!(gutter_char_wide_char, ["--gutter-char", "あ"]);
+        snapshot_error_test!(gutter_char_empty, ["--gutter-char", ""]);
+        snapshot_error_test!(
+            bat_doesnt_support_match_marker,
+            ["--printer", "bat", "--match-marker", "*"]
+        );
+        snapshot_error_test!(match_marker_multiple_chars, ["--match-marker", "**"]);
+        snapshot_error_test!(match_marker_wide_char, ["--match-marker", "あ"]);
+        snapshot_error_test!(path_separator_multiple_chars, ["--path-separator", "//"]);
+        snapshot_error_test!(path_separator_empty, ["--path-separator", ""]);
+        snapshot_error_test!(
+            unknown_syntect_theme,
+            [
+                "--printer",
+                "syntect",
+                "--theme",
+                "this-theme-does-not-exist"
+            ]
+        );
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(
+            unknown_syntect_theme_before_search,
+            [
+                "--printer",
+                "syntect",
+                "--theme",
+                "this-theme-does-not-exist",
+                "pattern",
+                "this-path-does-not-exist"
+            ]
+        );
 
         #[test]
         fn arg_parser_debug_assert() {
@@ -983,6 +2793,21 @@ mod tests {
                 &["--unknown-arg"][..],
                 &["--printer", "foo"][..],
                 &["--wrap", "foo"][..],
+                &["--separator-style", "foo"][..],
+                &["--region-style", "foo"][..],
+                &["--sort", "foo"][..],
+                &["--sort-reverse"][..],
+                &["--json-text"][..],
+                &["--summary-only", "--json-lines"][..],
+                &["--count-matches", "--json-lines"][..],
+                &["--count-matches", "--summary-only"][..],
+                &["--raw", "--json-lines"][..],
+                &["--raw", "--count-matches"][..],
+                &["--first-only", "--last-only"][..],
+                &["--null"][..],
+                &["--render-only", "foo.rs", "pattern"][..],
+                &["--pattern-from-stdin", "--highlight", "foo"][..],
+                &["--pattern-from-stdin", "--input", "out.txt"][..],
                 &["--generate-completion-script", "unknown-shell"][..],
             ] {
                 let parsed = command().try_get_matches_from(args);
@@ -1024,14 +2849,42 @@ mod tests {
         snapshot_test!(pat_only, ["pat"]);
         snapshot_test!(pat_and_dirs, ["pat", "dir1", "dir2"]);
         snapshot_test!(glob_one, ["--glob", "*.txt", "pat", "dir"]);
+        snapshot_test!(ext_one, ["--ext", "rs", "pat", "dir"]);
+        snapshot_test!(ext_many, ["--ext", "rs,go", "--ext", ".txt", "pat", "dir"]);
         snapshot_test!(
             glob_many,
             ["-g", "*.txt", "-g", "*.rs", "-g", "*.md", "pat", "dir"]
         );
         snapshot_test!(glob_before_opt, ["-g", "*.txt", "-i", "pat", "dir"]);
         snapshot_test!(glob_arg_with_hyphen, ["-g", "-foo_*.txt", "pat", "dir"]);
+        snapshot_test!(iglob_one, ["--iglob", "*.TXT", "pat", "dir"]);
+        snapshot_test!(
+            glob_and_iglob,
+            [
+                "-g",
+                "*.rs",
+                "--iglob",
+                "!*_TEST.RS",
+                "-g",
+                "!vendor",
+                "pat",
+                "dir"
+            ]
+        );
         snapshot_test!(ignore_case_smart_case, ["-i", "-S", "pat", "dir"]);
         snapshot_test!(smart_case_ignore_case, ["-S", "-i", "pat", "dir"]);
+        snapshot_test!(case_sensitive, ["-s", "pat", "dir"]);
+        snapshot_test!(ignore_case_case_sensitive, ["-i", "-s", "pat", "dir"]);
+        snapshot_test!(case_sensitive_ignore_case, ["-s", "-i", "pat", "dir"]);
+        snapshot_test!(
+            smart_case_case_sensitive,
+            ["-S", "--case-sensitive", "pat", "dir"]
+        );
+        snapshot_test!(
+            case_sensitive_smart_case,
+            ["--case-sensitive", "-S", "pat", "dir"]
+        );
+        snapshot_test!(all_case_flags, ["-i", "-S", "-s", "pat", "dir"]);
         snapshot_test!(max_count, ["--max-count", "100", "pat", "dir"]);
         snapshot_test!(max_count_short, ["-m", "100", "pat", "dir"]);
         snapshot_test!(max_depth, ["--max-depth", "10", "pat", "dir"]);
@@ -1039,10 +2892,59 @@ mod tests {
         snapshot_test!(word_regexp_line_regexp, ["-w", "-x", "pat", "dir"]);
         snapshot_test!(pcre2, ["-P", "pat", "dir"]);
         snapshot_test!(fixed_string_override_pcre2, ["-F", "-P", "pat", "dir"]);
+        snapshot_test!(engine_pcre2, ["--engine", "pcre2", "pat", "dir"]);
+        snapshot_test!(engine_default, ["--engine", "default", "pat", "dir"]);
+        snapshot_test!(
+            engine_override_pcre2,
+            ["-P", "--engine", "default", "pat", "dir"]
+        );
+        snapshot_test!(
+            pcre2_override_engine,
+            ["--engine", "default", "-P", "pat", "dir"]
+        );
+        snapshot_test!(
+            fixed_string_override_engine_pcre2,
+            ["-F", "--engine", "pcre2", "pat", "dir"]
+        );
         snapshot_test!(type_one, ["--type", "rust", "pat", "dir"]);
         snapshot_test!(type_many, ["-t", "rust", "-t", "go", "pat", "dir"]);
         snapshot_test!(type_not_one, ["--type-not", "rust", "pat", "dir"]);
         snapshot_test!(type_not_many, ["-T", "rust", "-T", "go", "pat", "dir"]);
+        snapshot_test!(context_ratio, ["--context-ratio", "0.5", "pat", "dir"]);
+        snapshot_test!(first_match_only, ["--first-match-only", "pat", "dir"]);
+        snapshot_test!(
+            trim_trailing_newlines,
+            ["--trim-trailing-newlines", "pat", "dir"]
+        );
+        snapshot_test!(per_match_context, ["--per-match-context", "pat", "dir"]);
+        snapshot_test!(
+            match_bounded_context,
+            ["--match-bounded-context", "pat", "dir"]
+        );
+        snapshot_test!(
+            balance_edge_context,
+            ["--balance-edge-context", "pat", "dir"]
+        );
+        snapshot_test!(strip_cwd_prefix, ["--strip-cwd-prefix", "pat", "dir"]);
+        snapshot_test!(warn_slow, ["--warn-slow", "100", "pat", "dir"]);
+        snapshot_test!(min_matches, ["--min-matches", "3", "pat", "dir"]);
+        snapshot_test!(
+            type_case_insensitive,
+            ["--type-case-insensitive", "-t", "rust", "pat", "dir"]
+        );
+        snapshot_test!(exclude_match, ["--exclude-match", "foo", "pat", "dir"]);
+        snapshot_test!(
+            ignore_case_paths,
+            [
+                "--ignore-case-paths",
+                "-g",
+                "*.rs",
+                "-t",
+                "rust",
+                "pat",
+                "dir"
+            ]
+        );
         snapshot_test!(
             type_and_type_not_many,
             ["-t", "rust", "-T", "rust", "-T", "go", "-t", "go", "pat", "dir"]
@@ -1083,6 +2985,27 @@ mod tests {
         snapshot_test!(unrestricted_once, ["-u"]);
         snapshot_test!(unrestricted_twice, ["-u", "-u"]);
         snapshot_test!(encoding, ["--encoding", "sjis"]);
+        snapshot_test!(debug_encoding, ["--debug-encoding"]);
+        snapshot_test!(no_parallel, ["--no-parallel"]);
+        snapshot_test!(max_results, ["--max-results", "3"]);
+        snapshot_test!(sort_reverse, ["--sort", "path", "--sort-reverse"]);
+        snapshot_test!(require_git, ["--require-git"]);
+        snapshot_test!(hidden_top_only, ["--hidden-top-only"]);
+        snapshot_test!(ignore_dot, ["--ignore-dot", "--hidden"]);
+        snapshot_test!(no_ignore_vcs, ["--no-ignore-vcs", "pat", "dir"]);
+        snapshot_test!(no_ignore_dot, ["--no-ignore-dot", "pat", "dir"]);
+        snapshot_test!(
+            no_ignore_vcs_and_dot,
+            ["--no-ignore-vcs", "--no-ignore-dot", "pat", "dir"]
+        );
+        snapshot_test!(
+            search_binary_as_text,
+            ["--search-binary-as-text", "pat", "dir"]
+        );
+        snapshot_test!(
+            highlight_matches_in_context_lines,
+            ["--highlight-matches-in-context-lines", "pat", "dir"]
+        );
 
         macro_rules! snapshot_error_test {
             ($name:ident, $args:expr) => {
@@ -1113,6 +3036,46 @@ mod tests {
         snapshot_error_test!(too_many_u_flags_single, ["-uuu"]);
     }
 
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn read_pattern_from_stdin() {
+        for (input, want) in [
+            ("foo\n", "foo"),
+            ("foo", "foo"),
+            ("foo\r\nbar\n", "foo"),
+            ("\n", ""),
+        ] {
+            let have = read_pattern(io::Cursor::new(input)).unwrap();
+            assert_eq!(have, want, "{input:?}");
+        }
+
+        let err = read_pattern(io::Cursor::new("")).unwrap_err();
+        let msg = format!("{err}");
+        assert!(msg.contains("No pattern was given via stdin"), "{msg:?}");
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn search_paths_with_pattern_from_stdin() {
+        use std::path::Path;
+
+        for (args, want) in [
+            (&["pat"][..], None),
+            (&["pat", "a", "b"][..], Some(&["a", "b"][..])),
+            (&["--pattern-from-stdin"][..], None),
+            (&["--pattern-from-stdin", "a"][..], Some(&["a"][..])),
+            (
+                &["--pattern-from-stdin", "a", "b"][..],
+                Some(&["a", "b"][..]),
+            ),
+        ] {
+            let mat = command().get_matches_from(args);
+            let have = search_paths(&mat, mat.get_flag("pattern-from-stdin"));
+            let want = want.map(|w| w.iter().map(Path::new).collect::<Vec<_>>());
+            assert_eq!(have, want, "{args:?}");
+        }
+    }
+
     #[test]
     fn generate_completion() {
         for shell in COMPLETION_SHELLS {
@@ -1122,6 +3085,220 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn write_output_to_socket() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixListener;
+
+        let path = env::temp_dir().join(format!("hgrep-test-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mat = command().get_matches_from([
+            OsString::from("hgrep"),
+            "--socket".into(),
+            path.clone().into(),
+        ]);
+        let mut output = Output::new(&mat).unwrap();
+        assert!(matches!(output, Output::Socket(_)));
+        output.write_all(b"hello").unwrap();
+        drop(output);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(received, "hello");
+    }
+
+    #[test]
+    fn write_output_to_file() {
+        use std::io::Write;
+
+        let path = env::temp_dir().join(format!("hgrep-test-output-{}.txt", process::id()));
+        std::fs::write(&path, "this is truncated").unwrap();
+
+        let mat = command().get_matches_from([
+            OsString::from("hgrep"),
+            "--output".into(),
+            path.clone().into(),
+        ]);
+        let mut output = Output::new(&mat).unwrap();
+        assert!(matches!(output, Output::File(_)));
+        output.write_all(b"hello").unwrap();
+        drop(output);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "hello");
+
+        let mat = command().get_matches_from([
+            OsString::from("hgrep"),
+            "--output".into(),
+            env::temp_dir()
+                .join("this-dir-does-not-exist")
+                .join("out.txt")
+                .into(),
+        ]);
+        let err = Output::new(&mat).err().unwrap();
+        let msg = format!("{err}");
+        assert!(msg.contains("Could not create output file"), "{msg:?}");
+    }
+
+    #[test]
+    fn write_output_with_encoding() {
+        use std::io::Write;
+
+        let path = env::temp_dir().join(format!("hgrep-test-output-sjis-{}.txt", process::id()));
+        let mat = command().get_matches_from([
+            OsString::from("hgrep"),
+            "--output".into(),
+            path.clone().into(),
+            "--output-encoding".into(),
+            "sjis".into(),
+        ]);
+        let mut output = Output::new(&mat).unwrap();
+        assert!(matches!(output, Output::Encoded(_)));
+        output
+            .write_all("\x1b[31m日本語\x1b[0m".as_bytes())
+            .unwrap();
+        drop(output);
+
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, b"\x1b[31m\x93\xfa\x96\x7b\x8c\xea\x1b[0m");
+
+        let mat = command().get_matches_from(["hgrep", "--output-encoding", "foooooo"]);
+        let err = Output::new(&mat).err().unwrap();
+        let msg = format!("{err}");
+        assert!(msg.contains("Unknown encoding name"), "{msg:?}");
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    #[test]
+    fn print_results_to_output_file() {
+        let path = env::temp_dir().join(format!("hgrep-test-results-{}.txt", process::id()));
+        let input = std::path::Path::new("testdata")
+            .join("syntect")
+            .join("multi_regions.rs");
+
+        let mat = command().get_matches_from([
+            OsString::from("--output"),
+            path.clone().into(),
+            "--render-only".into(),
+            input.into(),
+        ]);
+        assert!(run(mat).unwrap());
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains("multi_regions.rs"), "{written:?}");
+        assert!(written.contains("match to a line"), "{written:?}");
+    }
+
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("hgrep-test-{name}-{}", process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn decode_files_with_encoding() {
+        let dir = std::path::Path::new("testdata")
+            .join("chunk")
+            .join("encoding");
+        let want = std::fs::read_to_string(dir.join("utf8.txt")).unwrap();
+        let want: Vec<_> = want.lines().skip(2).take(3).collect(); // Line 3 to 5 is a chunk
+        let tmp = TempDir::new("decode-files");
+
+        let run_with = |name: &str, args: &[OsString]| {
+            let out = tmp.0.join(format!("{name}-out.txt"));
+            let mut argv = vec![
+                OsString::from("--raw"),
+                "-c1".into(),
+                "-C3".into(),
+                "--output".into(),
+                out.clone().into(),
+            ];
+            argv.extend_from_slice(args);
+            let result = run(command().get_matches_from(argv));
+            let written = std::fs::read_to_string(&out).unwrap_or_default();
+            result.map(|found| (found, written))
+        };
+
+        for (enc, file) in [("sjis", "sjis.txt"), ("utf-16le", "utf16le_bom.txt")] {
+            let path = dir.join(file);
+            let input = tmp.0.join(format!("{file}-input.txt"));
+            std::fs::write(&input, format!("{}:4:\n", path.display())).unwrap();
+            let result = run_with(
+                file,
+                &[
+                    "--input".into(),
+                    input.clone().into(),
+                    "-E".into(),
+                    enc.into(),
+                ],
+            );
+
+            let (found, written) = result.unwrap();
+            assert!(found, "{enc}: {written:?}");
+            let lines: Vec<_> = written.lines().skip(1).collect();
+            assert_eq!(lines.len(), want.len(), "{enc}: {written:?}");
+            for (got, want) in lines.iter().zip(want.iter()) {
+                assert!(got.ends_with(want), "{enc}: {got:?} vs {want:?}");
+            }
+        }
+
+        #[cfg(feature = "ripgrep")]
+        {
+            let path = dir.join("sjis.txt");
+            let (found, written) = run_with(
+                "rg-sjis",
+                &["-E".into(), "sjis".into(), "う".into(), path.clone().into()],
+            )
+            .unwrap();
+            assert!(found, "{written:?}");
+            let lines: Vec<_> = written.lines().skip(1).collect();
+            assert_eq!(lines.len(), want.len(), "{written:?}");
+            assert!(lines[1].ends_with(want[1]), "{written:?}");
+
+            let err = run_with(
+                "rg-unknown-enc",
+                &["-E".into(), "foooo".into(), "う".into(), path.into()],
+            )
+            .unwrap_err();
+            let msg = format!("{err}");
+            assert!(msg.contains("Unknown encoding name: \"foooo\""), "{msg:?}");
+        }
+
+        let input = tmp.0.join("unknown-enc-input.txt");
+        std::fs::write(&input, format!("{}:4:\n", dir.join("utf8.txt").display())).unwrap();
+        let err = run_with(
+            "unknown-enc",
+            &[
+                "--input".into(),
+                input.clone().into(),
+                "-E".into(),
+                "foooo".into(),
+            ],
+        )
+        .unwrap_err();
+        let msg = format!("{err}");
+        assert!(msg.contains("Unknown encoding name: \"foooo\""), "{msg:?}");
+    }
+
     mod args {
         use super::*;
         use std::ffi::OsString;
@@ -1186,6 +3363,34 @@ mod tests {
             assert_eq!(want, have);
         }
 
+        #[test]
+        fn no_default_opts_flag() {
+            let _lock = MU.lock().unwrap();
+            let _guard = Guard::new();
+            env::set_var(OPTS_ENV_VAR, "-i -S");
+
+            for args in [
+                &["--no-default-opts", "pat"][..],
+                &["pat", "--no-default-opts"][..],
+                &["pat", "--no-default-opts", "--", "dir"][..],
+            ] {
+                let want = args.iter().map(OsString::from).collect::<Vec<_>>();
+                let have = Args::with_args(want.clone()).unwrap().collect::<Vec<_>>();
+                assert_eq!(want, have, "{args:?}");
+            }
+
+            // The flag after `--` is not an option
+            let args = ["--", "--no-default-opts"].map(OsString::from).to_vec();
+            let have = Args::with_args(args).unwrap().collect::<Vec<_>>();
+            let want = ["-i", "-S", "--", "--no-default-opts"].map(OsString::from);
+            assert_eq!(&want[..], &have);
+
+            // Broken environment variable is not an error since it is not read
+            env::set_var(OPTS_ENV_VAR, "'-i");
+            let args = vec![OsString::from("--no-default-opts")];
+            Args::with_args(args).unwrap();
+        }
+
         #[test]
         fn broken_shell_command_in_env() {
             let _lock = MU.lock().unwrap();
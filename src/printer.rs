@@ -1,6 +1,16 @@
-use crate::chunk::File;
+use crate::broken_pipe::IgnoreBrokenPipe;
+use crate::chunk::{encoding_for_label, File, LinesInclusive};
+use crate::json::{JsonStats, JsonSummary};
 use anyhow::Result;
+use encoding_rs::{EncoderResult, Encoding};
+use std::borrow::Cow;
+use std::cmp;
 use std::env;
+use std::io::{self, Write};
+use std::path::{self, Path};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum TextWrapMode {
@@ -8,6 +18,38 @@ pub enum TextWrapMode {
     Never,
 }
 
+// How to highlight matched regions
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegionStyle {
+    Color,   // Foreground and background colors of the theme
+    Reverse, // Reverse video attribute of terminal which adapts to any terminal colors
+}
+
+// How to draw separator lines between code snippets
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeparatorStyle {
+    Dashed, // '...' in gutter followed by dashed horizontal rule
+    Blank,  // Blank line with only gutter
+    Dots,   // '...' in gutter without rule
+}
+
+// How to print tab characters in code
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TabMode {
+    Keep,          // Pass tab characters through as-is so that copied text contains them
+    Expand(usize), // Replace a tab character with the number of spaces
+}
+
+impl TabMode {
+    // Width of tab where 0 means passing tabs through as-is. This is the convention of bat's config
+    pub fn width(self) -> usize {
+        match self {
+            Self::Keep => 0,
+            Self::Expand(width) => width,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TermColorSupport {
     True,
@@ -92,42 +134,620 @@ impl TermColorSupport {
     }
 }
 
+// Brightness of terminal background for --theme-background-detect
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TermBackground {
+    Dark,
+    Light,
+}
+
+impl TermBackground {
+    // Query the background color to the terminal with OSC 11. Dark is assumed when the terminal does not respond
+    // within the timeout
+    pub fn detect(timeout: Duration) -> Self {
+        Self::query(timeout).unwrap_or(Self::Dark)
+    }
+
+    // The query and the response are written to and read from /dev/tty instead of stdout and stdin so that grep output
+    // read from stdin is never consumed. The terminal is put into non-canonical mode without echo while waiting for the
+    // response so that the response is not shown on the screen
+    #[cfg(not(windows))]
+    fn query(timeout: Duration) -> Option<Self> {
+        use std::fs::OpenOptions;
+        use std::io::{IsTerminal, Read};
+        use std::os::fd::AsRawFd;
+        use std::time::Instant;
+
+        if !std::io::stdout().is_terminal() {
+            return None; // Output is not shown on the terminal
+        }
+
+        let mut tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .ok()?;
+        let fd = tty.as_raw_fd();
+
+        let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: `fd` is a valid file descriptor while `tty` is alive and `saved` is initialized on success
+        let saved = unsafe {
+            if libc::tcgetattr(fd, saved.as_mut_ptr()) != 0 {
+                return None;
+            }
+            saved.assume_init()
+        };
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        // SAFETY: `fd` is a valid file descriptor and `raw` is a valid termios struct
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return None;
+        }
+
+        let mut response = vec![];
+        if tty
+            .write_all(b"\x1b]11;?\x07")
+            .and_then(|_| tty.flush())
+            .is_ok()
+        {
+            let deadline = Instant::now() + timeout;
+            let mut buf = [0; 64];
+            // The response is terminated with BEL or ST (ESC \\)
+            while !response.ends_with(b"\x07") && !response.ends_with(b"\x1b\\") {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let mut pfd = libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // SAFETY: `pfd` is a valid pollfd struct and its length is 1
+                if unsafe { libc::poll(&mut pfd, 1, remaining.as_millis() as libc::c_int) } <= 0 {
+                    break; // Timeout or error
+                }
+                match tty.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => response.extend_from_slice(&buf[..n]),
+                }
+            }
+        }
+
+        // SAFETY: `fd` is a valid file descriptor and `saved` is the termios struct got from the same file descriptor
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+        Self::parse_osc11_response(&response)
+    }
+
+    #[cfg(windows)]
+    fn query(_timeout: Duration) -> Option<Self> {
+        None
+    }
+
+    // Parse the response of OSC 11 query like "\x1b]11;rgb:1e1e/1e1e/1e1e\x07". Each component consists of 1 to 4 hex
+    // digits. The brightness is calculated as relative luminance
+    pub fn parse_osc11_response(response: &[u8]) -> Option<Self> {
+        let response = std::str::from_utf8(response).ok()?;
+        let start = response.find("\x1b]11;rgb:")? + "\x1b]11;rgb:".len();
+        let body = &response[start..];
+        let body = body
+            .strip_suffix('\x07')
+            .or_else(|| body.strip_suffix("\x1b\\"))?;
+
+        let mut rgb = [0.0; 3];
+        let mut components = body.split('/');
+        for c in rgb.iter_mut() {
+            let hex = components.next()?;
+            if hex.is_empty() || hex.len() > 4 {
+                return None;
+            }
+            let value = u16::from_str_radix(hex, 16).ok()?;
+            let max = (1u32 << (hex.len() * 4)) - 1;
+            *c = value as f64 / max as f64;
+        }
+        if components.next().is_some() {
+            return None;
+        }
+
+        let [r, g, b] = rgb;
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        Some(if luminance > 0.5 {
+            Self::Light
+        } else {
+            Self::Dark
+        })
+    }
+}
+
 pub struct PrinterOptions<'main> {
-    pub tab_width: usize,
+    pub tab: TabMode,
     pub theme: Option<&'main str>,
     pub grid: bool,
     pub background_color: bool,
     pub color_support: TermColorSupport,
     pub term_width: u16,
+    pub max_chunk_width: Option<u16>, // Max width of code in snippets. Header and grid lines still fit to the terminal
     pub custom_assets: bool,
+    pub file_gap: usize, // Number of empty lines between files. This is only for bat printer without grid
     pub text_wrap: TextWrapMode,
     pub first_only: bool,
+    pub last_only: bool,
+    pub matches_only: bool,
+    pub replace_newline: bool,
+    pub respect_modeline: bool,
     pub ascii_lines: bool,
+    pub gutter_char: Option<&'main str>,
+    pub match_marker: Option<&'main str>, // Character drawn before line numbers of matched lines
+    pub highlight_word: bool,
+    pub first_region_per_line: bool,
+    pub region_style: RegionStyle,
+    pub monospace_width: bool,
+    pub context_fold: Option<u64>,
+    pub line_numbers_on_match_only: bool,
+    pub dedup_lines: bool,
+    pub match_highlight: bool,
+    pub show_control_chars: bool,
+    pub minimap: bool,
+    pub blame: bool,
+    pub render_cache: bool,
+    pub chunk_headers: bool,
+    pub separator_style: SeparatorStyle,
+    pub separator_width: Option<u16>, // Max width of dashed rule in separator lines
+    pub compact_gutter: bool, // Drop margins around line numbers. This is only set when no context is printed
+    pub line_numbers_right: bool, // Draw the gutter with line numbers at the right of code
+    pub space_after_match: bool,
+    pub indent_guides: bool,
+    pub wrap_marker: bool,
+    pub hyperlink: bool,
+    pub background_rgb: Option<(u8, u8, u8)>,
+    pub strip_prefix: Option<&'main Path>,
+    pub path_separator: Option<char>,
 }
 
 impl<'main> Default for PrinterOptions<'main> {
     fn default() -> Self {
         use terminal_size::{terminal_size, Width};
         Self {
-            tab_width: 4,
+            tab: TabMode::Expand(4),
             theme: None,
             grid: true,
             background_color: false,
             color_support: TermColorSupport::detect(),
             custom_assets: false,
+            file_gap: 2,
+            max_chunk_width: None,
             term_width: terminal_size().map(|(Width(w), _)| w).unwrap_or(80), // Note: `tput` returns 80 when tty is not found
             text_wrap: TextWrapMode::Char,
             first_only: false,
+            last_only: false,
+            matches_only: false,
+            replace_newline: false,
+            respect_modeline: false,
             ascii_lines: false,
+            gutter_char: None,
+            match_marker: None,
+            highlight_word: false,
+            first_region_per_line: false,
+            region_style: RegionStyle::Color,
+            monospace_width: false,
+            context_fold: None,
+            line_numbers_on_match_only: false,
+            dedup_lines: false,
+            match_highlight: true,
+            show_control_chars: false,
+            minimap: false,
+            blame: false,
+            render_cache: false,
+            compact_gutter: false,
+            line_numbers_right: false,
+            chunk_headers: false,
+            separator_style: SeparatorStyle::Dashed,
+            separator_width: None,
+            space_after_match: false,
+            indent_guides: false,
+            wrap_marker: false,
+            hyperlink: false,
+            background_rgb: None,
+            strip_prefix: None,
+            path_separator: None,
         }
     }
 }
 
+impl<'main> PrinterOptions<'main> {
+    // Theme name where `auto` is resolved to the theme fitting to the color support of the terminal. `None` means the
+    // default theme of each printer
+    pub fn resolved_theme(&self) -> Option<&'main str> {
+        match self.theme {
+            Some("auto") => Some(match self.color_support {
+                TermColorSupport::Ansi16 => "ansi",
+                TermColorSupport::Ansi256 => "zenburn", // Colors are closest to the 256 colors palette among dark themes
+                TermColorSupport::True => "Monokai Extended",
+            }),
+            theme => theme,
+        }
+    }
+}
+
+// Parse color in hex notation like "#1e1e1e" into RGB values. The leading '#' is optional
+pub fn parse_hex_color(color: &str) -> Result<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!(
+            "Color must be hex notation with 6 digits like \"#1e1e1e\" but got {:?}",
+            color,
+        );
+    }
+    let c = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap(); // OK since all digits were checked
+    Ok((c(0), c(2), c(4)))
+}
+
+// Convert the path into a string for displaying it in headers. When the separator is specified, path separators in the
+// path are replaced with it. This is only for display and the path must not be used for opening the file.
+pub fn display_path(path: &Path, separator: Option<char>) -> Cow<'_, str> {
+    let displayed = path.as_os_str().to_string_lossy();
+    let displayed = match separator {
+        Some(sep) if displayed.contains(path::is_separator) => displayed
+            .replace(path::is_separator, sep.encode_utf8(&mut [0; 4]))
+            .into(),
+        _ => displayed,
+    };
+    // Newlines in a path break the layout of the header
+    if displayed.contains(['\n', '\r']) {
+        displayed.replace('\n', "\\n").replace('\r', "\\r").into()
+    } else {
+        displayed
+    }
+}
+
+// Remove the leading prefix from the path for displaying it in headers. The prefix is compared per path component so
+// "foo" does not strip "foobar/a.rs". When the prefix does not match or nothing would remain, the path is returned as-is.
+pub fn strip_path_prefix<'a>(path: &'a Path, prefix: Option<&Path>) -> &'a Path {
+    match prefix.and_then(|p| path.strip_prefix(p).ok()) {
+        Some(stripped) if !stripped.as_os_str().is_empty() => stripped,
+        _ => path,
+    }
+}
+
+// Build `file://` URL of the path with the line number as its anchor for OSC 8 hyperlinks. Relative paths (e.g. paths read
+// from grep output via stdin) are resolved against the current directory. When the directory is unknown, no URL is built
+// since a relative path cannot form a `file://` URL.
+pub fn file_url(path: &Path, cwd: Option<&Path>, line: u64) -> Option<String> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        cwd?.join(path)
+    };
+    let path = path.to_string_lossy();
+
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/'); // Windows path like C:\foo
+    }
+    for b in path.bytes() {
+        match b {
+            b'\\' if cfg!(windows) => url.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(b as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    url.push_str(&format!("#L{}", line));
+    Some(url)
+}
+
 // Trait to replace printer implementation for unit tests
 pub trait Printer {
     fn print(&self, file: File) -> Result<()>;
 }
 
+// Printer wrapper to count the total number of matched lines. The counter is atomic so that it can be shared across
+// threads printing files in parallel
+pub struct CountingPrinter<'a, P: Printer> {
+    printer: P,
+    count: Option<&'a AtomicU64>,
+    summary: Option<&'a JsonSummary>,
+    stats: Option<&'a JsonStats>,
+}
+
+impl<'a, P: Printer> CountingPrinter<'a, P> {
+    pub fn new(printer: P, count: Option<&'a AtomicU64>) -> Self {
+        Self {
+            printer,
+            count,
+            summary: None,
+            stats: None,
+        }
+    }
+
+    // Record each printed file to the summary for --summary-file
+    pub fn summary(mut self, summary: Option<&'a JsonSummary>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    // Count each printed file in the statistics for --stats-json
+    pub fn stats(mut self, stats: Option<&'a JsonStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+}
+
+impl<'a, P: Printer> Printer for CountingPrinter<'a, P> {
+    fn print(&self, file: File) -> Result<()> {
+        if let Some(count) = self.count {
+            count.fetch_add(file.line_matches.len() as u64, Ordering::Relaxed);
+        }
+        if let Some(summary) = self.summary {
+            summary.record(&file);
+        }
+        if let Some(stats) = self.stats {
+            stats.record(&file);
+        }
+        self.printer.print(file)
+    }
+}
+
+// Printer to print only the summary of each file like "path: C chunks, M matches" instead of rendering the chunks
+pub struct SummaryPrinter<W: Write> {
+    out: Mutex<W>,
+    null: bool,
+}
+
+impl<W: Write> SummaryPrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+            null: false,
+        }
+    }
+
+    // Terminate each path with NUL byte instead of ": " for `xargs -0` like ripgrep's --null
+    pub fn null(mut self, yes: bool) -> Self {
+        self.null = yes;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<W: Write> Printer for SummaryPrinter<W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return Ok(());
+        }
+        let line = format!(
+            "{}{}{} chunks, {} matches\n",
+            file.path.display(),
+            if self.null { "\0" } else { ": " },
+            file.chunks.len(),
+            file.line_matches.len(),
+        );
+        let mut out = self.out.lock().unwrap();
+        out.write_all(line.as_bytes()).ignore_broken_pipe()?;
+        Ok(())
+    }
+}
+
+// Printer to print the number of matches per file like 'path:N' for --count-matches. Unlike the number of matched lines,
+// each region in a line is counted. A line without regions such as a line read from grep output is counted as one match
+pub struct CountMatchesPrinter<W: Write> {
+    out: Mutex<W>,
+}
+
+impl<W: Write> CountMatchesPrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<W: Write> Printer for CountMatchesPrinter<W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return Ok(());
+        }
+        let count: usize = file
+            .line_matches
+            .iter()
+            .map(|m| cmp::max(m.ranges.len(), 1))
+            .sum();
+        let line = format!("{}:{}\n", file.path.display(), count);
+        let mut out = self.out.lock().unwrap();
+        out.write_all(line.as_bytes()).ignore_broken_pipe()?;
+        Ok(())
+    }
+}
+
+// Printer to print chunks as plain text without any color for --raw. Each file starts with its path and each line is
+// prefixed with its line number. Like grep, the number is followed by ':' for matched lines and '-' for context lines.
+// Chunks are separated with '--' and files are separated with an empty line
+pub struct RawPrinter<W: Write> {
+    out: Mutex<W>,
+    printed: AtomicBool,
+}
+
+impl<W: Write> RawPrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+            printed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<W: Write> Printer for RawPrinter<W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return Ok(());
+        }
+
+        let lnum_width = file.chunks.last().unwrap().1.to_string().len(); // OK since chunks are not empty
+        let mut text = format!("{}\n", file.path.display());
+        let mut lines = LinesInclusive::new(&file.contents).peekable();
+        let mut lmats = file.line_matches.iter().peekable();
+        for (i, &(start, end)) in file.chunks.iter().enumerate() {
+            if i > 0 {
+                text.push_str("--\n");
+            }
+            while let Some((line, lnum)) = lines.next_if(|&(_, lnum)| lnum <= end) {
+                if lnum < start {
+                    continue;
+                }
+                let matched = lmats.next_if(|m| m.line_number == lnum).is_some();
+                let sep = if matched { ':' } else { '-' };
+                let line = line.strip_suffix('\n').unwrap_or(line);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                text.push_str(&format!("{lnum:>lnum_width$}{sep}{line}\n"));
+            }
+        }
+
+        let mut out = self.out.lock().unwrap();
+        if self.printed.swap(true, Ordering::Relaxed) {
+            out.write_all(b"\n").ignore_broken_pipe()?;
+        }
+        out.write_all(text.as_bytes()).ignore_broken_pipe()?;
+        Ok(())
+    }
+}
+
+// Writer to encode printed text in the encoding for --output-encoding. Escape sequences such as colors and hyperlinks are
+// written as-is so that they remain ASCII. Characters which cannot be represented in the encoding are replaced with '?'.
+// Written bytes are buffered until they form complete UTF-8 characters and escape sequences
+pub struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: &'static Encoding,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    pub fn new(inner: W, label: &str) -> Result<Self> {
+        let encoding = encoding_for_label(label)?;
+        if encoding.output_encoding() != encoding {
+            anyhow::bail!(
+                "Encoding {:?} cannot be used for output. Only ASCII-compatible encodings are supported",
+                encoding.name(),
+            );
+        }
+        Ok(Self {
+            inner,
+            encoding,
+            pending: vec![],
+        })
+    }
+
+    fn write_text(&mut self, mut text: &str) -> io::Result<()> {
+        // Encoder is created per text so that stateful encodings like ISO-2022-JP return to ASCII before escape sequences
+        let mut encoder = self.encoding.new_encoder();
+        let mut buf = [0; 1024];
+        loop {
+            let (result, read, written) =
+                encoder.encode_from_utf8_without_replacement(text, &mut buf, true);
+            self.inner.write_all(&buf[..written])?;
+            text = &text[read..];
+            match result {
+                EncoderResult::InputEmpty => return Ok(()),
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(_) => self.inner.write_all(b"?")?,
+            }
+        }
+    }
+
+    // Write the pending bytes and return the number of bytes consumed. Incomplete UTF-8 characters or escape sequences at
+    // the end are not consumed
+    fn write_pending(&mut self) -> io::Result<usize> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut i = 0;
+        while i < pending.len() {
+            if pending[i] == b'\x1b' {
+                let Some(len) = escape_sequence_len(&pending[i..]) else {
+                    break;
+                };
+                self.inner.write_all(&pending[i..i + len])?;
+                i += len;
+                continue;
+            }
+            let end = memchr::memchr(b'\x1b', &pending[i..]).map_or(pending.len(), |j| i + j);
+            let bytes = &pending[i..end];
+            let len = match std::str::from_utf8(bytes) {
+                Ok(text) => {
+                    self.write_text(text)?;
+                    bytes.len()
+                }
+                Err(err) => {
+                    let valid = err.valid_up_to();
+                    self.write_text(std::str::from_utf8(&bytes[..valid]).unwrap())?;
+                    match err.error_len() {
+                        Some(len) => {
+                            self.inner.write_all(b"?")?; // Invalid UTF-8 sequence
+                            valid + len
+                        }
+                        None if end < pending.len() => {
+                            self.inner.write_all(b"?")?; // Truncated character before escape sequence
+                            bytes.len()
+                        }
+                        None => {
+                            i += valid;
+                            break; // Wait for the rest of the character
+                        }
+                    }
+                }
+            };
+            i += len;
+        }
+        self.pending = pending;
+        Ok(i)
+    }
+}
+
+// Length of the escape sequence at the head of the bytes. CSI (e.g. colors) ends with a byte in 0x40..=0x7e and OSC (e.g.
+// hyperlinks) ends with BEL or ST. None means the sequence is not complete yet
+fn escape_sequence_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.get(1)? {
+        b'[' => bytes[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map(|i| i + 3),
+        b']' => bytes[2..].iter().enumerate().find_map(|(i, b)| match b {
+            b'\x07' => Some(i + 3),
+            b'\\' if bytes[i + 1] == b'\x1b' => Some(i + 3),
+            _ => None,
+        }),
+        _ => Some(2),
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let consumed = self.write_pending()?;
+        self.pending.drain(..consumed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for EncodeWriter<W> {
+    fn drop(&mut self) {
+        // Incomplete bytes left at the end are invalid as UTF-8 text
+        if !self.pending.is_empty() {
+            let _ = self.inner.write_all(b"?");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -216,4 +836,348 @@ mod tests {
             assert_eq!(detected, want, "COLORTERM={colorterm:?} and TERM={term:?}",);
         }
     }
+
+    #[test]
+    fn test_count_matched_lines_in_parallel() {
+        use crate::chunk::LineMatch;
+        use std::path::PathBuf;
+        use std::sync::Mutex;
+        use std::thread;
+
+        struct DummyPrinter(Mutex<usize>);
+        impl Printer for DummyPrinter {
+            fn print(&self, _file: File) -> Result<()> {
+                *self.0.lock().unwrap() += 1;
+                Ok(())
+            }
+        }
+
+        let count = AtomicU64::new(0);
+        let printer = CountingPrinter::new(DummyPrinter(Mutex::new(0)), Some(&count));
+        thread::scope(|s| {
+            for i in 0..8 {
+                let printer = &printer;
+                s.spawn(move || {
+                    let lmats = (1..=i).map(LineMatch::lnum).collect();
+                    let file = File::new(PathBuf::from("foo.rs"), lmats, vec![], String::new());
+                    printer.print(file).unwrap();
+                });
+            }
+        });
+
+        assert_eq!(count.load(Ordering::Relaxed), 28); // 0 + 1 + ... + 7
+        assert_eq!(*printer.printer.0.lock().unwrap(), 8);
+
+        let printer = CountingPrinter::new(DummyPrinter(Mutex::new(0)), None);
+        let file = File::new(
+            PathBuf::from("foo.rs"),
+            vec![LineMatch::lnum(1)],
+            vec![],
+            String::new(),
+        );
+        printer.print(file).unwrap();
+        assert_eq!(*printer.printer.0.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_display_path() {
+        let path = Path::new("foo/bar/baz.rs");
+        assert_eq!(display_path(path, None), "foo/bar/baz.rs");
+        assert_eq!(display_path(path, Some('/')), "foo/bar/baz.rs");
+        assert_eq!(display_path(path, Some('\\')), "foo\\bar\\baz.rs");
+        assert_eq!(display_path(Path::new("foo.rs"), Some('\\')), "foo.rs");
+        assert_eq!(
+            display_path(Path::new("foo\nbar\r.rs"), None),
+            "foo\\nbar\\r.rs"
+        );
+
+        let path = Path::new("foo\\bar\\baz.rs");
+        assert_eq!(display_path(path, None), "foo\\bar\\baz.rs");
+        #[cfg(windows)]
+        assert_eq!(display_path(path, Some('/')), "foo/bar/baz.rs");
+        #[cfg(not(windows))]
+        assert_eq!(display_path(path, Some('/')), "foo\\bar\\baz.rs"); // '\\' is not a separator
+    }
+
+    #[test]
+    fn test_strip_path_prefix() {
+        let path = Path::new("foo/bar/baz.rs");
+        assert_eq!(strip_path_prefix(path, None), path);
+        assert_eq!(
+            strip_path_prefix(path, Some(Path::new("foo"))),
+            Path::new("bar/baz.rs")
+        );
+        assert_eq!(
+            strip_path_prefix(path, Some(Path::new("foo/"))),
+            Path::new("bar/baz.rs")
+        );
+        assert_eq!(
+            strip_path_prefix(path, Some(Path::new("foo/bar"))),
+            Path::new("baz.rs")
+        );
+        assert_eq!(strip_path_prefix(path, Some(Path::new("bar"))), path);
+        assert_eq!(strip_path_prefix(path, Some(Path::new("fo"))), path);
+        assert_eq!(strip_path_prefix(path, Some(path)), path);
+    }
+
+    #[test]
+    fn test_parse_osc11_response() {
+        for (response, want) in [
+            ("\x1b]11;rgb:0000/0000/0000\x07", Some(TermBackground::Dark)),
+            (
+                "\x1b]11;rgb:ffff/ffff/ffff\x07",
+                Some(TermBackground::Light),
+            ),
+            (
+                "\x1b]11;rgb:1e1e/1e1e/1e1e\x1b\\",
+                Some(TermBackground::Dark),
+            ),
+            (
+                "\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\",
+                Some(TermBackground::Light),
+            ),
+            ("\x1b]11;rgb:f/f/f\x07", Some(TermBackground::Light)),
+            ("\x1b]11;rgb:28/2c/34\x07", Some(TermBackground::Dark)),
+            (
+                "\x1b]11;rgb:0000/ffff/0000\x07",
+                Some(TermBackground::Light),
+            ), // Green is bright
+            ("\x1b]11;rgb:0000/0000/ffff\x07", Some(TermBackground::Dark)), // Blue is dark
+            ("", None),
+            ("\x1b]11;rgb:ffff/ffff/ffff", None),
+            ("\x1b]11;rgb:ffff/ffff\x07", None),
+            ("\x1b]11;rgb:ffff/ffff/ffff/ffff\x07", None),
+            ("\x1b]11;rgb:fffff/ffff/ffff\x07", None),
+            ("\x1b]11;rgb:ffff//ffff\x07", None),
+            ("\x1b]11;rgb:gggg/ffff/ffff\x07", None),
+            ("\x1b]10;rgb:ffff/ffff/ffff\x07", None),
+        ] {
+            let have = TermBackground::parse_osc11_response(response.as_bytes());
+            assert_eq!(have, want, "{response:?}");
+        }
+    }
+
+    #[test]
+    fn test_resolved_theme() {
+        for (color_support, want) in [
+            (TermColorSupport::Ansi16, "ansi"),
+            (TermColorSupport::Ansi256, "zenburn"),
+            (TermColorSupport::True, "Monokai Extended"),
+        ] {
+            let mut opts = PrinterOptions {
+                color_support,
+                ..Default::default()
+            };
+            assert_eq!(opts.resolved_theme(), None);
+            opts.theme = Some("Nord");
+            assert_eq!(opts.resolved_theme(), Some("Nord"));
+            opts.theme = Some("auto");
+            assert_eq!(opts.resolved_theme(), Some(want), "{color_support:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#1e1e1e").unwrap(), (0x1e, 0x1e, 0x1e));
+        assert_eq!(parse_hex_color("FF8000").unwrap(), (255, 128, 0));
+        assert_eq!(parse_hex_color("#aBcDeF").unwrap(), (0xab, 0xcd, 0xef));
+        for color in [
+            "",
+            "#",
+            "#fff",
+            "#1e1e1e1e",
+            "#1e1e1g",
+            "##1e1e1e",
+            "#１２３",
+        ] {
+            let err = parse_hex_color(color).unwrap_err();
+            let msg = format!("{}", err);
+            assert!(
+                msg.contains("hex notation"),
+                "color={color:?}, message={msg:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_url() {
+        #[cfg(not(windows))]
+        let (cwd, abs, abs_url) = ("/path/to/cwd", "/abs/foo.rs", "file:///abs/foo.rs#L3");
+        #[cfg(windows)]
+        let (cwd, abs, abs_url) = (
+            r"C:\path\to\cwd",
+            r"C:\abs\foo.rs",
+            "file:///C:/abs/foo.rs#L3",
+        );
+        let cwd = Path::new(cwd);
+
+        // Absolute path does not depend on the current directory
+        assert_eq!(file_url(Path::new(abs), Some(cwd), 3).unwrap(), abs_url);
+        assert_eq!(file_url(Path::new(abs), None, 3).unwrap(), abs_url);
+
+        // Relative path is resolved against the current directory
+        #[cfg(not(windows))]
+        let want = "file:///path/to/cwd/src/foo.rs#L10";
+        #[cfg(windows)]
+        let want = "file:///C:/path/to/cwd/src/foo.rs#L10";
+        let rel = Path::new("src").join("foo.rs");
+        assert_eq!(file_url(&rel, Some(cwd), 10).unwrap(), want);
+        assert_eq!(file_url(&rel, None, 10), None);
+
+        // Characters not allowed in URL are escaped
+        let url = file_url(Path::new("a b/#あ.rs"), Some(cwd), 1).unwrap();
+        assert!(url.ends_with("/a%20b/%23%E3%81%82.rs#L1"), "{:?}", url);
+    }
+
+    #[test]
+    fn test_summary_printer() {
+        use crate::chunk::LineMatch;
+        use std::path::PathBuf;
+
+        let files = || {
+            [
+                File::new(
+                    PathBuf::from("foo.rs"),
+                    vec![LineMatch::lnum(2), LineMatch::lnum(3), LineMatch::lnum(10)],
+                    vec![(1, 5), (8, 12)],
+                    String::new(),
+                ),
+                File::new(PathBuf::from("no_match.rs"), vec![], vec![], String::new()),
+                File::new(
+                    PathBuf::from("dir/bar.txt"),
+                    vec![LineMatch::lnum(1)],
+                    vec![(1, 1)],
+                    String::new(),
+                ),
+            ]
+        };
+        for (null, want) in [
+            (
+                false,
+                "foo.rs: 2 chunks, 3 matches\ndir/bar.txt: 1 chunks, 1 matches\n",
+            ),
+            (
+                true,
+                "foo.rs\x002 chunks, 3 matches\ndir/bar.txt\x001 chunks, 1 matches\n",
+            ),
+        ] {
+            let printer = SummaryPrinter::new(vec![]).null(null);
+            for file in files() {
+                printer.print(file).unwrap();
+            }
+            let output = String::from_utf8(printer.into_inner()).unwrap();
+            assert_eq!(output, want, "null={null}");
+        }
+    }
+
+    #[test]
+    fn test_count_matches_printer() {
+        use crate::chunk::LineMatch;
+        use std::path::PathBuf;
+
+        let printer = CountMatchesPrinter::new(vec![]);
+        for file in [
+            File::new(
+                PathBuf::from("foo.rs"),
+                vec![
+                    LineMatch::new(2, vec![(0, 3), (5, 8), (10, 13)]),
+                    LineMatch::new(3, vec![(1, 2)]),
+                    LineMatch::new(10, vec![(0, 1), (4, 5)]),
+                ],
+                vec![(1, 5), (8, 12)],
+                String::new(),
+            ),
+            File::new(PathBuf::from("no_match.rs"), vec![], vec![], String::new()),
+            File::new(
+                PathBuf::from("dir/bar.txt"),
+                vec![LineMatch::lnum(1), LineMatch::lnum(4)], // Lines without regions
+                vec![(1, 4)],
+                String::new(),
+            ),
+        ] {
+            printer.print(file).unwrap();
+        }
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(output, "foo.rs:6\ndir/bar.txt:2\n");
+    }
+
+    #[test]
+    fn test_raw_printer() {
+        use crate::chunk::LineMatch;
+        use std::path::PathBuf;
+
+        let contents = (1..=12).map(|i| format!("line {i}\n")).collect::<String>();
+        let printer = RawPrinter::new(vec![]);
+        for file in [
+            File::new(
+                PathBuf::from("foo.rs"),
+                vec![LineMatch::lnum(2), LineMatch::lnum(3), LineMatch::lnum(10)],
+                vec![(1, 4), (9, 11)],
+                contents,
+            ),
+            File::new(PathBuf::from("no_match.rs"), vec![], vec![], String::new()),
+            File::new(
+                PathBuf::from("dir/bar.txt"),
+                vec![LineMatch::new(1, vec![(0, 3)])],
+                vec![(1, 2)],
+                "foo\r\nbar".to_string(), // CRLF and no newline at the end
+            ),
+        ] {
+            printer.print(file).unwrap();
+        }
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let want = "\
+foo.rs
+ 1-line 1
+ 2:line 2
+ 3:line 3
+ 4-line 4
+--
+ 9-line 9
+10:line 10
+11-line 11
+
+dir/bar.txt
+1:foo
+2-bar
+";
+        assert_eq!(output, want);
+        assert!(!output.contains('\x1b'), "{output:?}");
+    }
+
+    #[test]
+    fn test_encode_writer_shift_jis() {
+        let input =
+            "\x1b[38;2;1;2;3mこんにちは\x1b[0m \x1b]8;;file:///a.rs\x1b\\a.rs\x1b]8;;\x07 ✅ end\n";
+        let (want, _, _) = encoding_rs::SHIFT_JIS.encode("こんにちは");
+        let mut want = want.into_owned();
+        want.splice(0..0, b"\x1b[38;2;1;2;3m".iter().copied());
+        want.extend_from_slice(b"\x1b[0m \x1b]8;;file:///a.rs\x1b\\a.rs\x1b]8;;\x07 ? end\n");
+
+        // Write at once
+        let mut w = EncodeWriter::new(vec![], "sjis").unwrap();
+        w.write_all(input.as_bytes()).unwrap();
+        assert_eq!(w.pending, b"");
+        assert_eq!(w.inner, want);
+
+        // Write byte by byte to split UTF-8 characters and escape sequences across writes
+        let mut w = EncodeWriter::new(vec![], "shift_jis").unwrap();
+        for b in input.as_bytes() {
+            w.write_all(&[*b]).unwrap();
+        }
+        assert_eq!(w.pending, b"");
+        assert_eq!(w.inner, want);
+    }
+
+    #[test]
+    fn test_encode_writer_invalid_encoding() {
+        for (label, msg) in [
+            ("foooooo", "Unknown encoding name"),
+            ("utf-16le", "cannot be used for output"),
+        ] {
+            let err = EncodeWriter::new(vec![], label).err().unwrap();
+            let err = format!("{err}");
+            assert!(err.contains(msg), "{label:?}: {err:?}");
+        }
+    }
 }
@@ -1,55 +1,63 @@
 use crate::broken_pipe::IgnoreBrokenPipe as _;
-use crate::chunk::Files;
+use crate::chunk::{
+    self, encoding_for_label, merge_regions, parse_size, sort_files, warn_slow_file, Files,
+    LinesInclusive, SortKey,
+};
 use crate::grep::GrepMatch;
+use crate::json::write_json_str;
 use crate::printer::Printer;
-use anyhow::{Context, Result};
+use anyhow::{Context as _, Result};
 use grep_matcher::{LineTerminator, Matcher};
 use grep_pcre2::{RegexMatcher as Pcre2Matcher, RegexMatcherBuilder as Pcre2MatcherBuilder};
 use grep_regex::{RegexMatcher, RegexMatcherBuilder};
 use grep_searcher::{
     BinaryDetection, Encoding, MmapChoice, Searcher, SearcherBuilder, Sink, SinkMatch,
 };
-use ignore::overrides::OverrideBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
 use ignore::types::{Types, TypesBuilder};
-use ignore::{Walk, WalkBuilder};
+use ignore::{DirEntry, Walk, WalkBuilder};
 use rayon::prelude::*;
 use std::env;
 use std::fs::File;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 // Note: 'main is a lifetime of scope of main() function
 
-fn parse_size(input: &str) -> Result<u64> {
-    if input.is_empty() {
-        anyhow::bail!("Size string must not be empty");
-    }
-
-    let i = input.len() - 1;
-    let (input, mag) = match input.as_bytes()[i] {
-        b'k' | b'K' => (&input[..i], 1 << 10),
-        b'm' | b'M' => (&input[..i], 1 << 20),
-        b'g' | b'G' => (&input[..i], 1 << 30),
-        _ => (input, 1),
-    };
-
-    let u: u64 = input
-        .parse()
-        .with_context(|| format!("Could not parse {:?} as unsigned integer", input))?;
-
-    Ok(u * mag)
+// Regex engine to search files
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Engine {
+    #[default]
+    Default, // Rust regex crate
+    Pcre2,
 }
 
 #[derive(Default, Debug)]
 pub struct Config<'main> {
     min_context: u64,
     max_context: u64,
+    context_ratio: Option<f64>,
+    first_match_only: bool,
+    trim_trailing_blank_lines: bool,
+    per_match_context: bool,
+    match_bounded_context: bool,
+    highlight_context_matches: bool,
+    balance_edge_context: bool,
+    strip_cwd_prefix: bool,
+    min_matches: usize,
     no_ignore: bool,
+    no_ignore_vcs: bool,
+    no_ignore_dot: bool,
     hidden: bool,
+    hidden_top_only: bool,
+    ignore_dot: bool,
     case_insensitive: bool,
     smart_case: bool,
     globs: Box<[&'main str]>,
+    iglobs: Box<[&'main str]>,
+    exts: Box<[&'main str]>,
     glob_case_insensitive: bool,
     fixed_strings: bool,
     word_regexp: bool,
@@ -58,19 +66,30 @@ pub struct Config<'main> {
     crlf: bool,
     multiline_dotall: bool,
     mmap: bool,
+    search_binary_as_text: bool,
     max_count: Option<u64>,
     max_depth: Option<usize>,
     max_filesize: Option<u64>,
     line_regexp: bool,
-    pcre2: bool,
+    engine: Engine,
     types: Vec<&'main str>,
     types_not: Vec<&'main str>,
+    type_case_insensitive: bool,
+    ignore_case_paths: bool,
     invert_match: bool,
+    exclude_match: Option<&'main str>,
     one_file_system: bool,
+    require_git: bool,
     no_unicode: bool,
     regex_size_limit: Option<usize>,
     dfa_size_limit: Option<usize>,
     encoding: Option<&'main str>,
+    sort: Option<SortKey>,
+    sort_reverse: bool,
+    warn_slow: Option<Duration>,
+    debug_encoding: bool,
+    no_parallel: bool,
+    max_results: Option<usize>,
 }
 
 impl<'main> Config<'main> {
@@ -90,16 +109,106 @@ impl<'main> Config<'main> {
         self
     }
 
+    pub fn context_ratio(&mut self, ratio: f64) -> &mut Self {
+        self.context_ratio = Some(ratio);
+        self
+    }
+
+    pub fn first_match_only(&mut self, yes: bool) -> &mut Self {
+        self.first_match_only = yes;
+        self
+    }
+
+    pub fn trim_trailing_blank_lines(&mut self, yes: bool) -> &mut Self {
+        self.trim_trailing_blank_lines = yes;
+        self
+    }
+
+    pub fn per_match_context(&mut self, yes: bool) -> &mut Self {
+        self.per_match_context = yes;
+        self
+    }
+
+    pub fn match_bounded_context(&mut self, yes: bool) -> &mut Self {
+        self.match_bounded_context = yes;
+        self
+    }
+
+    // Highlight occurrences of the pattern in context lines as well as matched lines
+    pub fn highlight_context_matches(&mut self, yes: bool) -> &mut Self {
+        self.highlight_context_matches = yes;
+        self
+    }
+
+    pub fn balance_edge_context(&mut self, yes: bool) -> &mut Self {
+        self.balance_edge_context = yes;
+        self
+    }
+
+    pub fn strip_cwd_prefix(&mut self, yes: bool) -> &mut Self {
+        self.strip_cwd_prefix = yes;
+        self
+    }
+
+    pub fn warn_slow(&mut self, threshold: Duration) -> &mut Self {
+        self.warn_slow = Some(threshold);
+        self
+    }
+
+    pub fn debug_encoding(&mut self, yes: bool) -> &mut Self {
+        self.debug_encoding = yes;
+        self
+    }
+
+    // Search and print files one by one on the current thread. This is useful for debugging
+    pub fn no_parallel(&mut self, yes: bool) -> &mut Self {
+        self.no_parallel = yes;
+        self
+    }
+
+    // Stop searching after the number of files are printed. The number counts files, not matches
+    pub fn max_results(&mut self, num: usize) -> &mut Self {
+        self.max_results = Some(num);
+        self
+    }
+
+    pub fn min_matches(&mut self, num: usize) -> &mut Self {
+        self.min_matches = num;
+        self
+    }
+
     pub fn no_ignore(&mut self, yes: bool) -> &mut Self {
         self.no_ignore = yes;
         self
     }
 
+    // Don't respect ignore files of version control systems such as .gitignore, global gitignore, and .git/info/exclude
+    pub fn no_ignore_vcs(&mut self, yes: bool) -> &mut Self {
+        self.no_ignore_vcs = yes;
+        self
+    }
+
+    // Don't respect .ignore and .rgignore files
+    pub fn no_ignore_dot(&mut self, yes: bool) -> &mut Self {
+        self.no_ignore_dot = yes;
+        self
+    }
+
     pub fn hidden(&mut self, yes: bool) -> &mut Self {
         self.hidden = yes;
         self
     }
 
+    pub fn hidden_top_only(&mut self, yes: bool) -> &mut Self {
+        self.hidden_top_only = yes;
+        self
+    }
+
+    pub fn ignore_dot(&mut self, yes: bool) -> &mut Self {
+        self.ignore_dot = yes;
+        self
+    }
+
     pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
         self.case_insensitive = yes;
         if yes {
@@ -121,6 +230,17 @@ impl<'main> Config<'main> {
         self
     }
 
+    // Globs matched case insensitively regardless of --glob-case-insensitive
+    pub fn iglobs(&mut self, globs: impl Iterator<Item = &'main str>) -> &mut Self {
+        self.iglobs = globs.collect();
+        self
+    }
+
+    pub fn exts(&mut self, exts: impl Iterator<Item = &'main str>) -> &mut Self {
+        self.exts = exts.collect();
+        self
+    }
+
     pub fn glob_case_insensitive(&mut self, yes: bool) -> &mut Self {
         self.glob_case_insensitive = yes;
         self
@@ -129,7 +249,7 @@ impl<'main> Config<'main> {
     pub fn fixed_strings(&mut self, yes: bool) -> &mut Self {
         self.fixed_strings = yes;
         if yes {
-            self.pcre2 = false; // for regex_syntax::escape
+            self.engine = Engine::Default; // for regex_syntax::escape
         }
         self
     }
@@ -175,6 +295,12 @@ impl<'main> Config<'main> {
         self
     }
 
+    // Search binary files as if they were text files instead of stopping at the first NUL byte
+    pub fn search_binary_as_text(&mut self, yes: bool) -> &mut Self {
+        self.search_binary_as_text = yes;
+        self
+    }
+
     pub fn max_count(&mut self, num: u64) -> &mut Self {
         self.max_count = Some(num);
         self
@@ -185,8 +311,8 @@ impl<'main> Config<'main> {
         self
     }
 
-    pub fn pcre2(&mut self, yes: bool) -> &mut Self {
-        self.pcre2 = yes;
+    pub fn engine(&mut self, engine: Engine) -> &mut Self {
+        self.engine = engine;
         self
     }
 
@@ -200,6 +326,18 @@ impl<'main> Config<'main> {
         self
     }
 
+    pub fn type_case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.type_case_insensitive = yes;
+        self
+    }
+
+    // Match both globs and file types case insensitively. This is a shorthand of --glob-case-insensitive and
+    // --type-case-insensitive so it enables them even if they are not set. --iglob is always case insensitive
+    pub fn ignore_case_paths(&mut self, yes: bool) -> &mut Self {
+        self.ignore_case_paths = yes;
+        self
+    }
+
     pub fn max_filesize(&mut self, input: &str) -> Result<&mut Self> {
         self.max_filesize = Some(parse_size(input)?);
         Ok(self)
@@ -210,11 +348,22 @@ impl<'main> Config<'main> {
         self
     }
 
+    // Drop matched lines which also match the regex
+    pub fn exclude_match(&mut self, pat: &'main str) -> &mut Self {
+        self.exclude_match = Some(pat);
+        self
+    }
+
     pub fn one_file_system(&mut self, yes: bool) -> &mut Self {
         self.one_file_system = yes;
         self
     }
 
+    pub fn require_git(&mut self, yes: bool) -> &mut Self {
+        self.require_git = yes;
+        self
+    }
+
     pub fn no_unicode(&mut self, yes: bool) -> &mut Self {
         self.no_unicode = yes;
         self
@@ -235,30 +384,62 @@ impl<'main> Config<'main> {
         self
     }
 
-    fn build_walker(&self, mut paths: impl Iterator<Item = &'main Path>) -> Result<Walk> {
-        let target = paths.next().unwrap();
+    pub fn sort(&mut self, key: SortKey) -> &mut Self {
+        self.sort = Some(key);
+        self
+    }
 
+    pub fn sort_reverse(&mut self, yes: bool) -> &mut Self {
+        self.sort_reverse = yes;
+        self
+    }
+
+    fn build_overrides(&self, target: &Path) -> Result<Override> {
         let mut builder = OverrideBuilder::new(target);
-        if self.glob_case_insensitive {
+        if self.glob_case_insensitive || self.ignore_case_paths {
             builder.case_insensitive(true)?;
         }
+        // Globs of extensions are added before --glob so that globs given by --glob take precedence
+        for ext in self.exts.iter() {
+            let ext = ext.trim_start_matches('.');
+            if !ext.is_empty() {
+                builder.add(&format!("*.{}", ext))?;
+            }
+        }
         for glob in self.globs.iter() {
             builder.add(glob)?;
         }
-        let overrides = builder.build()?;
+        // Like ripgrep, --iglob is added after --glob so it takes precedence when both match the same path. Toggling the
+        // case sensitivity only affects globs added after that
+        if !self.iglobs.is_empty() {
+            builder.case_insensitive(true)?;
+            for glob in self.iglobs.iter() {
+                builder.add(glob)?;
+            }
+        }
+        Ok(builder.build()?)
+    }
+
+    fn build_walker(&self, mut paths: impl Iterator<Item = &'main Path>) -> Result<Walk> {
+        let target = paths.next().unwrap();
+        let overrides = self.build_overrides(target)?;
 
         let mut builder = WalkBuilder::new(target);
         for path in paths {
             builder.add(path);
         }
+        let vcs = !self.no_ignore && !self.no_ignore_vcs;
+        let dot = !self.no_ignore && !self.no_ignore_dot;
         builder
             .hidden(!self.hidden)
             .parents(!self.no_ignore)
-            .ignore(!self.no_ignore)
-            .git_global(!self.no_ignore)
-            .git_ignore(!self.no_ignore)
-            .git_exclude(!self.no_ignore)
-            .require_git(false)
+            .ignore(dot)
+            .git_global(vcs)
+            .git_ignore(vcs)
+            .git_exclude(vcs)
+            // Unlike ripgrep, .gitignore is respected even outside git repositories by default. hgrep is often used for
+            // searching directories which are not managed by git yet (e.g. extracted source archives)
+            .require_git(self.require_git)
             .follow_links(self.follow_symlink)
             .max_depth(self.max_depth)
             .max_filesize(self.max_filesize)
@@ -266,10 +447,21 @@ impl<'main> Config<'main> {
             .types(self.build_types()?)
             .same_file_system(self.one_file_system);
 
-        if !self.no_ignore {
+        if dot {
             builder.add_custom_ignore_filename(".rgignore");
         }
 
+        if self.hidden_top_only && !self.hidden {
+            // Hidden files directly under the search roots are searched, but hidden directories such as .git/ are not
+            builder.hidden(false).filter_entry(|entry| {
+                let depth = entry.depth();
+                let hidden = entry.file_name().as_encoded_bytes().starts_with(b".");
+                depth == 0
+                    || !hidden
+                    || (depth == 1 && entry.file_type().is_some_and(|t| !t.is_dir()))
+            });
+        }
+
         Ok(builder.build())
     }
 
@@ -314,6 +506,24 @@ impl<'main> Config<'main> {
         })
     }
 
+    // Matcher for --exclude-match. The regex is always compiled with the default engine regardless of --engine and --pcre2.
+    // Case-sensitivity flags like --ignore-case and --smart-case are applied as well as the pattern to search
+    fn build_exclude_matcher(&self) -> Result<Option<RegexMatcher>> {
+        let Some(pat) = self.exclude_match else {
+            return Ok(None);
+        };
+        let mut builder = RegexMatcherBuilder::new();
+        builder
+            .case_insensitive(self.case_insensitive)
+            .case_smart(self.smart_case)
+            .multi_line(true)
+            .unicode(!self.no_unicode);
+        let matcher = builder.build(pat).with_context(|| {
+            format!("Could not compile regex {pat:?} given with --exclude-match")
+        })?;
+        Ok(Some(matcher))
+    }
+
     fn build_pcre2_matcher(&self, pat: &str) -> Result<Pcre2Matcher> {
         let mut builder = Pcre2MatcherBuilder::new();
         builder
@@ -352,8 +562,13 @@ impl<'main> Config<'main> {
         } else {
             MmapChoice::never()
         };
+        let binary = if self.search_binary_as_text {
+            BinaryDetection::none()
+        } else {
+            BinaryDetection::quit(0)
+        };
         builder
-            .binary_detection(BinaryDetection::quit(0))
+            .binary_detection(binary)
             .line_number(true)
             .multi_line(self.multiline)
             .memory_map(mmap)
@@ -362,6 +577,8 @@ impl<'main> Config<'main> {
             builder.line_terminator(LineTerminator::crlf());
         }
         if let Some(label) = self.encoding {
+            // Check the label in advance to report the same error as decoding files in other modes
+            encoding_for_label(label)?;
             builder.encoding(Some(Encoding::new(label)?));
         }
         Ok(builder.build())
@@ -370,6 +587,15 @@ impl<'main> Config<'main> {
     fn build_types(&self) -> Result<Types> {
         let mut builder = TypesBuilder::new();
         builder.add_defaults();
+        if self.type_case_insensitive || self.ignore_case_paths {
+            // `TypesBuilder` does not provide an option to match globs case-insensitively
+            for def in builder.definitions() {
+                builder.clear(def.name());
+                for glob in def.globs() {
+                    builder.add(def.name(), &case_insensitive_glob(glob))?;
+                }
+            }
+        }
         for ty in &self.types {
             builder.select(ty);
         }
@@ -394,12 +620,124 @@ impl<'main> Config<'main> {
             Ok(())
         }
 
-        let types = self.build_types()?;
-        print(out, &types).ignore_broken_pipe()?;
+        print(out, &default_types()?).ignore_broken_pipe()?;
+        Ok(())
+    }
+
+    // Print the same type definitions as `print_types` in one JSON array for tools. The schema is:
+    //
+    //   [{"name":"rust","globs":["*.rs"]},...]
+    pub fn print_types_json<W: io::Write>(&self, mut out: W) -> Result<()> {
+        let mut json = String::new();
+        json.push('[');
+        for (i, def) in default_types()?.definitions().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"name\":");
+            write_json_str(&mut json, def.name());
+            json.push_str(",\"globs\":[");
+            for (i, glob) in def.globs().iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_json_str(&mut json, glob);
+            }
+            json.push_str("]}");
+        }
+        json.push_str("]\n");
+        out.write_all(json.as_bytes()).ignore_broken_pipe()?;
         Ok(())
     }
 }
 
+// Print the original globs even if --type-case-insensitive is enabled
+fn default_types() -> Result<Types> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    Ok(builder.build()?)
+}
+
+// Print the version of PCRE2 library linked to the binary for --pcre2-version
+pub fn print_pcre2_version<W: io::Write>(mut out: W) -> Result<()> {
+    let (major, minor) = grep_pcre2::version();
+    let jit = if grep_pcre2::is_jit_available() {
+        "is"
+    } else {
+        "is not"
+    };
+    writeln!(
+        out,
+        "PCRE2 {}.{} is available (JIT {} available)",
+        major, minor, jit,
+    )
+    .ignore_broken_pipe()?;
+    Ok(())
+}
+
+// Convert a glob to match file names case-insensitively. For example, '*.rs' is converted into '*.[rR][sS]'. Letters
+// in character class are also added in both cases such as '[ch]' to '[cChH]'.
+fn case_insensitive_glob(glob: &str) -> String {
+    let mut converted = String::with_capacity(glob.len() * 2);
+    let mut in_class = false;
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                converted.push(c);
+                if let Some(c) = chars.next() {
+                    converted.push(c);
+                }
+            }
+            '[' if !in_class => {
+                in_class = true;
+                converted.push(c);
+            }
+            ']' if in_class => {
+                in_class = false;
+                converted.push(c);
+            }
+            c if c.is_ascii_alphabetic() => {
+                let (l, u) = (c.to_ascii_lowercase(), c.to_ascii_uppercase());
+                if in_class {
+                    converted.push(l);
+                    converted.push(u);
+                } else {
+                    converted.push('[');
+                    converted.push(l);
+                    converted.push(u);
+                    converted.push(']');
+                }
+            }
+            c => converted.push(c),
+        }
+    }
+    converted
+}
+
+// Walker reports a loop error when following a symbolic link which points to its ancestor directory with --follow
+fn is_loop_error(err: &ignore::Error) -> bool {
+    use ignore::Error;
+    match err {
+        Error::Loop { .. } => true,
+        Error::WithPath { err, .. }
+        | Error::WithDepth { err, .. }
+        | Error::WithLineNumber { err, .. } => is_loop_error(err),
+        _ => false,
+    }
+}
+
+// Check if the path contains a component starting with '.' under the search root. Components of the root path given by
+// user are not checked so that explicitly specified dot files and dot directories are searched
+fn has_dot_component(entry: &DirEntry) -> bool {
+    entry
+        .path()
+        .components()
+        .rev()
+        .take(entry.depth())
+        .any(|c| c.as_os_str().as_encoded_bytes().starts_with(b"."))
+}
+
 pub fn grep<'main, P: Printer + Sync>(
     printer: P,
     pat: &str,
@@ -414,21 +752,60 @@ pub fn grep<'main, P: Printer + Sync>(
         config.build_walker(paths)?
     };
 
+    let ignore_dot = config.ignore_dot;
     let paths = entries.filter_map(|entry| match entry {
         Ok(entry) => {
-            if entry.file_type().is_some_and(|t| t.is_file()) {
+            if ignore_dot && has_dot_component(&entry) {
+                None
+            } else if entry.file_type().is_some_and(|t| t.is_file()) {
                 Some(Ok(entry.into_path()))
             } else {
                 None
             }
         }
+        Err(err) if is_loop_error(&err) => {
+            // Searching the same directory again is meaningless. Skip it and continue the traversal
+            eprintln!(
+                "\x1b[1;93mwarning:\x1b[0m Skipped symbolic link loop: {}",
+                err
+            );
+            None
+        }
         Err(err) => Some(Err(anyhow::Error::new(err))),
     });
 
-    if config.pcre2 {
-        Ripgrep::with_pcre2(pat, config, printer)?.grep(paths)
-    } else {
-        Ripgrep::with_regex(pat, config, printer)?.grep(paths)
+    match config.engine {
+        Engine::Default => Ripgrep::with_regex(pat, config, printer)?.grep(paths),
+        Engine::Pcre2 => Ripgrep::with_pcre2(pat, config, printer)?.grep(paths),
+    }
+}
+
+// Matcher to find matched regions in lines read from stdin. The pattern is compiled with the same config as `grep()` so
+// that case-sensitivity flags like --ignore-case and --smart-case are also applied
+pub struct RegionMatcher(RegexMatcher);
+
+impl RegionMatcher {
+    pub fn new(pat: &str, config: &Config<'_>) -> Result<Self> {
+        Ok(Self(config.build_regex_matcher(pat)?))
+    }
+
+    pub fn find_regions(&self, file: &mut chunk::File) -> Result<()> {
+        let mut lmats = file.line_matches.iter_mut().peekable();
+        for (line, lnum) in LinesInclusive::new(&file.contents) {
+            let Some(lmat) = lmats.next_if(|m| m.line_number == lnum) else {
+                continue;
+            };
+            if !lmat.ranges.is_empty() {
+                continue; // Regions are already known
+            }
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            self.0.find_iter(line.as_bytes(), |m| {
+                lmat.ranges.push((m.start(), m.end()));
+                true
+            })?;
+        }
+        Ok(())
     }
 }
 
@@ -444,7 +821,7 @@ impl<'a> LineRegions<'a> {
     }
 
     fn line_ranges(&mut self, line_len: usize) -> Vec<(usize, usize)> {
-        // Invariant: self.ranges is sorted and not over-wrapped
+        // Invariant: self.ranges is sorted and not over-wrapped. It is ensured by `merge_regions`
         let line_start = self.offset;
         let line_end = line_start + line_len;
 
@@ -491,9 +868,10 @@ impl<'a> LineRegions<'a> {
 }
 
 struct Matches<'a, M: Matcher> {
-    count: &'a Option<Mutex<u64>>,
+    remaining: Option<u64>, // Number of matches which can still be found in the file for --max-count
     path: PathBuf,
     matcher: &'a M,
+    exclude: Option<&'a RegexMatcher>,
     buf: Vec<GrepMatch>,
 }
 
@@ -501,13 +879,22 @@ impl<'a, M: Matcher> Sink for Matches<'a, M> {
     type Error = io::Error;
 
     fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
-        if let Some(count) = &self.count {
-            // Note: AtomicU64 is not available since it does not provide fetch_saturating_sub
-            let mut c = count.lock().unwrap();
-            if *c == 0 {
+        // Excluded lines are not counted for --max-count. A multi-line match is excluded as a whole when any of its lines
+        // matches the exclusion
+        if let Some(exclude) = self.exclude {
+            let excluded = exclude
+                .is_match(mat.bytes())
+                .map_err(|e| io::Error::other(format!("{}", e)))?;
+            if excluded {
+                return Ok(true);
+            }
+        }
+
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
                 return Ok(false);
             }
-            *c -= 1;
+            *remaining -= 1;
         }
 
         let line_number = mat.line_number().unwrap();
@@ -519,39 +906,46 @@ impl<'a, M: Matcher> Sink for Matches<'a, M> {
                 ranges.push((m.start(), m.end()));
                 true
             })
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))?;
+            .map_err(|e| io::Error::other(format!("{}", e)))?;
+        merge_regions(&mut ranges);
         let mut regions = LineRegions::new(&ranges);
 
-        let mut line_number = line_number;
-        for line in mat.lines() {
+        let mut line_end = 0;
+        for (line, line_number) in mat.lines().zip(line_number..) {
+            line_end += line.len();
+            // Tag the line when some match continues across the end of this line so that printers can know which lines
+            // came from one multi-line match
+            let continued = ranges.iter().any(|&(s, e)| s < line_end && line_end < e);
             self.buf.push(GrepMatch {
                 path: path.to_owned(),
                 line_number,
                 ranges: regions.line_ranges(line.len()),
+                continued,
             });
-            line_number += 1;
         }
 
-        Ok(true)
+        // Stop searching the rest of the file as soon as the last match is found
+        Ok(self.remaining != Some(0))
     }
 }
 
 struct Ripgrep<'main, M: Matcher, P: Printer> {
     config: Config<'main>,
     matcher: M,
-    count: Option<Mutex<u64>>,
+    exclude: Option<RegexMatcher>, // Matcher for --exclude-match
+    results: Option<Mutex<usize>>, // Number of files which can still be printed for --max-results
     printer: P,
 }
 
 impl<'main, P: Printer + Sync> Ripgrep<'main, RegexMatcher, P> {
     fn with_regex(pat: &str, config: Config<'main>, printer: P) -> Result<Self> {
-        Ok(Self::new(config.build_regex_matcher(pat)?, config, printer))
+        Self::new(config.build_regex_matcher(pat)?, config, printer)
     }
 }
 
 impl<'main, P: Printer + Sync> Ripgrep<'main, Pcre2Matcher, P> {
     fn with_pcre2(pat: &str, config: Config<'main>, printer: P) -> Result<Self> {
-        Ok(Self::new(config.build_pcre2_matcher(pat)?, config, printer))
+        Self::new(config.build_pcre2_matcher(pat)?, config, printer)
     }
 }
 
@@ -560,34 +954,47 @@ where
     M: Matcher + Sync,
     P: Printer + Sync,
 {
-    fn new(matcher: M, config: Config<'main>, printer: P) -> Self {
-        Self {
-            count: config.max_count.map(Mutex::new),
+    fn new(matcher: M, config: Config<'main>, printer: P) -> Result<Self> {
+        Ok(Self {
+            results: config.max_results.map(Mutex::new),
+            exclude: config.build_exclude_matcher()?,
             matcher,
             printer,
             config,
-        }
+        })
     }
 
     // Return Result<Option<Vec<_>>> instead of Result<Vec<_>> to make the `filter_map` predicate easy
     // in `grep()` method
     fn search(&self, path: PathBuf) -> Result<Option<Vec<GrepMatch>>> {
-        if let Some(count) = &self.count {
-            if *count.lock().unwrap() == 0 {
-                return Ok(None);
+        if self.config.max_count == Some(0) {
+            return Ok(None);
+        }
+        // Searching the remaining files is meaningless when they are not printed. Sorting files needs all of them
+        if self.config.sort.is_none() {
+            if let Some(results) = &self.results {
+                if *results.lock().unwrap() == 0 {
+                    return Ok(None);
+                }
             }
         }
 
+        let start = Instant::now();
         let file = File::open(&path)?;
         let mut searcher = self.config.build_searcher()?;
+        // --max-count limits the number of matches in each file independently
         let mut matches = Matches {
-            count: &self.count,
+            remaining: self.config.max_count,
             path,
             matcher: &self.matcher,
+            exclude: self.exclude.as_ref(),
             buf: vec![],
         };
 
         searcher.search_file(&self.matcher, &file, &mut matches)?;
+        if let Some(threshold) = self.config.warn_slow {
+            warn_slow_file(&matches.path, start, threshold);
+        }
         if matches.buf.is_empty() {
             return Ok(None);
         }
@@ -595,11 +1002,123 @@ where
         Ok(Some(matches.buf))
     }
 
-    fn print_matches(&self, matches: Vec<GrepMatch>) -> Result<bool> {
+    // Find occurrences of the pattern in context lines of the chunks. Matched lines are skipped since their regions were
+    // already found while searching
+    fn find_context_matches(&self, mut file: chunk::File) -> Result<chunk::File> {
+        let mut lmats = file.line_matches.iter().peekable();
+        let mut chunks = file.chunks.iter().peekable();
+        let mut found = vec![];
+        for (line, lnum) in LinesInclusive::new(&file.contents) {
+            while chunks.next_if(|(_, end)| *end < lnum).is_some() {}
+            let Some(&&(start, _)) = chunks.peek() else {
+                break;
+            };
+            while lmats.next_if(|m| m.line_number < lnum).is_some() {}
+            if lnum < start || lmats.next_if(|m| m.line_number == lnum).is_some() {
+                continue;
+            }
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let mut ranges = vec![];
+            self.matcher
+                .find_iter(line.as_bytes(), |m| {
+                    ranges.push((m.start(), m.end()));
+                    true
+                })
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            if !ranges.is_empty() {
+                found.push(chunk::LineMatch::new(lnum, ranges));
+            }
+        }
+        file.context_matches = found.into_boxed_slice();
+        Ok(file)
+    }
+
+    fn chunks(
+        &self,
+        matches: Vec<GrepMatch>,
+    ) -> Result<impl Iterator<Item = Result<chunk::File>> + '_> {
         let (min, max) = (self.config.min_context, self.config.max_context);
+        let files = Files::new(matches.into_iter().map(Ok), min, max, self.config.encoding)?
+            .first_match_only(self.config.first_match_only)
+            .trim_trailing_blank_lines(self.config.trim_trailing_blank_lines)
+            .per_match_context(self.config.per_match_context)
+            .match_bounded_context(self.config.match_bounded_context)
+            .balance_edge_context(self.config.balance_edge_context)
+            .strip_cwd_prefix(self.config.strip_cwd_prefix)
+            .min_matches(self.config.min_matches)
+            .debug_encoding(self.config.debug_encoding);
+        let files = match self.config.context_ratio {
+            Some(ratio) => files.context_ratio(ratio),
+            None => files,
+        };
+        Ok(files.map(|file| match file {
+            Ok(file) if self.config.highlight_context_matches => self.find_context_matches(file),
+            file => file,
+        }))
+    }
+
+    // Take one of the remaining files for --max-results. The number is decremented before printing so that files more
+    // than the limit are never printed from multiple threads
+    fn take_result(&self) -> bool {
+        let Some(results) = &self.results else {
+            return true;
+        };
+        let mut results = results.lock().unwrap();
+        if *results == 0 {
+            return false;
+        }
+        *results -= 1;
+        true
+    }
+
+    fn print_matches(&self, matches: Vec<GrepMatch>) -> Result<bool> {
+        let mut found = false;
+        for file in self.chunks(matches)? {
+            let file = file?;
+            if !self.take_result() {
+                break;
+            }
+            self.printer.print(file)?;
+            found = true;
+        }
+        Ok(found)
+    }
+
+    // Files must be collected before printing since the order of searching files in parallel is not predictable
+    fn grep_sorted<I>(&self, paths: I, key: SortKey) -> Result<bool>
+    where
+        I: Iterator<Item = Result<PathBuf>> + Send,
+    {
+        let search = |path: Result<PathBuf>| match self.search(path?)? {
+            Some(matches) => self.chunks(matches)?.collect::<Result<Vec<_>>>(),
+            None => Ok(vec![]),
+        };
+
+        let mut files = if self.config.no_parallel {
+            let mut files = vec![];
+            for path in paths {
+                files.append(&mut search(path)?);
+            }
+            files
+        } else {
+            paths
+                .par_bridge()
+                .map(search)
+                .try_reduce(Vec::new, |mut a, mut b| {
+                    a.append(&mut b);
+                    Ok(a)
+                })?
+        };
+
+        sort_files(&mut files, key, self.config.sort_reverse);
+
         let mut found = false;
-        for file in Files::new(matches.into_iter().map(Ok), min, max, self.config.encoding)? {
-            self.printer.print(file?)?;
+        for file in files {
+            if !self.take_result() {
+                break;
+            }
+            self.printer.print(file)?;
             found = true;
         }
         Ok(found)
@@ -609,6 +1128,20 @@ where
     where
         I: Iterator<Item = Result<PathBuf>> + Send,
     {
+        if let Some(key) = self.config.sort {
+            return self.grep_sorted(paths, key);
+        }
+
+        if self.config.no_parallel {
+            let mut found = false;
+            for path in paths {
+                if let Some(matches) = self.search(path?)? {
+                    found |= self.print_matches(matches)?;
+                }
+            }
+            return Ok(found);
+        }
+
         paths
             .par_bridge()
             .filter_map(|path| match path {
@@ -624,7 +1157,7 @@ where
 mod tests {
     use super::*;
     use crate::chunk::{File, LineMatch};
-    use crate::test::{read_all_expected_chunks, read_expected_chunks};
+    use crate::test::{read_all_expected_chunks, read_expected_chunks, TempDir};
     use pretty_assertions::assert_eq;
     use regex::Regex;
     use std::ffi::OsStr;
@@ -728,6 +1261,607 @@ mod tests {
         assert_eq!(expected, got);
     }
 
+    #[test]
+    fn test_grep_no_parallel() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)).into_os_string())
+            .collect::<Vec<_>>();
+
+        for sort in [None, Some(SortKey::Path)] {
+            let mut printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.no_parallel(true);
+            if let Some(key) = sort {
+                config.sort(key);
+            }
+            let found = grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(AsRef::as_ref)),
+                config,
+            )
+            .unwrap();
+
+            printer.validate_and_remove_region_ranges();
+            let got = printer.0.into_inner().unwrap();
+
+            // Files are searched in the order of the given paths
+            let mut expected = read_all_expected_chunks(&dir, &inputs);
+            if sort.is_some() {
+                expected.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+
+            assert!(found, "sort={sort:?}");
+            assert_eq!(expected, got, "sort={sort:?}");
+        }
+    }
+
+    #[test]
+    fn test_grep_max_count_per_file() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)).into_os_string())
+            .collect::<Vec<_>>();
+        let expected = read_all_expected_chunks(&dir, &inputs);
+        assert!(
+            expected.iter().filter(|f| f.line_matches.len() > 2).count() > 1,
+            "at least two files should have more than 2 matches: {expected:?}",
+        );
+
+        // Search in parallel several times since the order of searching files is not deterministic
+        for (max, no_parallel) in [(1, false), (2, false), (2, true), (2, false), (2, false)] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.max_count(max).no_parallel(no_parallel);
+            let found = grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(AsRef::as_ref)),
+                config,
+            )
+            .unwrap();
+            let msg = format!("max={max}, no_parallel={no_parallel}");
+            assert!(found, "{msg}");
+
+            let mut got = printer.0.into_inner().unwrap();
+            got.sort_by(|a, b| a.path.cmp(&b.path));
+            let mut want: Vec<_> = expected.iter().filter(|f| !f.chunks.is_empty()).collect();
+            want.sort_by(|a, b| a.path.cmp(&b.path));
+            assert_eq!(got.len(), want.len(), "{msg}");
+
+            // Each file is capped independently. Files searched earlier don't reduce the matches of other files
+            for (got, want) in got.iter().zip(want) {
+                assert_eq!(got.path, want.path, "{msg}");
+                let lnums: Vec<_> = got.line_matches.iter().map(|m| m.line_number).collect();
+                let want: Vec<_> = want
+                    .line_matches
+                    .iter()
+                    .map(|m| m.line_number)
+                    .take(max as usize)
+                    .collect();
+                assert_eq!(lnums, want, "{msg}, path={:?}", got.path);
+            }
+        }
+
+        // No file is searched with zero
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(3, 6);
+        config.max_count(0);
+        let found = grep(
+            &printer,
+            r"\*$",
+            Some(paths.iter().map(AsRef::as_ref)),
+            config,
+        )
+        .unwrap();
+        assert!(!found);
+        assert!(printer.0.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_grep_max_results() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)).into_os_string())
+            .collect::<Vec<_>>();
+        assert!(paths.len() > 2, "{paths:?}");
+
+        for (sort, no_parallel) in [(None, false), (None, true), (Some(SortKey::Path), false)] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.max_results(2).no_parallel(no_parallel);
+            if let Some(key) = sort {
+                config.sort(key);
+            }
+            let found = grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(AsRef::as_ref)),
+                config,
+            )
+            .unwrap();
+
+            let got = printer.0.into_inner().unwrap();
+            let msg = format!("sort={sort:?}, no_parallel={no_parallel}");
+            assert!(found, "{msg}");
+            assert_eq!(got.len(), 2, "{msg}");
+            if sort.is_some() {
+                let mut expected = read_all_expected_chunks(&dir, &inputs);
+                expected.sort_by(|a, b| a.path.cmp(&b.path));
+                let got: Vec<_> = got.iter().map(|f| &f.path).collect();
+                let expected: Vec<_> = expected.iter().take(2).map(|f| &f.path).collect();
+                assert_eq!(got, expected, "{msg}");
+            }
+        }
+
+        // No file is printed with zero
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(3, 6);
+        config.max_results(0);
+        let found = grep(
+            &printer,
+            r"\*$",
+            Some(paths.iter().map(AsRef::as_ref)),
+            config,
+        )
+        .unwrap();
+        assert!(!found);
+        assert!(printer.0.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_grep_sorted_by_path() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)).into_os_string())
+            .collect::<Vec<_>>();
+
+        for reverse in [false, true] {
+            let mut printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.sort(SortKey::Path).sort_reverse(reverse);
+            let found = grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(AsRef::as_ref)),
+                config,
+            )
+            .unwrap();
+            assert!(found);
+
+            printer.validate_and_remove_region_ranges();
+            let got = printer.0.into_inner().unwrap();
+
+            let mut expected = read_all_expected_chunks(&dir, &inputs);
+            expected.sort_by(|a, b| a.path.cmp(&b.path));
+            if reverse {
+                expected.reverse();
+            }
+
+            assert_eq!(expected, got, "reverse={}", reverse);
+        }
+    }
+
+    #[test]
+    fn test_grep_hidden_top_only() {
+        let tmp = TempDir::new("hidden-top-only");
+        let dir = tmp.path();
+        for subdir in [".git", ".hidden", "sub"] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        for file in [
+            ".env",
+            ".git/config",
+            ".hidden/file.txt",
+            "sub/.env",
+            "sub/file.txt",
+            "file.txt",
+        ] {
+            fs::write(dir.join(file), "this line matches\n").unwrap();
+        }
+
+        let mut found = vec![];
+        for (top_only, hidden) in [(true, false), (false, false), (true, true)] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.hidden_top_only(top_only).hidden(hidden);
+            grep(&printer, "matches", Some(iter::once(dir)), config).unwrap();
+            let mut paths: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| fs::canonicalize(f.path).unwrap())
+                .collect();
+            paths.sort();
+            found.push(paths);
+        }
+
+        let canon = fs::canonicalize(dir).unwrap();
+        let expected = |files: &[&str]| -> Vec<PathBuf> {
+            let mut v: Vec<_> = files.iter().map(|f| canon.join(f)).collect();
+            v.sort();
+            v
+        };
+
+        assert_eq!(found[0], expected(&[".env", "file.txt", "sub/file.txt"]));
+        assert_eq!(found[1], expected(&["file.txt", "sub/file.txt"]));
+        assert_eq!(
+            found[2],
+            expected(&[
+                ".env",
+                ".git/config",
+                ".hidden/file.txt",
+                "sub/.env",
+                "sub/file.txt",
+                "file.txt"
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_grep_no_ignore_vcs_and_dot() {
+        let tmp = TempDir::new("no-ignore");
+        let dir = tmp.path();
+        for (file, content) in [
+            (".gitignore", "vcs.txt\n"),
+            (".ignore", "dot.txt\n"),
+            (".rgignore", "rg.txt\n"),
+        ] {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        for file in ["vcs.txt", "dot.txt", "rg.txt", "file.txt"] {
+            fs::write(dir.join(file), "this line matches\n").unwrap();
+        }
+
+        let search = |f: fn(&mut Config<'_>)| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            f(&mut config);
+            grep(&printer, "matches", Some(iter::once(dir)), config).unwrap();
+            let mut names: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let found = [
+            search(|_| {}),
+            search(|c| {
+                c.no_ignore_vcs(true);
+            }),
+            search(|c| {
+                c.no_ignore_dot(true);
+            }),
+            search(|c| {
+                c.no_ignore_vcs(true).no_ignore_dot(true);
+            }),
+            search(|c| {
+                c.no_ignore(true);
+            }),
+        ];
+
+        assert_eq!(found[0], ["file.txt"]);
+        assert_eq!(found[1], ["file.txt", "vcs.txt"]);
+        assert_eq!(found[2], ["dot.txt", "file.txt", "rg.txt"]);
+        assert_eq!(found[3], ["dot.txt", "file.txt", "rg.txt", "vcs.txt"]);
+        assert_eq!(found[4], ["dot.txt", "file.txt", "rg.txt", "vcs.txt"]);
+    }
+
+    #[test]
+    fn test_grep_search_binary_as_text() {
+        let tmp = TempDir::new("binary");
+        let dir = tmp.path();
+        let path = dir.join("binary.bin");
+        fs::write(&path, b"\x00\x01\x02\nthis line matches\x1b[31m\n\x7fELF\n").unwrap();
+
+        let search = |yes: bool| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.search_binary_as_text(yes);
+            grep(
+                &printer,
+                "matches",
+                Some(iter::once(path.as_path())),
+                config,
+            )
+            .unwrap();
+            printer.0.into_inner().unwrap()
+        };
+        let skipped = search(false);
+        let found = search(true);
+
+        assert!(skipped.is_empty(), "{:?}", skipped);
+        assert_eq!(found.len(), 1, "{:?}", found);
+        let lnums: Vec<_> = found[0]
+            .line_matches
+            .iter()
+            .map(|m| m.line_number)
+            .collect();
+        assert_eq!(lnums, [2]);
+    }
+
+    #[test]
+    fn test_grep_highlight_context_matches() {
+        let tmp = TempDir::new("context");
+        let dir = tmp.path();
+        let path = dir.join("context.txt");
+        fs::write(&path, "foo\nthis is match\nbar\nmatch and match\nbaz\n").unwrap();
+
+        let search = |yes: bool| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            // The second occurrence is not a match but appears in the context lines
+            config.max_count(1).highlight_context_matches(yes);
+            grep(&printer, "match", Some(iter::once(path.as_path())), config).unwrap();
+            printer.0.into_inner().unwrap()
+        };
+        let without = search(false);
+        let with = search(true);
+
+        assert_eq!(without.len(), 1, "{:?}", without);
+        assert!(without[0].context_matches.is_empty(), "{:?}", without);
+
+        assert_eq!(with.len(), 1, "{:?}", with);
+        let lnums: Vec<_> = with[0].line_matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(lnums, [2]);
+        let got: Vec<_> = with[0]
+            .context_matches
+            .iter()
+            .map(|m| (m.line_number, m.ranges.clone()))
+            .collect();
+        assert_eq!(got, [(4, vec![(0, 5), (10, 15)])]);
+    }
+
+    #[test]
+    fn test_grep_exclude_match() {
+        let tmp = TempDir::new("exclude");
+        let dir = tmp.path();
+        let path = dir.join("exclude.txt");
+        let mut contents = String::new();
+        for lnum in 1..=30 {
+            let line = match lnum {
+                5 => "match\n",
+                15 => "match but skip this\n",
+                25 => "MATCH and SKIP\n",
+                _ => "line\n",
+            };
+            contents.push_str(line);
+        }
+        fs::write(&path, contents).unwrap();
+
+        let search = |exclude: Option<&'static str>, f: fn(&mut Config<'_>)| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(2, 2);
+            if let Some(pat) = exclude {
+                config.exclude_match(pat);
+            }
+            f(&mut config);
+            grep(
+                &printer,
+                "(?i)match",
+                Some(iter::once(path.as_path())),
+                config,
+            )
+            .unwrap();
+            let files = printer.0.into_inner().unwrap();
+            files
+                .iter()
+                .map(|f| {
+                    let lnums: Vec<_> = f.line_matches.iter().map(|m| m.line_number).collect();
+                    (lnums, f.chunks.to_vec())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            search(None, |_| {}),
+            [(vec![5, 15, 25], vec![(3, 7), (13, 17), (23, 27)])],
+        );
+        // Excluded lines are not even shown as matched lines so their chunks disappear
+        assert_eq!(
+            search(Some("skip"), |_| {}),
+            [(vec![5, 25], vec![(3, 7), (23, 27)])],
+        );
+        assert_eq!(
+            search(Some("skip"), |c| {
+                c.case_insensitive(true);
+            }),
+            [(vec![5], vec![(3, 7)])],
+        );
+        // Excluded lines are not counted for --max-count
+        assert_eq!(
+            search(Some("^match$"), |c| {
+                c.max_count(1);
+            }),
+            [(vec![15], vec![(13, 17)])],
+        );
+        // All matches are excluded
+        assert_eq!(search(Some("(?i)match"), |_| {}), []);
+
+        // A multi-line match is excluded as a whole
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(0, 0);
+        config.multiline(true).exclude_match("skip");
+        grep(
+            &printer,
+            r"line\nmatch",
+            Some(iter::once(path.as_path())),
+            config,
+        )
+        .unwrap();
+        let files = printer.0.into_inner().unwrap();
+        let lnums: Vec<_> = files[0]
+            .line_matches
+            .iter()
+            .map(|m| m.line_number)
+            .collect();
+        assert_eq!(lnums, [4, 5]);
+
+        // Invalid regex
+        let mut config = Config::new(0, 0);
+        config.exclude_match("(");
+        let err = grep(
+            &DummyPrinter::default(),
+            "match",
+            Some(iter::once(path.as_path())),
+            config,
+        )
+        .unwrap_err();
+        let msg = format!("{err}");
+        assert!(msg.contains("--exclude-match"), "{msg:?}");
+    }
+
+    #[test]
+    fn test_grep_ignore_dot() {
+        let tmp = TempDir::new("ignore-dot");
+        let dir = tmp.path();
+        for subdir in [".config/app", "sub"] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        for file in [
+            ".env",
+            ".config/app/settings.txt",
+            "sub/.env",
+            "sub/file.txt",
+            "file.txt",
+        ] {
+            fs::write(dir.join(file), "this line matches\n").unwrap();
+        }
+
+        let search = |root: &Path, ignore_dot: bool| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.hidden(true).ignore_dot(ignore_dot);
+            grep(&printer, "matches", Some(iter::once(root)), config).unwrap();
+            let mut paths: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| fs::canonicalize(f.path).unwrap())
+                .collect();
+            paths.sort();
+            paths
+        };
+        let found = [
+            search(dir, false),
+            search(dir, true),
+            // Dot directory explicitly given as search path is searched
+            search(&dir.join(".config"), true),
+            search(&dir.join(".env"), true),
+        ];
+
+        let canon = fs::canonicalize(dir).unwrap();
+        let expected = |files: &[&str]| -> Vec<PathBuf> {
+            let mut v: Vec<_> = files.iter().map(|f| canon.join(f)).collect();
+            v.sort();
+            v
+        };
+
+        assert_eq!(
+            found[0],
+            expected(&[
+                ".env",
+                ".config/app/settings.txt",
+                "sub/.env",
+                "sub/file.txt",
+                "file.txt"
+            ]),
+        );
+        assert_eq!(found[1], expected(&["sub/file.txt", "file.txt"]));
+        assert_eq!(found[2], expected(&[".config/app/settings.txt"]));
+        assert_eq!(found[3], expected(&[".env"]));
+    }
+
+    #[test]
+    fn test_grep_exts() {
+        let tmp = TempDir::new("exts");
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["a.rs", "b.go", "c.txt", "sub/d.rs", "sub/e.RS"] {
+            fs::write(dir.join(file), "this line matches\n").unwrap();
+        }
+        let canon = fs::canonicalize(dir).unwrap();
+
+        let search = |exts: &[&'static str], globs: &[&'static str], case_insensitive: bool| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config
+                .exts(exts.iter().copied())
+                .globs(globs.iter().copied())
+                .glob_case_insensitive(case_insensitive);
+            grep(&printer, "matches", Some(iter::once(dir)), config).unwrap();
+            let mut paths: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| {
+                    let path = fs::canonicalize(f.path).unwrap();
+                    path.strip_prefix(&canon).unwrap().to_path_buf()
+                })
+                .collect();
+            paths.sort();
+            paths
+        };
+        let found = [
+            search(&["rs"], &[], false),
+            search(&["rs", ".go"], &[], false),
+            search(&["rs"], &[], true),
+            search(&["rs"], &["!a.rs"], false), // --glob takes precedence
+        ];
+
+        let expected = |files: &[&str]| -> Vec<PathBuf> {
+            let mut v: Vec<_> = files.iter().map(|f| Path::new(f).to_path_buf()).collect();
+            v.sort();
+            v
+        };
+        assert_eq!(found[0], expected(&["a.rs", "sub/d.rs"]));
+        assert_eq!(found[1], expected(&["a.rs", "b.go", "sub/d.rs"]));
+        assert_eq!(found[2], expected(&["a.rs", "sub/d.rs", "sub/e.RS"]));
+        assert_eq!(found[3], expected(&["sub/d.rs"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_grep_follow_symlink_loop() {
+        let tmp = TempDir::new("symlink-loop");
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("file.txt"), "this line matches\n").unwrap();
+        std::os::unix::fs::symlink(dir, dir.join("sub").join("loop")).unwrap();
+
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(3, 6);
+        config.follow_symlink(true);
+        let found = grep(&printer, "matches", Some(iter::once(dir)), config);
+        let paths: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| fs::canonicalize(f.path).unwrap())
+            .collect();
+        let expected = fs::canonicalize(dir.join("sub").join("file.txt")).unwrap();
+
+        assert!(found.unwrap());
+        assert_eq!(paths, [expected]);
+    }
+
     #[test]
     fn test_grep_no_match_found() {
         let path = Path::new("testdata").join("chunk").join("single_max.in");
@@ -786,6 +1920,154 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_print_types_json() {
+        let config = Config::default();
+        let mut buf = Vec::new();
+        config.print_types_json(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let defs = json.as_array().unwrap();
+        assert!(!defs.is_empty());
+        for def in defs {
+            let obj = def.as_object().unwrap();
+            let mut keys: Vec<_> = obj.keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            assert_eq!(keys, ["globs", "name"], "{:?}", def);
+            assert!(!def["name"].as_str().unwrap().is_empty(), "{:?}", def);
+            let globs = def["globs"].as_array().unwrap();
+            assert!(!globs.is_empty(), "{:?}", def);
+            assert!(globs.iter().all(|g| g.is_string()), "{:?}", def);
+        }
+
+        let rust = defs.iter().find(|d| d["name"] == "rust").unwrap();
+        assert_eq!(rust["globs"], serde_json::json!(["*.rs"]));
+    }
+
+    #[test]
+    fn test_case_insensitive_glob() {
+        for (input, want) in [
+            ("*.rs", "*.[rR][sS]"),
+            ("Makefile", "[mM][aA][kK][eE][fF][iI][lL][eE]"),
+            ("*.[ch]", "*.[cChH]"),
+            ("*.{c,h}", "*.{[cC],[hH]}"),
+            ("*.py3", "*.[pP][yY]3"),
+            ("\\a*", "\\a*"),
+        ] {
+            assert_eq!(case_insensitive_glob(input), want, "input={:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_build_overrides_with_iglob() {
+        let root = Path::new(".");
+        let matched = |config: &Config<'_>, path: &str| {
+            let overrides = config.build_overrides(root).unwrap();
+            match overrides.matched(path, false) {
+                m if m.is_whitelist() => "include",
+                m if m.is_ignore() => "exclude",
+                _ => "none",
+            }
+        };
+
+        let mut config = Config::default();
+        config
+            .globs(["*.rs"].into_iter())
+            .iglobs(["*.md"].into_iter());
+        for (path, want) in [
+            ("foo.rs", "include"),
+            ("FOO.RS", "exclude"),
+            ("foo.md", "include"),
+            ("FOO.MD", "include"),
+            ("foo.txt", "exclude"),
+        ] {
+            assert_eq!(matched(&config, path), want, "path={path}");
+        }
+
+        // --iglob takes precedence over --glob
+        let mut config = Config::default();
+        config
+            .globs(["*.rs", "!test_*"].into_iter())
+            .iglobs(["TEST_*.RS"].into_iter());
+        for (path, want) in [
+            ("foo.rs", "include"),
+            ("test_foo.rs", "include"),
+            ("test_foo.py", "exclude"),
+        ] {
+            assert_eq!(matched(&config, path), want, "path={path}");
+        }
+
+        // Negated --iglob excludes paths matched by --glob
+        let mut config = Config::default();
+        config
+            .globs(["*.rs"].into_iter())
+            .iglobs(["!VENDOR_*"].into_iter());
+        for (path, want) in [
+            ("foo.rs", "include"),
+            ("vendor_foo.rs", "exclude"),
+            ("Vendor_foo.rs", "exclude"),
+        ] {
+            assert_eq!(matched(&config, path), want, "path={path}");
+        }
+
+        // Globs without --iglob are still case sensitive
+        let mut config = Config::default();
+        config.globs(["*.rs"].into_iter());
+        assert_eq!(matched(&config, "FOO.RS"), "exclude");
+    }
+
+    #[test]
+    fn test_type_case_insensitive() {
+        let mut config = Config::default();
+        config.types(["rust", "make"].into_iter());
+        let types = config.build_types().unwrap();
+        for path in ["foo.rs", "Makefile"] {
+            assert!(types.matched(path, false).is_whitelist(), "{}", path);
+        }
+        for path in ["FOO.RS", "foo.Rs", "MAKEFILE", "foo.py"] {
+            assert!(!types.matched(path, false).is_whitelist(), "{}", path);
+        }
+
+        config.type_case_insensitive(true);
+        let types = config.build_types().unwrap();
+        for path in ["foo.rs", "FOO.RS", "foo.Rs", "Makefile", "MAKEFILE"] {
+            assert!(types.matched(path, false).is_whitelist(), "{}", path);
+        }
+        assert!(!types.matched("foo.py", false).is_whitelist());
+    }
+
+    #[test]
+    fn test_ignore_case_paths() {
+        let mut config = Config::default();
+        config
+            .globs(["*.md"].into_iter())
+            .types(["rust"].into_iter())
+            .ignore_case_paths(true);
+        let overrides = config.build_overrides(Path::new(".")).unwrap();
+        let types = config.build_types().unwrap();
+        for path in ["foo.md", "FOO.MD"] {
+            assert!(overrides.matched(path, false).is_whitelist(), "{}", path);
+        }
+        for path in ["foo.rs", "FOO.RS"] {
+            assert!(types.matched(path, false).is_whitelist(), "{}", path);
+        }
+
+        // Disabling --glob-case-insensitive and --type-case-insensitive explicitly does not matter
+        config
+            .glob_case_insensitive(false)
+            .type_case_insensitive(false);
+        let overrides = config.build_overrides(Path::new(".")).unwrap();
+        let types = config.build_types().unwrap();
+        assert!(overrides.matched("FOO.MD", false).is_whitelist());
+        assert!(types.matched("FOO.RS", false).is_whitelist());
+
+        config.ignore_case_paths(false);
+        let overrides = config.build_overrides(Path::new(".")).unwrap();
+        let types = config.build_types().unwrap();
+        assert!(!overrides.matched("FOO.MD", false).is_whitelist());
+        assert!(!types.matched("FOO.RS", false).is_whitelist());
+    }
+
     fn read_ripgrep_expected(file_name: &str) -> File {
         let path = Path::new("testdata").join("ripgrep").join(file_name);
         let contents = fs::read_to_string(&path).unwrap();
@@ -822,9 +2104,11 @@ mod tests {
                 let line_number = s.next().unwrap().parse().unwrap();
                 let start = s.next().unwrap().parse().unwrap();
                 let end = s.next().unwrap().parse().unwrap();
+                let continued = s.next() == Some("+"); // Optional '+' means the match continues to the next line
                 line_matches.push(LineMatch {
                     line_number,
                     ranges: vec![(start, end)],
+                    continued,
                 })
             }
         }
@@ -887,16 +2171,40 @@ mod tests {
     #[test]
     fn test_pcre2() {
         test_ripgrep_config("pcre2.txt", r"this\sis\stest", |c| {
-            c.pcre2(true);
+            c.engine(Engine::Pcre2);
+        });
+    }
+
+    #[test]
+    fn test_print_pcre2_version() {
+        let mut out = vec![];
+        print_pcre2_version(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let (major, minor) = grep_pcre2::version();
+        let want = format!("PCRE2 {}.{} is available (JIT ", major, minor);
+        assert!(out.starts_with(&want), "{:?}", out);
+        assert!(out.ends_with(" available)\n"), "{:?}", out);
+    }
+
+    #[test]
+    fn test_warn_slow() {
+        // Timing is not predictable. Check that warning slow files does not affect the results
+        test_ripgrep_config("pcre2.txt", r"this\sis\stest", |c| {
+            c.engine(Engine::Pcre2).warn_slow(Duration::ZERO);
         });
     }
 
     #[test]
     fn test_invalid_encoding_error() {
-        Config::new(1, 2)
+        let err = Config::new(1, 2)
             .encoding("foooooooooooo")
             .build_searcher()
             .unwrap_err();
+        let msg = format!("{err}");
+        assert!(
+            msg.contains("Unknown encoding name: \"foooooooooooo\""),
+            "{msg:?}",
+        );
     }
 
     macro_rules! line_regions_tests {
@@ -985,6 +2293,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_region_matcher_case_insensitive() {
+        let contents = "Foo bar\nfoo FOO\nbar\nfOo\r\n";
+        let lmats = vec![
+            LineMatch::lnum(1),
+            LineMatch::lnum(2),
+            LineMatch::lnum(3),
+            LineMatch::lnum(4),
+        ];
+
+        for (pat, f, want) in [
+            (
+                "foo",
+                (|_| {}) as fn(&mut Config),
+                vec![vec![], vec![(0, 3)], vec![], vec![]],
+            ),
+            (
+                "foo",
+                |c| {
+                    c.case_insensitive(true);
+                },
+                vec![vec![(0, 3)], vec![(0, 3), (4, 7)], vec![], vec![(0, 3)]],
+            ),
+            (
+                "foo",
+                |c| {
+                    c.smart_case(true);
+                },
+                vec![vec![(0, 3)], vec![(0, 3), (4, 7)], vec![], vec![(0, 3)]],
+            ),
+            (
+                "Foo",
+                |c| {
+                    c.smart_case(true);
+                },
+                vec![vec![(0, 3)], vec![], vec![], vec![]],
+            ),
+        ] {
+            let mut config = Config::default();
+            f(&mut config);
+            let matcher = RegionMatcher::new(pat, &config).unwrap();
+            let mut file = File::new(
+                PathBuf::from("test.txt"),
+                lmats.clone(),
+                vec![(1, 4)],
+                contents.to_string(),
+            );
+            matcher.find_regions(&mut file).unwrap();
+            let got: Vec<_> = file.line_matches.iter().map(|m| m.ranges.clone()).collect();
+            assert_eq!(got, want, "pattern={:?} config={:?}", pat, config);
+        }
+    }
+
     #[test]
     fn test_parse_size() {
         let tests = &[
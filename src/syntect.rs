@@ -1,14 +1,24 @@
 use crate::broken_pipe::IgnoreBrokenPipe as _;
-use crate::chunk::{File, LinesInclusive};
-use crate::printer::{Printer, PrinterOptions, TermColorSupport, TextWrapMode};
+use crate::chunk::{merge_regions, File, LineMatch, LinesInclusive};
+use crate::printer::{
+    display_path, file_url, strip_path_prefix, Printer, PrinterOptions, RegionStyle,
+    SeparatorStyle, TabMode, TermColorSupport, TextWrapMode,
+};
 use ansi_colours::ansi256_from_rgb;
 use anyhow::Result;
 use flate2::read::ZlibDecoder;
 use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::hash::{Hash, Hasher};
 use std::io::{self, Stdout, StdoutLock, Write};
+use std::iter;
 use std::ops::{Deref, DerefMut};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::str::Chars;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use syntect::highlighting::{
     Color, FontStyle, HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
 };
@@ -44,23 +54,50 @@ impl WriteOnLocked for Stdout {
     }
 }
 
+pub struct MutexWriterGuard<'a, W: Write>(MutexGuard<'a, W>);
+
+impl<'a, W: Write> Write for MutexWriterGuard<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Any writer guarded with mutex can be used as output of `SyntectPrinter` (e.g. `Mutex<Vec<u8>>`, `Mutex<fs::File>`).
+// The lock is taken per file so that files printed from multiple threads are not mixed.
+impl<W: Write> WriteOnLocked for Mutex<W> {
+    type Locked<'a>
+        = MutexWriterGuard<'a, W>
+    where
+        W: 'a;
+    fn lock(&self) -> Self::Locked<'_> {
+        // Output written before a panic in other thread is still valid since each file is written at once
+        MutexWriterGuard(Mutex::lock(self).unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
 pub fn list_themes<W: Write>(out: W, opts: &PrinterOptions<'_>) -> Result<()> {
     let syntaxes = load_syntax_set()?;
     list_themes_with_syntaxes(out, opts, &syntaxes)
 }
 
+// All themes of bat and syntect sorted by their names
+fn sorted_themes() -> Result<Vec<(String, Theme)>> {
+    let mut m = load_bat_themes()?.themes;
+    m.extend(ThemeSet::load_defaults().themes);
+    let mut v: Vec<_> = m.into_iter().collect();
+    v.sort_by(|l, r| l.0.cmp(&r.0));
+    Ok(v)
+}
+
 fn list_themes_with_syntaxes<W: Write>(
     mut out: W,
     opts: &PrinterOptions<'_>,
     syntaxes: &SyntaxSet,
 ) -> Result<()> {
-    let themes = {
-        let mut m = load_bat_themes()?.themes;
-        m.extend(ThemeSet::load_defaults().themes);
-        let mut v: Vec<_> = m.into_iter().collect();
-        v.sort_by(|l, r| l.0.cmp(&r.0));
-        v
-    };
+    let themes = sorted_themes()?;
 
     let syntax = syntaxes.find_syntax_by_name("Rust").unwrap();
     let sample_file = File::sample_file();
@@ -84,6 +121,36 @@ fn list_themes_with_syntaxes<W: Write>(
     Ok(())
 }
 
+// Print only the theme names one per line for --theme-names. Samples are not rendered unlike --list-themes
+pub fn list_theme_names<W: Write>(mut out: W) -> Result<()> {
+    sorted_themes()?
+        .iter()
+        .try_for_each(|(name, _)| writeln!(out, "{}", name))
+        .ignore_broken_pipe()?;
+    Ok(())
+}
+
+// Print only the code of the sample file highlighted with the theme for --theme-preview
+pub fn preview_theme<W: Write>(out: W, name: &str, opts: &PrinterOptions<'_>) -> Result<()> {
+    let syntaxes = load_syntax_set()?;
+    preview_theme_with_syntaxes(out, name, opts, &syntaxes)
+}
+
+fn preview_theme_with_syntaxes<W: Write>(
+    mut out: W,
+    name: &str,
+    opts: &PrinterOptions<'_>,
+    syntaxes: &SyntaxSet,
+) -> Result<()> {
+    let themes = load_themes(Some(name))?;
+    let theme = &themes.themes[name];
+    let syntax = syntaxes.find_syntax_by_name("Rust").unwrap();
+    let sample_file = File::sample_file();
+    let mut drawer = Drawer::new(&mut out, opts, theme, &sample_file.chunks);
+    let hl = LineHighlighter::new(syntax, theme, syntaxes);
+    drawer.draw_file(&sample_file, hl).ignore_broken_pipe()
+}
+
 // Use u64::log10 once it is stabilized: https://github.com/rust-lang/rust/issues/70887
 #[inline]
 fn num_digits(n: u64) -> u16 {
@@ -107,6 +174,314 @@ impl<'line> Token<'line> {
     }
 }
 
+// Marker which replaces newlines in a multi-line match with --replace-newline
+const NEWLINE_MARKER: &str = "\u{2424}";
+
+// Unicode control picture of C0 control character or DEL such as '␍' for '\r'. Tab is not a target since it is handled
+// by --tab option
+fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\t' => None,
+        '\0'..='\u{1f}' => char::from_u32(0x2400 + c as u32),
+        '\u{7f}' => Some('\u{2421}'),
+        _ => None,
+    }
+}
+
+// Append tokens and regions of the line to the joined line. When the line is continued to the next line, its newline is
+// replaced with the marker. Regions including the newline include the marker instead.
+fn join_line<'line>(
+    joined_tokens: &mut Vec<Token<'line>>,
+    joined_regions: &mut Vec<(usize, usize)>,
+    mut tokens: Vec<Token<'line>>,
+    regions: &[(usize, usize)],
+    continued: bool,
+) {
+    let base: usize = joined_tokens.iter().map(|t| t.text.len()).sum();
+    let mut marker_len = 0;
+    if continued {
+        if let Some(tok) = tokens.last_mut() {
+            let style = tok.style;
+            tok.chomp();
+            if tok.text.is_empty() {
+                tokens.pop();
+            }
+            tokens.push(Token {
+                style,
+                text: NEWLINE_MARKER,
+            });
+            marker_len = NEWLINE_MARKER.len();
+        }
+    }
+    let len = tokens.iter().map(|t| t.text.len()).sum::<usize>() - marker_len;
+
+    for &(s, e) in regions {
+        let s = base + cmp::min(s, len);
+        let e = if continued && e > len {
+            base + len + marker_len
+        } else {
+            base + cmp::min(e, len)
+        };
+        if s < e {
+            joined_regions.push((s, e));
+        }
+    }
+    joined_tokens.extend(tokens);
+}
+
+// Characters of --minimap from the lowest density to the highest
+const MINIMAP_CHARS: [char; 5] = [' ', '\u{2591}', '\u{2592}', '\u{2593}', '\u{2588}'];
+const MINIMAP_ASCII_CHARS: [char; 5] = [' ', '.', ':', '+', '#'];
+
+// Density of matched lines for --minimap. The whole file is divided into `rows` regions like a scrollbar and each
+// character shows the ratio of matched lines in the region. The ratio is rounded up so that even one matched line in a
+// large region is visible.
+fn minimap_glyphs(lmats: &[LineMatch], lines: u64, rows: u64, chars: &[char; 5]) -> Vec<char> {
+    let lines = cmp::max(lines, 1);
+    let rows = cmp::max(rows, 1);
+    let levels = chars.len() as u64 - 1;
+    (0..rows)
+        .map(|row| {
+            let start = row * lines / rows + 1;
+            let end = cmp::max((row + 1) * lines / rows, start);
+            let matched = lmats
+                .iter()
+                .filter(|m| start <= m.line_number && m.line_number <= end)
+                .count() as u64;
+            let level = cmp::min((matched * levels).div_ceil(end - start + 1), levels);
+            chars[level as usize]
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Minimap {
+    glyphs: Vec<char>,
+    row: usize, // Index of the next code line to draw
+}
+
+// Convert seconds since the Unix epoch to the date in the proleptic Gregorian calendar
+fn civil_date(secs: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = secs.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Parse the timezone offset like "+0900" in the output of `git blame` as seconds
+fn parse_tz_offset(tz: &str) -> Option<i64> {
+    let (sign, hhmm) = match tz.split_at_checked(1)? {
+        ("+", hhmm) => (1, hhmm),
+        ("-", hhmm) => (-1, hhmm),
+        _ => return None,
+    };
+    let (hh, mm) = hhmm.split_at_checked(2)?;
+    Some(sign * (hh.parse::<i64>().ok()? * 3600 + mm.parse::<i64>().ok()? * 60))
+}
+
+#[derive(Default, Clone, Copy)]
+struct BlameCommit<'a> {
+    author: &'a str,
+    time: i64,
+    tz: i64, // Offset in seconds
+}
+
+// Annotations of `git blame` for matched lines drawn at the right margin with --blame. Each annotation consists of the
+// short commit hash, the author date, and the author name like "1a2b3c4 2024-05-06 rhysd"
+struct Blame {
+    lines: HashMap<u64, String>,
+    width: usize,         // Max width of the annotations
+    pending: Option<u64>, // Line number of the annotation drawn at the end of the next row
+}
+
+impl Blame {
+    // Run `git blame` for the matched lines of the file. `None` is returned when the file is not tracked by Git or
+    // `git` command is not available. In the case, the file is printed without the margin
+    fn load(path: &Path, lmats: &[LineMatch]) -> Option<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(dir).args(["blame", "--porcelain"]);
+        // Consecutive matched lines are blamed as one range
+        let mut lnums = lmats.iter().map(|m| m.line_number).peekable();
+        while let Some(start) = lnums.next() {
+            let mut end = start;
+            while let Some(n) = lnums.next_if(|&n| n <= end + 1) {
+                end = cmp::max(end, n);
+            }
+            cmd.arg("-L").arg(format!("{},{}", start, end));
+        }
+        cmd.arg("--").arg(path.file_name()?);
+
+        let output = cmd
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let blame = Self::parse(&String::from_utf8_lossy(&output.stdout));
+        (!blame.lines.is_empty()).then_some(blame)
+    }
+
+    // Parse the output of `git blame --porcelain`. The header of each line starts with the commit hash followed by the
+    // line numbers. Author information follows only at the first line of each commit
+    fn parse(porcelain: &str) -> Self {
+        let mut commits: HashMap<&str, BlameCommit<'_>> = HashMap::new();
+        let mut lines = HashMap::new();
+        let mut current: Option<(&str, u64)> = None; // Commit hash and line number of the current entry
+        for line in porcelain.lines() {
+            if line.starts_with('\t') {
+                // The contents of the line ends the entry
+                if let Some((hash, lnum)) = current.take() {
+                    let BlameCommit { author, time, tz } =
+                        commits.get(hash).copied().unwrap_or_default();
+                    let (y, m, d) = civil_date(time + tz);
+                    let short = hash.get(..7).unwrap_or(hash);
+                    let note = format!("{short} {y:04}-{m:02}-{d:02} {author}");
+                    lines.insert(lnum, note);
+                }
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            if key.len() == 40 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+                // "{hash} {original line} {final line} {lines in group}"
+                let lnum = value.split(' ').nth(1).and_then(|n| n.parse().ok());
+                current = lnum.map(|n| (key, n));
+                commits.entry(key).or_default();
+                continue;
+            }
+            let Some((hash, _)) = current else {
+                continue;
+            };
+            let commit = commits.entry(hash).or_default();
+            match key {
+                "author" => commit.author = value,
+                "author-time" => commit.time = value.parse().unwrap_or(0),
+                "author-tz" => commit.tz = parse_tz_offset(value).unwrap_or(0),
+                _ => {}
+            }
+        }
+        Self::new(lines)
+    }
+
+    fn new(lines: HashMap<u64, String>) -> Self {
+        let width = lines.values().map(|l| l.width_cjk()).max().unwrap_or(0);
+        Self {
+            lines,
+            width,
+            pending: None,
+        }
+    }
+}
+
+// Ranges of lines folded by --context-fold. Non-matched lines between two matches in the same chunk are folded when they
+// are more than `keep` lines away from both matches. Only one line is never folded since the marker occupies one line.
+fn fold_ranges(file: &File, keep: u64) -> Vec<(u64, u64)> {
+    let mut folds = vec![];
+    for &(start, end) in file.chunks.iter() {
+        let lnums = file
+            .line_matches
+            .iter()
+            .map(|m| m.line_number)
+            .filter(|n| start <= *n && *n <= end);
+        for (prev, next) in lnums.clone().zip(lnums.skip(1)) {
+            let (s, e) = (
+                prev.saturating_add(keep.saturating_add(1)),
+                next.saturating_sub(keep.saturating_add(1)),
+            );
+            if s < e {
+                folds.push((s, e));
+            }
+        }
+    }
+    folds
+}
+
+// Chunks and line matches after removing the chunks whose matched lines are identical to the ones of a previous chunk,
+// and the number of removed chunks for each remaining chunk. Lines are compared by their exact text
+struct DedupChunks {
+    chunks: Vec<(u64, u64)>,
+    line_matches: Vec<LineMatch>,
+    identical: Vec<usize>,
+}
+
+impl DedupChunks {
+    fn new(file: &File) -> Self {
+        let lines: Vec<_> = file.contents.lines().collect();
+        let mut seen: HashMap<Vec<&str>, usize> = HashMap::new();
+        let mut dedup = Self {
+            chunks: vec![],
+            line_matches: vec![],
+            identical: vec![],
+        };
+        for &(start, end) in file.chunks.iter() {
+            let lmats = file
+                .line_matches
+                .iter()
+                .filter(|m| start <= m.line_number && m.line_number <= end);
+            let key: Vec<_> = lmats
+                .clone()
+                .map(|m| lines.get(m.line_number as usize - 1).copied().unwrap_or(""))
+                .collect();
+            if let Some(&idx) = seen.get(&key) {
+                dedup.identical[idx] += 1;
+                continue;
+            }
+            seen.insert(key, dedup.chunks.len());
+            dedup.chunks.push((start, end));
+            dedup.line_matches.extend(lmats.cloned());
+            dedup.identical.push(0);
+        }
+        dedup
+    }
+}
+
+// Expand each region outward to the boundaries of the words (runs of alphanumeric characters and '_') at its edges.
+// When a region spans multiple words, only its start and end are expanded so the words and the non-word characters
+// between them are all included. Regions overlapping after the expansion are merged into one.
+fn expand_regions_to_words(line: &str, regions: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    fn is_word_char(c: &char) -> bool {
+        c.is_alphanumeric() || *c == '_'
+    }
+
+    let mut expanded: Vec<(usize, usize)> = Vec::with_capacity(regions.len());
+    for &(s, e) in regions {
+        let (s, e) = match (line.get(..s), line.get(e..)) {
+            (Some(before), Some(after)) if s < e => {
+                let s = s - before
+                    .chars()
+                    .rev()
+                    .take_while(is_word_char)
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+                let e = e + after
+                    .chars()
+                    .take_while(is_word_char)
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+                (s, e)
+            }
+            _ => (s, e), // Empty region or region out of the line
+        };
+        match expanded.last_mut() {
+            Some((_, last_end)) if s < *last_end => *last_end = cmp::max(*last_end, e),
+            _ => expanded.push((s, e)),
+        }
+    }
+    expanded
+}
+
 #[derive(Clone, Copy)]
 enum RegionBoundary {
     Start,
@@ -342,6 +717,8 @@ struct Canvas<W: Write> {
     palette: Palette,
     current_fg: Option<Color>,
     current_bg: Option<Color>,
+    reverse_region: bool,
+    reversed: bool, // Reverse video attribute is currently enabled
 }
 
 impl<W: Write> Deref for Canvas<W> {
@@ -371,6 +748,8 @@ impl<W: Write> Canvas<W> {
             palette,
             current_fg: None,
             current_bg: None,
+            reverse_region: opts.region_style == RegionStyle::Reverse,
+            reversed: false,
         }
     }
 
@@ -385,6 +764,7 @@ impl<W: Write> Canvas<W> {
         writeln!(self.out, "\x1b[0m")?; // Reset on newline to ensure to reset color
         self.current_fg = None;
         self.current_bg = None;
+        self.reversed = false;
         Ok(())
     }
 
@@ -498,8 +878,32 @@ impl<W: Write> Canvas<W> {
     }
 
     fn set_region_color(&mut self) -> io::Result<()> {
-        self.set_fg(self.palette.region_fg)?;
-        self.set_bg(self.palette.region_bg)
+        if !self.reverse_region {
+            self.set_fg(self.palette.region_fg)?;
+            return self.set_bg(self.palette.region_bg);
+        }
+        if !self.reversed {
+            self.out.write_all(b"\x1b[7m")?;
+            self.reversed = true;
+        }
+        Ok(())
+    }
+
+    // Only reverse video attribute needs to be unset explicitly. Colors of region are overwritten by the next colors
+    fn unset_region_color(&mut self) -> io::Result<()> {
+        if self.reversed {
+            self.out.write_all(b"\x1b[27m")?;
+            self.reversed = false;
+        }
+        Ok(())
+    }
+
+    fn reset_color(&mut self) -> io::Result<()> {
+        self.out.write_all(b"\x1b[0m")?;
+        self.current_fg = None;
+        self.current_bg = None;
+        self.reversed = false;
+        Ok(())
     }
 
     fn set_gutter_color(&mut self) -> io::Result<()> {
@@ -538,33 +942,48 @@ impl<W: Write> Canvas<W> {
         self.draw_sample_row(&[("MatchRegionFG:", self.palette.region_fg),  ("MatchRegionBG:", self.palette.region_bg)])?;
         self.draw_sample_row(&[("GutterFG:     ", self.palette.gutter_fg)])
     }
+
+    #[rustfmt::skip]
+    fn draw_legend(&mut self) -> io::Result<()> {
+        self.draw_sample_row(&[("Matched line:  ", self.palette.match_bg),  ("Line number:", self.palette.match_lnum_fg)])?;
+        self.draw_sample_row(&[("Matched region:", self.palette.region_bg), ("Gutter:     ", self.palette.gutter_fg)])
+    }
 }
 
 struct LineChars<'a> {
     horizontal: &'a str,
     vertical: &'a str,
     vertical_and_right: &'a str,
+    vertical_and_left: &'a str,
     down_and_horizontal: &'a str,
     up_and_horizontal: &'a str,
     dashed_horizontal: &'a str,
+    indent_guide: &'a str,
+    wrap_marker: &'a str,
 }
 
 const UNICODE_LINE_CHARS: LineChars<'static> = LineChars {
     horizontal: "─",
     vertical: "│",
     vertical_and_right: "├",
+    vertical_and_left: "┤",
     down_and_horizontal: "┬",
     up_and_horizontal: "┴",
     dashed_horizontal: "╶",
+    indent_guide: "│",
+    wrap_marker: "↪",
 };
 
 const ASCII_LINE_CHARS: LineChars<'static> = LineChars {
     horizontal: "-",
     vertical: "|",
     vertical_and_right: "|",
+    vertical_and_left: "|",
     down_and_horizontal: "-",
     up_and_horizontal: "-",
     dashed_horizontal: "-",
+    indent_guide: "|",
+    wrap_marker: ">",
 };
 
 // Note: More flexible version of syntect::easy::HighlightLines for our use case
@@ -610,85 +1029,267 @@ impl<'a> LineHighlighter<'a> {
 struct Drawer<'file, W: Write> {
     grid: bool,
     term_width: u16,
+    max_chunk_width: Option<u16>,
     lnum_width: u16,
+    compact_gutter: bool,
+    line_numbers_right: Option<Option<(u64, bool)>>, // Line number and whether it is matched, drawn at the end of the row
     first_only: bool,
+    last_only: bool,
+    matches_only: bool,
+    replace_newline: bool,
+    highlight_word: bool,
+    first_region_per_line: bool,
+    monospace_width: bool,
+    context_fold: Option<u64>,
+    line_numbers_on_match_only: bool,
+    dedup_lines: bool,
+    match_highlight: bool,
+    show_control_chars: bool,
+    minimap: Option<Minimap>,
+    blame: Option<Blame>,
+    chunk_headers: bool,
+    separator_style: SeparatorStyle,
+    separator_width: Option<u16>,
+    space_after_match: bool,
+    match_marker: Option<&'file str>,
+    indent_guides: Option<usize>, // Width of one indent level
+    wrap_marker: bool,
+    strip_prefix: Option<&'file Path>,
+    path_separator: Option<char>,
+    link: Option<String>, // URL of hyperlink on the header
     wrap: bool,
-    tab_width: u16,
+    tab: TabMode,
     chars: LineChars<'file>,
     canvas: Canvas<W>,
 }
 
 impl<'file, W: Write> Drawer<'file, W> {
-    fn new(out: W, opts: &PrinterOptions<'_>, theme: &'file Theme, chunks: &[(u64, u64)]) -> Self {
+    fn new(
+        out: W,
+        opts: &PrinterOptions<'file>,
+        theme: &'file Theme,
+        chunks: &[(u64, u64)],
+    ) -> Self {
         let last_lnum = chunks.last().map(|(_, e)| *e).unwrap_or(0);
         let mut lnum_width = num_digits(last_lnum);
-        if chunks.len() > 1 {
+        if chunks.len() > 1 && !opts.matches_only {
             lnum_width = cmp::max(lnum_width, 3); // Consider '...' in gutter
         }
+        if opts.match_marker.is_some() {
+            lnum_width += 1; // Reserve the column for the marker before line numbers
+        }
 
-        let chars = if opts.ascii_lines {
+        let mut chars = if opts.ascii_lines {
             ASCII_LINE_CHARS
         } else {
             UNICODE_LINE_CHARS
         };
+        if let Some(c) = opts.gutter_char {
+            chars.vertical = c;
+        }
 
         Drawer {
             grid: opts.grid,
             term_width: opts.term_width,
+            max_chunk_width: opts.max_chunk_width,
             lnum_width,
+            compact_gutter: opts.compact_gutter,
+            line_numbers_right: opts.line_numbers_right.then_some(None),
             wrap: opts.text_wrap == TextWrapMode::Char,
-            tab_width: opts.tab_width as u16,
+            tab: opts.tab,
             first_only: opts.first_only,
+            last_only: opts.last_only,
+            matches_only: opts.matches_only,
+            replace_newline: opts.replace_newline,
+            highlight_word: opts.highlight_word,
+            first_region_per_line: opts.first_region_per_line,
+            monospace_width: opts.monospace_width,
+            context_fold: opts.context_fold,
+            line_numbers_on_match_only: opts.line_numbers_on_match_only,
+            dedup_lines: opts.dedup_lines,
+            match_highlight: opts.match_highlight,
+            show_control_chars: opts.show_control_chars,
+            minimap: opts.minimap.then(Minimap::default),
+            blame: None,
+            chunk_headers: opts.chunk_headers,
+            separator_style: opts.separator_style,
+            separator_width: opts.separator_width,
+            space_after_match: opts.space_after_match,
+            match_marker: opts.match_marker,
+            indent_guides: opts.indent_guides.then(|| match opts.tab.width() {
+                0 => 4, // Only spaces are considered when tabs are kept
+                w => w,
+            }),
+            wrap_marker: opts.wrap_marker,
+            strip_prefix: opts.strip_prefix,
+            path_separator: opts.path_separator,
+            link: None,
             chars,
             canvas: Canvas::new(out, opts, theme),
         }
     }
 
+    // With --compact-gutter, the margins around line numbers are dropped. The gutter is "12│" with grid and "12 " without
+    // grid while it is " 12 │ " and " 12 " respectively by default
     #[inline]
     fn gutter_width(&self) -> u16 {
-        if self.grid {
+        if self.compact_gutter {
+            self.lnum_width + 1
+        } else if self.grid {
             self.lnum_width + 4
         } else {
             self.lnum_width + 2
         }
     }
 
+    // Width of code after the gutter. It is narrower than the terminal when --max-chunk-width is set. In the case, the
+    // rest of the line is left blank as if the terminal were narrower, but header and grid lines are not shortened
+    fn body_width(&self) -> usize {
+        let mut width = self.width_before_blame();
+        if let Some(w) = self.blame_width() {
+            width = width.saturating_sub(w as u16 + 1); // Space and annotation of --blame at the right margin
+        }
+        match self.max_chunk_width {
+            Some(max) => cmp::min(width, max) as usize,
+            None => width as usize,
+        }
+    }
+
+    // Width after the gutter excluding the minimap at the right margin
+    fn width_before_blame(&self) -> u16 {
+        let width = self.term_width.saturating_sub(self.gutter_width());
+        if self.minimap.is_some() {
+            width.saturating_sub(2) // Space and character of minimap at the right margin
+        } else {
+            width
+        }
+    }
+
+    // Width of annotations of --blame. Long annotations are cut so that they occupy at most 1/3 of the width after the
+    // gutter and the minimap
+    fn blame_width(&self) -> Option<usize> {
+        let blame = self.blame.as_ref()?;
+        let max = self.width_before_blame() as usize / 3;
+        Some(cmp::min(blame.width, max))
+    }
+
     fn draw_horizontal_line(&mut self, sep: &str) -> io::Result<()> {
         self.canvas.set_gutter_color()?;
-        let gutter_width = self.gutter_width();
-        for _ in 0..gutter_width - 2 {
+        // Number of columns before the vertical line of the grid
+        let left = if self.line_numbers_right.is_some() {
+            self.term_width - self.gutter_width() + self.gutter_margin() as u16
+        } else if self.compact_gutter {
+            self.lnum_width
+        } else {
+            self.lnum_width + 2
+        };
+        for _ in 0..left {
             self.canvas.write_all(self.chars.horizontal.as_bytes())?;
         }
         self.canvas.write_all(sep.as_bytes())?;
-        for _ in 0..self.term_width - gutter_width + 1 {
+        for _ in 0..self.term_width - left - 1 {
             self.canvas.write_all(self.chars.horizontal.as_bytes())?;
         }
         self.canvas.draw_newline()
     }
 
+    // Width of margins around line numbers in the gutter
+    fn gutter_margin(&self) -> usize {
+        if self.compact_gutter {
+            0
+        } else {
+            1
+        }
+    }
+
+    // Rows are filled with spaces to the right edge when something is drawn after the text
+    fn fills_row(&self) -> bool {
+        self.canvas.has_background || self.line_numbers_right.is_some()
+    }
+
+    // Draw the line number with the leading margin. The width is `lnum_width + margin`
+    fn draw_lnum_text(&mut self, lnum: u64, matched: bool, margin: usize) -> io::Result<()> {
+        if !matched && self.line_numbers_on_match_only {
+            return self.canvas.draw_spaces(self.lnum_width as usize + margin);
+        }
+        let mut padding = (self.lnum_width - num_digits(lnum)) as usize;
+        if let Some(marker) = self.match_marker {
+            // The marker is put at the first column so that it is aligned regardless of the width of line numbers
+            let marker = if matched { marker } else { " " };
+            self.canvas.write_all(marker.as_bytes())?;
+            padding -= 1;
+        }
+        self.canvas.draw_spaces(padding + margin)?;
+        write!(self.canvas, "{}", lnum)
+    }
+
     fn draw_line_number(&mut self, lnum: u64, matched: bool) -> io::Result<()> {
-        if matched {
+        if let Some(pending) = &mut self.line_numbers_right {
+            *pending = Some((lnum, matched)); // Drawn at the end of the first row by `draw_right_gutter`
+            return Ok(());
+        }
+        let highlight = matched && self.match_highlight;
+        if highlight {
             self.canvas.set_match_lnum_color()?;
         } else {
             self.canvas.set_gutter_color()?;
         }
-        let width = num_digits(lnum);
-        self.canvas
-            .draw_spaces((self.lnum_width - width) as usize)?;
-        write!(self.canvas, " {}", lnum)?;
+        let margin = self.gutter_margin();
+        self.draw_lnum_text(lnum, matched, margin)?;
         if self.grid {
-            if matched {
+            if highlight {
                 self.canvas.set_gutter_color()?;
             }
-            write!(self.canvas, " {}", self.chars.vertical)?;
+            self.canvas.draw_spaces(margin)?;
+            self.canvas.write_all(self.chars.vertical.as_bytes())?;
+            if self.compact_gutter {
+                return Ok(()); // No space between the grid line and code
+            }
         }
         self.canvas.set_default_bg()?;
         self.canvas.write_all(b" ")?;
         Ok(()) // Do not reset color because another color text will follow
     }
 
+    // Draw the gutter at the end of the row for --line-numbers-right. It is " │ 12 " with grid and " 12 " without grid.
+    // The line number is drawn only at the first row of the line so rows such as wrapped rows have no line number
+    fn draw_right_gutter(&mut self) -> io::Result<()> {
+        let Some(pending) = &mut self.line_numbers_right else {
+            return Ok(());
+        };
+        let lnum = pending.take();
+        let margin = self.gutter_margin();
+        self.canvas.set_gutter_color()?;
+        // Without grid, a space is necessary to separate line numbers from code even if the gutter is compact
+        let lead = if self.grid {
+            self.canvas.draw_spaces(margin)?;
+            self.canvas.write_all(self.chars.vertical.as_bytes())?;
+            margin
+        } else {
+            1
+        };
+        match lnum {
+            Some((lnum, matched)) => {
+                if matched && self.match_highlight {
+                    self.canvas.set_match_lnum_color()?;
+                }
+                self.draw_lnum_text(lnum, matched, lead)?;
+            }
+            None => self.canvas.draw_spaces(self.lnum_width as usize + lead)?,
+        }
+        self.canvas.draw_spaces(margin)
+    }
+
     fn draw_wrapping_gutter(&mut self) -> io::Result<()> {
+        if self.line_numbers_right.is_some() {
+            return Ok(()); // Drawn at the end of the row by `draw_right_gutter`
+        }
         self.canvas.set_gutter_color()?;
+        if self.compact_gutter {
+            self.canvas.draw_spaces(self.lnum_width as usize)?;
+            let c = if self.grid { self.chars.vertical } else { " " };
+            return self.canvas.write_all(c.as_bytes());
+        }
         self.canvas.draw_spaces(self.lnum_width as usize + 2)?;
         if self.grid {
             write!(self.canvas, "{} ", self.chars.vertical)?;
@@ -697,48 +1298,277 @@ impl<'file, W: Write> Drawer<'file, W> {
     }
 
     fn draw_separator_line(&mut self) -> io::Result<()> {
+        if self.separator_style == SeparatorStyle::Blank {
+            return self.draw_note_line("");
+        }
+        if self.line_numbers_right.is_some() {
+            return self.draw_right_separator_line();
+        }
+
         self.canvas.set_gutter_color()?;
         // + 1 for left margin and - 3 for length of "..."
-        let left_margin = self.lnum_width + 1 - 3;
+        let left_margin = if self.compact_gutter {
+            self.lnum_width - 3
+        } else {
+            self.lnum_width + 1 - 3
+        };
         self.canvas.draw_spaces(left_margin as usize)?;
         let w = if self.grid {
-            write!(self.canvas, "... {}", self.chars.vertical_and_right)?;
-            5
+            let c = match self.separator_style {
+                SeparatorStyle::Dots => self.chars.vertical,
+                _ => self.chars.vertical_and_right,
+            };
+            if self.compact_gutter {
+                write!(self.canvas, "...{}", c)?;
+                4
+            } else {
+                write!(self.canvas, "... {}", c)?;
+                5
+            }
         } else {
             write!(self.canvas, "...")?;
             3
         };
         self.canvas.set_default_bg()?;
-        let body_width = self.term_width - left_margin - w; // This crashes when terminal width is smaller than gutter
-        for _ in 0..body_width {
+        if self.separator_style == SeparatorStyle::Dashed {
+            let mut body_width = self.term_width - left_margin - w; // This crashes when terminal width is smaller than gutter
+            if let Some(max) = self.separator_width {
+                body_width = cmp::min(body_width, max);
+            }
+            for _ in 0..body_width {
+                self.canvas
+                    .write_all(self.chars.dashed_horizontal.as_bytes())?;
+            }
+        }
+        self.canvas.draw_newline()
+    }
+
+    // Separator line for --line-numbers-right. The dashed rule is drawn in the code area and '...' is drawn in the gutter
+    // at the right
+    fn draw_right_separator_line(&mut self) -> io::Result<()> {
+        self.canvas.set_gutter_color()?;
+        let body_width = (self.term_width - self.gutter_width()) as usize;
+        let mut width = 0;
+        if self.separator_style == SeparatorStyle::Dashed {
+            width = match self.separator_width {
+                Some(max) => cmp::min(body_width, max as usize),
+                None => body_width,
+            };
+            for _ in 0..width {
+                self.canvas
+                    .write_all(self.chars.dashed_horizontal.as_bytes())?;
+            }
+        }
+        self.canvas.draw_spaces(body_width - width)?;
+        let margin = self.gutter_margin();
+        let lead = if self.grid {
+            let c = match self.separator_style {
+                SeparatorStyle::Dots => self.chars.vertical,
+                _ => self.chars.vertical_and_left,
+            };
+            self.canvas.draw_spaces(margin)?;
+            self.canvas.write_all(c.as_bytes())?;
+            margin
+        } else {
+            1
+        };
+        // - 3 for length of "..."
+        self.canvas
+            .draw_spaces(lead + self.lnum_width as usize - 3)?;
+        self.canvas.write_all(b"...")?;
+        self.canvas.draw_spaces(margin)?;
+        self.canvas.draw_newline()
+    }
+
+    // Draw a header line with the range of lines of the chunk for --chunk-headers. This is drawn instead of the separator
+    // line between chunks
+    fn draw_chunk_header(&mut self, start: u64, end: u64) -> io::Result<()> {
+        if self.line_numbers_right.is_some() {
+            return self.draw_right_chunk_header(start, end);
+        }
+        self.canvas.set_gutter_color()?;
+        let left_margin = if self.compact_gutter {
+            self.lnum_width as usize
+        } else {
+            self.lnum_width as usize + 2
+        };
+        self.canvas.draw_spaces(left_margin)?;
+        let mut width = left_margin;
+        if self.grid {
+            self.canvas
+                .write_all(self.chars.vertical_and_right.as_bytes())?;
+            width += 1;
+        }
+        let label = format!(" lines {}-{} ", start, end);
+        for _ in 0..2 {
+            self.canvas.write_all(self.chars.horizontal.as_bytes())?;
+        }
+        self.canvas.write_all(label.as_bytes())?;
+        width += 2 + label.len();
+        for _ in width..self.term_width as usize {
+            self.canvas.write_all(self.chars.horizontal.as_bytes())?;
+        }
+        self.canvas.draw_newline()
+    }
+
+    // Chunk header for --line-numbers-right. The rule with the range of lines is drawn in the code area
+    fn draw_right_chunk_header(&mut self, start: u64, end: u64) -> io::Result<()> {
+        self.canvas.set_gutter_color()?;
+        let label = format!(" lines {}-{} ", start, end);
+        for _ in 0..2 {
+            self.canvas.write_all(self.chars.horizontal.as_bytes())?;
+        }
+        self.canvas.write_all(label.as_bytes())?;
+        let mut width = 2 + label.len();
+        let body_width = (self.term_width - self.gutter_width()) as usize;
+        let margin = if self.grid { self.gutter_margin() } else { 0 };
+        for _ in width..body_width + margin {
+            self.canvas.write_all(self.chars.horizontal.as_bytes())?;
+        }
+        width = cmp::max(width, body_width + margin);
+        if self.grid {
             self.canvas
-                .write_all(self.chars.dashed_horizontal.as_bytes())?;
+                .write_all(self.chars.vertical_and_left.as_bytes())?;
+            width += 1;
+        }
+        self.canvas.fill_spaces(width, self.term_width as usize)?;
+        self.canvas.draw_newline()
+    }
+
+    // Draw the character of --minimap at the right margin. Lines without the character such as wrapped lines only fill
+    // the margin with the background color
+    fn draw_minimap(&mut self, glyph: bool) -> io::Result<()> {
+        let Some(minimap) = &mut self.minimap else {
+            return Ok(());
+        };
+        let c = if glyph {
+            let c = minimap.glyphs.get(minimap.row).or(minimap.glyphs.last());
+            minimap.row += 1;
+            c.copied().unwrap_or(' ')
+        } else if self.fills_row() {
+            ' '
+        } else {
+            return Ok(());
+        };
+        self.canvas.reset_color()?;
+        self.canvas.set_match_lnum_color()?;
+        write!(self.canvas, " {}", c)
+    }
+
+    // Draw the annotation of --blame at the right margin. The annotation of a wrapped line is drawn at its first row.
+    // Rows without annotation such as context lines only fill the margin with the background color
+    fn draw_blame(&mut self) -> io::Result<()> {
+        let Some(width) = self.blame_width() else {
+            return Ok(());
+        };
+        let fills_row = self.fills_row();
+        let Some(blame) = &mut self.blame else {
+            return Ok(());
+        };
+        let note = blame.pending.take().and_then(|n| blame.lines.get(&n));
+        if note.is_none() && !fills_row {
+            return Ok(());
+        }
+        let mut text = String::new();
+        let mut text_width = 0;
+        for c in note.map(String::as_str).unwrap_or_default().chars() {
+            let w = if self.monospace_width {
+                1
+            } else {
+                c.width_cjk().unwrap_or(0)
+            };
+            if text_width + w > width {
+                break;
+            }
+            text.push(c);
+            text_width += w;
+        }
+        self.canvas.reset_color()?;
+        self.canvas.set_gutter_color()?;
+        write!(self.canvas, " {}", text)?;
+        if self.fills_row() || self.minimap.is_some() {
+            self.canvas.fill_spaces(text_width, width)?;
         }
+        Ok(())
+    }
+
+    // Draw a line of note in gutter color without line number. The note must consist of ASCII characters
+    fn draw_note_line(&mut self, note: &str) -> io::Result<()> {
+        self.draw_wrapping_gutter()?;
+        self.canvas.write_all(note.as_bytes())?;
+        if self.fills_row() {
+            let body_width = self.body_width();
+            self.canvas.fill_spaces(note.len(), body_width)?;
+        }
+        self.draw_blame()?;
+        self.draw_minimap(false)?;
+        self.draw_right_gutter()?;
         self.canvas.draw_newline()
     }
 
+    // Start a new row to wrap text. The width of text written in the new row is returned
     fn draw_text_wrappping(
         &mut self,
         matched: bool,
         style: Style,
         in_region: bool,
-    ) -> io::Result<()> {
+    ) -> io::Result<usize> {
+        self.draw_blame()?;
+        self.draw_minimap(false)?;
+        self.draw_right_gutter()?;
         self.canvas.draw_newline()?;
         self.draw_wrapping_gutter()?;
+        let mut width = 0;
+        if self.wrap_marker {
+            // The marker is not highlighted as a part of the text even in a matched line
+            self.canvas.write_all(self.chars.wrap_marker.as_bytes())?;
+            width += 1;
+        }
         if in_region {
-            self.canvas.set_region_color()
+            if self.canvas.reverse_region {
+                // Reverse video needs the colors of matched line to be reversed
+                self.canvas.set_match_style(style)?;
+            }
+            self.canvas.set_region_color()?;
         } else if matched {
-            self.canvas.set_match_style(style)
+            self.canvas.set_match_style(style)?;
+        } else {
+            self.canvas.set_style(style)?;
+        }
+        Ok(width)
+    }
+
+    // Draw a guide of --indent-guides in gutter color instead of a space at the start of indent level
+    fn draw_indent_guide(&mut self, style: Style) -> io::Result<()> {
+        self.canvas.set_fg(self.canvas.palette.gutter_fg)?;
+        self.canvas.write_all(self.chars.indent_guide.as_bytes())?;
+        self.canvas.set_fg(style.foreground)
+    }
+
+    // Return true when a guide of --indent-guides should be drawn at the column in leading whitespaces
+    fn at_indent_level(&self, indent: bool, in_region: bool, col: usize) -> bool {
+        match self.indent_guides {
+            // Regions starting in indentation are highlighted as-is
+            Some(level) => indent && !in_region && col.is_multiple_of(level),
+            None => false,
+        }
+    }
+
+    fn text_width(&self, text: &str) -> usize {
+        if self.monospace_width {
+            text.chars().count()
         } else {
-            self.canvas.set_style(style)
+            text.width_cjk()
         }
     }
 
+    // `regions` is `Some` when the line is matched. `context_regions` are occurrences of the pattern in a context line
     fn draw_line(
         &mut self,
         mut tokens: Vec<Token<'_>>,
         lnum: u64,
         regions: Option<Vec<(usize, usize)>>,
+        context_regions: &[(usize, usize)],
     ) -> io::Result<()> {
         // The highlighter requires newline at the end. But we don't want it since
         // - we sometimes need to fill the rest of line with spaces
@@ -750,14 +1580,28 @@ impl<'file, W: Write> Drawer<'file, W> {
             }
         }
 
-        let body_width = (self.term_width - self.gutter_width()) as usize;
+        let body_width = self.body_width();
+        self.draw_line_number(lnum, regions.is_some())?;
+        if let Some(blame) = &mut self.blame {
+            blame.pending = Some(lnum);
+        }
+
+        // Matched lines are styled as context lines when matches are not highlighted
+        let regions = regions.filter(|_| self.match_highlight);
         let matched = regions.is_some();
 
         let tokens = tokens.as_slice();
-        let regions = regions.as_ref().map(AsRef::as_ref).unwrap_or(&[][..]);
+        let context_regions = if self.match_highlight {
+            context_regions
+        } else {
+            &[]
+        };
+        let regions = regions
+            .as_ref()
+            .map(AsRef::as_ref)
+            .unwrap_or(context_regions);
         let mut events = DrawEvents::new(tokens, regions);
 
-        self.draw_line_number(lnum, matched)?;
         if matched {
             self.canvas.set_match_style(events.current_style)?;
         } else if !tokens.is_empty() {
@@ -766,22 +1610,37 @@ impl<'file, W: Write> Drawer<'file, W> {
 
         let mut width = 0; // Text width written to terminal
         let mut saw_zwj = false;
+        let mut indent = true; // Drawing leading whitespaces
         loop {
             match events.next_event() {
-                DrawEvent::Char('\t') if self.tab_width > 0 => {
-                    let w = self.tab_width as usize;
+                DrawEvent::Char('\t') if self.tab != TabMode::Keep => {
+                    let w = self.tab.width();
                     if width + w > body_width && self.wrap {
                         self.canvas.draw_spaces(body_width - width)?;
-                        self.draw_text_wrappping(matched, events.current_style, events.in_region)?;
-                        width = 0;
+                        width = self.draw_text_wrappping(
+                            matched,
+                            events.current_style,
+                            events.in_region,
+                        )?;
+                    } else if self.at_indent_level(indent, events.in_region, width) {
+                        self.draw_indent_guide(events.current_style)?;
+                        self.canvas.draw_spaces(w - 1)?;
+                        width += w;
                     } else {
                         self.canvas.draw_spaces(w)?;
                         width += w;
                     }
                 }
                 DrawEvent::Char(c) => {
+                    indent &= c == ' ' || c == '\t';
+                    let c = match control_picture(c) {
+                        Some(p) if self.show_control_chars => p,
+                        _ => c,
+                    };
                     // Handle zero width joiner
-                    let w = if c == '\u{200d}' {
+                    let w = if self.monospace_width {
+                        1
+                    } else if c == '\u{200d}' {
                         saw_zwj = true;
                         0
                     } else if saw_zwj {
@@ -791,11 +1650,18 @@ impl<'file, W: Write> Drawer<'file, W> {
                         c.width_cjk().unwrap_or(0)
                     };
                     if width + w > body_width && self.wrap {
-                        self.canvas.draw_spaces(body_width - width)?;
-                        self.draw_text_wrappping(matched, events.current_style, events.in_region)?;
-                        width = 0;
+                        self.canvas.draw_spaces(body_width.saturating_sub(width))?;
+                        width = self.draw_text_wrappping(
+                            matched,
+                            events.current_style,
+                            events.in_region,
+                        )?;
+                    }
+                    if c == ' ' && self.at_indent_level(indent, events.in_region, width) {
+                        self.draw_indent_guide(events.current_style)?;
+                    } else {
+                        write!(self.canvas, "{}", c)?;
                     }
-                    write!(self.canvas, "{}", c)?;
                     width += w;
                 }
                 DrawEvent::TokenBoundary(prev_style) => {
@@ -814,55 +1680,210 @@ impl<'file, W: Write> Drawer<'file, W> {
                     self.canvas.set_region_color()?;
                 }
                 DrawEvent::RegionEnd => {
-                    self.canvas.set_match_style(events.current_style)?;
+                    self.canvas.unset_region_color()?;
+                    if matched {
+                        self.canvas.set_match_style(events.current_style)?;
+                    } else {
+                        self.canvas.set_style(events.current_style)?;
+                    }
                 }
                 DrawEvent::Done => break,
             }
         }
 
+        self.canvas.unset_region_color()?; // Region may continue to the next line
         if matched {
             self.canvas.set_match_bg_color()?;
         } else if width == 0 {
             self.canvas.set_default_bg()?;
         }
-        if self.canvas.has_background || matched {
+        let blamed = self
+            .blame
+            .as_ref()
+            .is_some_and(|b| b.pending.is_some_and(|n| b.lines.contains_key(&n)));
+        if self.fills_row() || matched || self.minimap.is_some() || blamed {
             self.canvas.fill_spaces(width, body_width)?;
         }
+        self.draw_blame()?;
+        self.draw_minimap(true)?;
+        self.draw_right_gutter()?;
 
         self.canvas.draw_newline()
     }
 
+    // XXX: Cannot move out ranges in line match
+    fn line_regions(&self, line: &str, ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let mut ranges = ranges.to_vec();
+        // Regions must be disjoint for `DrawEvents` to toggle highlights correctly
+        merge_regions(&mut ranges);
+        if self.highlight_word {
+            ranges = expand_regions_to_words(line, &ranges);
+        }
+        if self.first_region_per_line {
+            ranges.truncate(1); // The whole line is still highlighted as a matched line
+        }
+        ranges
+    }
+
     fn draw_body(&mut self, file: &File, mut hl: LineHighlighter<'_>) -> Result<()> {
         assert!(!file.chunks.is_empty());
 
-        let mut matched = file.line_matches.as_ref();
-        let mut chunks = file.chunks.iter();
+        let dedup = self.dedup_lines.then(|| DedupChunks::new(file));
+        let (mut matched, chunks, identical) = match &dedup {
+            Some(d) => (&d.line_matches[..], &d.chunks[..], &d.identical[..]),
+            None => (file.line_matches.as_ref(), file.chunks.as_ref(), &[][..]),
+        };
+        let (chunks, identical) = if self.last_only {
+            let last = chunks.len() - 1;
+            (&chunks[last..], identical.get(last..).unwrap_or_default())
+        } else {
+            (chunks, identical)
+        };
+        let mut folds = match self.context_fold {
+            Some(keep) => fold_ranges(file, keep),
+            None => vec![],
+        };
+        // Folds in the removed chunks are never reached
+        folds.retain(|&(s, _)| chunks.iter().any(|&(start, end)| start <= s && s <= end));
+        if let Some(minimap) = &mut self.minimap {
+            let shown = if self.first_only {
+                &chunks[..1]
+            } else {
+                chunks
+            };
+            let lines: u64 = shown.iter().map(|(s, e)| e - s + 1).sum();
+            let folded: u64 = folds
+                .iter()
+                .filter(|&&(s, _)| shown.iter().any(|&(start, end)| start <= s && s <= end))
+                .map(|(s, e)| e - s + 1)
+                .sum();
+            let chars = if self.chars.horizontal == ASCII_LINE_CHARS.horizontal {
+                &MINIMAP_ASCII_CHARS
+            } else {
+                &MINIMAP_CHARS
+            };
+            let total = file.contents.lines().count() as u64;
+            minimap.glyphs = minimap_glyphs(&file.line_matches, total, lines - folded, chars);
+        }
+        let mut chunks = chunks
+            .iter()
+            .zip(identical.iter().copied().chain(iter::repeat(0)))
+            .peekable();
         let mut chunk = chunks.next().unwrap(); // OK since chunks is not empty
 
+        // Line number, tokens, and regions of the lines in a multi-line match joined by --replace-newline
+        let mut joined = None;
+        let mut folds = folds.as_slice();
+        let mut context_matched = file.context_matches.as_ref();
+        if self.chunk_headers {
+            let &(start, end) = chunk.0;
+            self.draw_chunk_header(start, end)?; // The first header follows the file header
+        }
+
         for (line, lnum) in LinesInclusive::new(&file.contents) {
-            let (start, end) = *chunk;
+            let (&(start, end), num_identical) = chunk;
             if lnum < start {
                 hl.skip_line(line)?; // Discard parsed result
                 continue;
             }
+            if let Some(((fold_start, fold_end), rest)) = folds.split_first() {
+                if *fold_start <= lnum {
+                    hl.skip_line(line)?; // Folded lines still need to be parsed to highlight the following lines
+                    if lnum == *fold_start {
+                        let hidden = fold_end - fold_start + 1;
+                        self.draw_note_line(&format!("... {} lines hidden ...", hidden))?;
+                    }
+                    if lnum == *fold_end {
+                        folds = rest;
+                    }
+                    continue;
+                }
+            }
             if start <= lnum && lnum <= end {
-                let regions = match matched.split_first() {
+                // Line matches out of chunks are never drawn. A multi-line match may start before the chunk
+                let mut head_hidden = false;
+                while let Some((m, ms)) = matched.split_first() {
+                    if m.line_number >= lnum {
+                        break;
+                    }
+                    head_hidden = m.continued && m.line_number + 1 == lnum;
+                    matched = ms;
+                }
+                if head_hidden {
+                    self.draw_note_line("... start of match is hidden ...")?;
+                }
+
+                let lmat = match matched.split_first() {
                     Some((m, ms)) if m.line_number == lnum => {
                         matched = ms;
-                        Some(m.ranges.clone()) // XXX: Cannot move out ranges in line match
+                        Some(m)
                     }
                     _ => None,
                 };
                 // Collect to `Vec` rather than handing HighlightIterator as-is. HighlightIterator takes ownership of Highlighter
                 // while the iteration. When the highlighter is stored in `self`, it means the iterator takes ownership of `self`.
-                self.draw_line(hl.highlight(line)?, lnum, regions)?;
+                let tokens = hl.highlight(line)?;
+                let ranges = lmat.map(|m| self.line_regions(line, &m.ranges));
+                let mut context_ranges = vec![];
+                if lmat.is_none() {
+                    while let Some((m, ms)) = context_matched.split_first() {
+                        if m.line_number > lnum {
+                            break;
+                        }
+                        if m.line_number == lnum {
+                            context_ranges = self.line_regions(line, &m.ranges);
+                        }
+                        context_matched = ms;
+                    }
+                }
+                match (lmat, ranges) {
+                    (Some(m), Some(ranges))
+                        if self.replace_newline && (m.continued || joined.is_some()) =>
+                    {
+                        let (_, toks, regions) =
+                            joined.get_or_insert_with(|| (lnum, vec![], vec![]));
+                        join_line(toks, regions, tokens, &ranges, m.continued);
+                        if !m.continued {
+                            let (lnum, toks, regions) = joined.take().unwrap();
+                            self.draw_line(toks, lnum, Some(regions), &[])?;
+                        }
+                    }
+                    (_, ranges) => self.draw_line(tokens, lnum, ranges, &context_ranges)?,
+                }
+
+                // The space is put after the whole line including wrapped rows. It is not necessary at the end of chunk
+                // since a separator line follows
+                if self.space_after_match && lmat.is_some() && joined.is_none() && lnum != end {
+                    self.draw_note_line("")?;
+                }
 
                 if lnum == end {
-                    if self.first_only {
+                    // A multi-line match may continue beyond the chunk. Draw the visible part of the match
+                    let next_start = chunks.peek().map(|&(&(s, _), _)| s);
+                    let next_shown =
+                        next_start == Some(end + 1) && (!self.first_only || joined.is_some());
+                    if lmat.is_some_and(|m| m.continued) && !next_shown {
+                        if let Some((lnum, toks, regions)) = joined.take() {
+                            self.draw_line(toks, lnum, Some(regions), &[])?;
+                        }
+                        self.draw_note_line("... rest of match is hidden ...")?;
+                    }
+
+                    // Chunks may be split in the middle of a multi-line match when no context line is shown
+                    let joining = joined.is_some();
+                    if num_identical > 0 && !joining {
+                        self.draw_note_line(&format!("(+{} identical)", num_identical))?;
+                    }
+                    if self.first_only && !joining {
                         break;
                     }
                     if let Some(c) = chunks.next() {
-                        self.draw_separator_line()?;
+                        if self.chunk_headers && !joining {
+                            let &(start, end) = c.0;
+                            self.draw_chunk_header(start, end)?;
+                        } else if !self.matches_only && !joining {
+                            self.draw_separator_line()?;
+                        }
                         chunk = c;
                     } else {
                         break;
@@ -877,13 +1898,21 @@ impl<'file, W: Write> Drawer<'file, W> {
     fn draw_header(&mut self, path: &Path) -> io::Result<()> {
         self.draw_horizontal_line(self.chars.horizontal)?;
         self.canvas.set_default_bg()?;
-        let path = path.as_os_str().to_string_lossy();
+        let path = display_path(
+            strip_path_prefix(path, self.strip_prefix),
+            self.path_separator,
+        );
         self.canvas.set_default_fg()?;
         self.canvas.set_bold()?;
-        write!(self.canvas, " {}", path)?;
+        if let Some(url) = &self.link {
+            // OSC 8 hyperlink. The link does not affect the width of the header
+            write!(self.canvas, " \x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, path)?;
+        } else {
+            write!(self.canvas, " {}", path)?;
+        }
         if self.canvas.has_background {
             self.canvas
-                .fill_spaces(path.width_cjk() + 1, self.term_width as usize)?;
+                .fill_spaces(self.text_width(&path) + 1, self.term_width as usize)?;
         }
         self.canvas.draw_newline()?;
         if self.grid {
@@ -907,7 +1936,8 @@ impl<'file, W: Write> Drawer<'file, W> {
     }
 }
 
-fn load_themes(name: Option<&str>) -> Result<ThemeSet> {
+// Load the theme set which contains the theme. This fails when the theme is unknown
+pub fn load_themes(name: Option<&str>) -> Result<ThemeSet> {
     let bat_defaults: ThemeSet = load_bat_themes()?;
     match name {
         None => Ok(bat_defaults),
@@ -923,6 +1953,50 @@ fn load_themes(name: Option<&str>) -> Result<ThemeSet> {
     }
 }
 
+// Find filetype declared by Vim modeline such as `vim: set ft=ruby:` or `vim: ft=ruby`. Like Vim, modeline is searched
+// in the first and last 5 lines of the file.
+fn find_modeline_filetype(contents: &str) -> Option<&str> {
+    const MODELINES: usize = 5;
+
+    fn parse_modeline(line: &str) -> Option<&str> {
+        let (_, rest) = ["vim:", "vi:", "ex:"].iter().find_map(|prefix| {
+            let idx = line.find(prefix)?;
+            // The prefix must be preceded by whitespace to avoid false positives like 'nvim:'
+            if idx > 0 && !line[..idx].ends_with([' ', '\t']) {
+                return None;
+            }
+            Some(line.split_at(idx + prefix.len()))
+        })?;
+        let rest = rest.trim_start();
+
+        // The second form `vim: set {options}:` ends at ':'. The first form `vim: {options}` is separated by ':' or spaces
+        let options = if let Some(opts) = rest
+            .strip_prefix("set ")
+            .or_else(|| rest.strip_prefix("se "))
+        {
+            opts.split(':')
+                .next()?
+                .split_whitespace()
+                .collect::<Vec<_>>()
+        } else {
+            rest.split([':', ' ', '\t']).collect()
+        };
+
+        options.into_iter().rev().find_map(|opt| {
+            let (name, value) = opt.split_once('=')?;
+            matches!(name, "ft" | "filetype" | "syn" | "syntax")
+                .then_some(value)
+                .filter(|v| !v.is_empty())
+        })
+    }
+
+    contents
+        .lines()
+        .take(MODELINES)
+        .chain(contents.lines().rev().take(MODELINES))
+        .find_map(parse_modeline)
+}
+
 pub struct SyntectAssets {
     pub syntax_set: SyntaxSet,
     pub theme_set: ThemeSet,
@@ -949,11 +2023,123 @@ impl Clone for SyntectAssets {
     }
 }
 
+// Rendered body of a file with the inputs of rendering. The inputs are compared on cache hit since the hash of them may
+// collide
+struct RenderedBody {
+    syntax: String,
+    contents: Box<str>,
+    chunks: Box<[(u64, u64)]>,
+    line_matches: Box<[LineMatch]>,
+    context_matches: Box<[LineMatch]>,
+    bytes: Box<[u8]>,
+}
+
+impl RenderedBody {
+    fn new(file: &File, syntax: &SyntaxReference, bytes: Vec<u8>) -> Self {
+        Self {
+            syntax: syntax.name.clone(),
+            contents: file.contents.clone(),
+            chunks: file.chunks.clone(),
+            line_matches: file.line_matches.clone(),
+            context_matches: file.context_matches.clone(),
+            bytes: bytes.into(),
+        }
+    }
+
+    fn is_for(&self, file: &File, syntax: &SyntaxReference) -> bool {
+        fn same_matches(l: &[LineMatch], r: &[LineMatch]) -> bool {
+            l.len() == r.len()
+                && l.iter().zip(r.iter()).all(|(l, r)| {
+                    l.line_number == r.line_number
+                        && l.ranges == r.ranges
+                        && l.continued == r.continued
+                })
+        }
+        self.syntax == syntax.name
+            && self.contents == file.contents
+            && self.chunks == file.chunks
+            && same_matches(&self.line_matches, &file.line_matches)
+            && same_matches(&self.context_matches, &file.context_matches)
+    }
+}
+
+enum CacheLookup {
+    Hit(Arc<RenderedBody>),
+    Miss { store: bool },
+}
+
+// Cache of rendered code snippets for --render-cache. Files which have the same contents are rendered into the same
+// bytes except for their headers. Highlighting depends on the syntax derived from the file path so it is also a part of
+// the key. Only the hash is remembered for the first occurrence so that bodies of unique files are not kept in memory.
+// The body is stored when the same hash is seen for the second time
+#[derive(Default)]
+struct RenderCache(Mutex<HashMap<u64, Option<Arc<RenderedBody>>>>);
+
+impl RenderCache {
+    fn key(file: &File, syntax: &SyntaxReference) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        syntax.name.hash(&mut hasher);
+        file.contents.hash(&mut hasher);
+        file.chunks.hash(&mut hasher);
+        file.line_matches.len().hash(&mut hasher);
+        for lmat in file.line_matches.iter().chain(file.context_matches.iter()) {
+            lmat.line_number.hash(&mut hasher);
+            lmat.ranges.hash(&mut hasher);
+            lmat.continued.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, Option<Arc<RenderedBody>>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lookup(&self, key: u64, file: &File, syntax: &SyntaxReference) -> CacheLookup {
+        let mut cache = self.lock();
+        match cache.get(&key) {
+            None => {
+                cache.insert(key, None);
+                CacheLookup::Miss { store: false }
+            }
+            Some(None) => CacheLookup::Miss { store: true },
+            Some(Some(body)) if body.is_for(file, syntax) => CacheLookup::Hit(body.clone()),
+            Some(Some(_)) => CacheLookup::Miss { store: false }, // Hash collision
+        }
+    }
+
+    // Files with the same contents may be rendered on multiple threads at the same time. The first one is kept
+    fn insert(&self, key: u64, body: RenderedBody) {
+        let mut cache = self.lock();
+        let slot = cache.entry(key).or_default();
+        if slot.is_none() {
+            *slot = Some(Arc::new(body));
+        }
+    }
+}
+
 pub struct SyntectPrinter<'main, W> {
     writer: W, // Protected with mutex because it should print file by file
     syntaxes: SyntaxSet,
     themes: ThemeSet,
     opts: PrinterOptions<'main>,
+    cwd: Option<PathBuf>, // To resolve relative paths for hyperlinks
+    cache: Option<RenderCache>,
+}
+
+// Replace the background colors of the themes. Since the replaced color is the default background of tokens and palette
+// colors are calculated against it, the colors of gutter and line numbers are adjusted to the new background
+fn override_background(mut themes: ThemeSet, rgb: Option<(u8, u8, u8)>) -> ThemeSet {
+    if let Some((r, g, b)) = rgb {
+        for theme in themes.themes.values_mut() {
+            theme.settings.background = Some(Color { r, g, b, a: 255 });
+        }
+    }
+    themes
+}
+
+// Annotations of --blame are different for each file even if the contents are the same
+fn new_render_cache(opts: &PrinterOptions<'_>) -> Option<RenderCache> {
+    (opts.render_cache && !opts.blame).then(RenderCache::default)
 }
 
 impl<'main> SyntectPrinter<'main, Stdout> {
@@ -964,11 +2150,19 @@ impl<'main> SyntectPrinter<'main, Stdout> {
 
 impl<'main, W> SyntectPrinter<'main, W> {
     pub fn new(writer: W, opts: PrinterOptions<'main>) -> Result<Self> {
+        let themes = load_themes(opts.resolved_theme())?;
+        Self::with_themes(writer, themes, opts)
+    }
+
+    // Create a printer with the themes loaded by `load_themes` in advance
+    pub fn with_themes(writer: W, themes: ThemeSet, opts: PrinterOptions<'main>) -> Result<Self> {
         Ok(Self {
             writer,
             syntaxes: load_syntax_set()?,
-            themes: load_themes(opts.theme)?,
+            themes: override_background(themes, opts.background_rgb),
+            cache: new_render_cache(&opts),
             opts,
+            cwd: env::current_dir().ok(),
         })
     }
 
@@ -976,8 +2170,10 @@ impl<'main, W> SyntectPrinter<'main, W> {
         Self {
             writer,
             syntaxes: assets.syntax_set,
-            themes: assets.theme_set,
+            themes: override_background(assets.theme_set, opts.background_rgb),
+            cache: new_render_cache(&opts),
             opts,
+            cwd: env::current_dir().ok(),
         }
     }
 
@@ -986,7 +2182,7 @@ impl<'main, W> SyntectPrinter<'main, W> {
     }
 
     fn theme(&self) -> &Theme {
-        let name = self.opts.theme.unwrap_or_else(|| {
+        let name = self.opts.resolved_theme().unwrap_or_else(|| {
             if self.opts.color_support == TermColorSupport::Ansi16 {
                 "ansi"
             } else {
@@ -997,6 +2193,15 @@ impl<'main, W> SyntectPrinter<'main, W> {
     }
 
     fn find_syntax(&self, file: &File) -> &SyntaxReference {
+        // Filetype declared in the file itself is more reliable than its file name
+        if self.opts.respect_modeline {
+            if let Some(syntax) = find_modeline_filetype(&file.contents)
+                .and_then(|ft| self.syntaxes.find_syntax_by_token(ft))
+            {
+                return syntax;
+            }
+        }
+
         let extension = file.path.extension();
         let file_name = file.path.file_name();
 
@@ -1076,18 +2281,55 @@ impl<'main, W> SyntectPrinter<'main, W> {
     }
 }
 
-impl<'main, W: WriteOnLocked> Printer for SyntectPrinter<'main, W> {
-    fn print(&self, file: File) -> Result<()> {
-        if file.chunks.is_empty() || file.line_matches.is_empty() {
+impl<'main, W: WriteOnLocked> SyntectPrinter<'main, W> {
+    // Print the key explaining the colors in the current palette. This is intended to be printed once before results
+    pub fn print_legend(&self) -> Result<()> {
+        let mut buf = vec![];
+        let mut drawer = Drawer::new(&mut buf, &self.opts, self.theme(), &[]);
+        drawer.canvas.draw_legend()?;
+        writeln!(drawer.canvas)?;
+
+        let mut output = self.writer.lock();
+        output.write_all(&buf).ignore_broken_pipe()?;
+        Ok(output.flush()?)
+    }
+
+    // Render chunks of the file even if no line is matched. This is used for previewing a whole file
+    pub fn render(&self, file: &File) -> Result<()> {
+        if file.chunks.is_empty() {
             return Ok(());
         }
 
         let mut buf = vec![];
         let theme = self.theme();
-        let syntax = self.find_syntax(&file);
+        let syntax = self.find_syntax(file);
 
         let hl = LineHighlighter::new(syntax, theme, &self.syntaxes);
-        Drawer::new(&mut buf, &self.opts, theme, &file.chunks).draw_file(&file, hl)?;
+        let mut drawer = Drawer::new(&mut buf, &self.opts, theme, &file.chunks);
+        if self.opts.hyperlink {
+            drawer.link = file_url(&file.path, self.cwd.as_deref(), file.chunks[0].0);
+        }
+        if self.opts.blame {
+            drawer.blame = Blame::load(&file.path, &file.line_matches);
+        }
+        if let Some(cache) = &self.cache {
+            let key = RenderCache::key(file, syntax);
+            drawer.draw_header(&file.path)?;
+            match cache.lookup(key, file, syntax) {
+                CacheLookup::Hit(body) => drawer.canvas.write_all(&body.bytes)?,
+                CacheLookup::Miss { store } => {
+                    let mut body = vec![];
+                    Drawer::new(&mut body, &self.opts, theme, &file.chunks).draw_body(file, hl)?;
+                    drawer.canvas.write_all(&body)?;
+                    if store {
+                        cache.insert(key, RenderedBody::new(file, syntax, body));
+                    }
+                }
+            }
+            drawer.draw_footer()?;
+        } else {
+            drawer.draw_file(file, hl)?;
+        }
 
         // Take lock here to print files in serial from multiple threads
         let mut output = self.writer.lock();
@@ -1096,16 +2338,26 @@ impl<'main, W: WriteOnLocked> Printer for SyntectPrinter<'main, W> {
     }
 }
 
+impl<'main, W: WriteOnLocked> Printer for SyntectPrinter<'main, W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.line_matches.is_empty() {
+            return Ok(());
+        }
+        self.render(&file)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk::{File, LineMatch};
+    use crate::test::TempDir;
     use once_cell::sync::Lazy;
     use std::cell::{RefCell, RefMut};
     use std::fmt;
     use std::fs;
     use std::mem;
     use std::path::PathBuf;
+    use std::ptr;
     use std::str;
 
     static ASSETS: Lazy<SyntectAssets> = Lazy::new(|| SyntectAssets::load(None).unwrap());
@@ -1261,6 +2513,133 @@ mod tests {
             run_uitest(file, outfile, f);
         }
 
+        // Annotations of --blame are given directly since the output of `git blame` depends on the history of this
+        // repository
+        fn run_blame_uitest(input: &str, f: fn(&mut PrinterOptions<'_>) -> ()) {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let file = read_chunks(dir.join(format!("{}.rs", input)));
+            let mut opts = PrinterOptions {
+                term_width: 80,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            f(&mut opts);
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+
+            let lines = file
+                .line_matches
+                .iter()
+                .enumerate()
+                .map(|(i, m)| {
+                    let note = if i == 0 {
+                        "1a2b3c4 2024-05-06 rhysd"
+                    } else {
+                        "0000000 2024-05-07 \u{9577}\u{3044}\u{540d}\u{524d}\u{306e}\u{4f5c}\u{8005}"
+                    };
+                    (m.line_number, note.to_string())
+                })
+                .collect();
+            let theme = printer.theme();
+            let hl = LineHighlighter::new(printer.find_syntax(&file), theme, &printer.syntaxes);
+            let mut printed = vec![];
+            let mut drawer = Drawer::new(&mut printed, &printer.opts, theme, &file.chunks);
+            drawer.blame = Some(Blame::new(lines));
+            drawer.draw_file(&file, hl).unwrap();
+
+            let expected = read_expected_file(&dir.join(format!("{}.out", input)));
+            assert_eq!(
+                printed,
+                expected,
+                "got:\n{}\nwant:\n{}",
+                String::from_utf8_lossy(&printed),
+                String::from_utf8_lossy(&expected),
+            );
+        }
+
+        #[test]
+        fn test_blame() {
+            run_blame_uitest("blame", |_| {});
+        }
+
+        #[test]
+        fn test_blame_bg() {
+            run_blame_uitest("blame_bg", |o| {
+                o.background_color = true;
+                o.minimap = true;
+            });
+        }
+
+        #[test]
+        fn test_blame_narrow() {
+            run_blame_uitest("blame_narrow", |o| {
+                o.term_width = 50;
+            });
+        }
+
+        // Chunks without context lines as if -c0 -C0 were given. Output files were generated by hgrep with -c0 -C0
+        fn run_zero_context_uitest(output: &str, f: fn(&mut PrinterOptions<'_>) -> ()) {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let mut file = read_chunks(dir.join("compact_gutter.rs"));
+            file.chunks = file
+                .line_matches
+                .iter()
+                .map(|m| (m.line_number, m.line_number))
+                .collect();
+            run_uitest(file, dir.join(format!("{}.out", output)), f);
+        }
+
+        #[test]
+        fn test_compact_gutter() {
+            run_zero_context_uitest("compact_gutter", |o| {
+                o.compact_gutter = true;
+            });
+        }
+
+        #[test]
+        fn test_compact_gutter_no_grid() {
+            run_zero_context_uitest("compact_gutter_no_grid", |o| {
+                o.compact_gutter = true;
+                o.grid = false;
+            });
+        }
+
+        #[test]
+        fn test_compact_gutter_default() {
+            run_zero_context_uitest("compact_gutter_default", |_| {});
+        }
+
+        #[test]
+        fn test_render_only() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let file = File::read_whole(dir.join("render_only.rs"), None).unwrap();
+            let opts = PrinterOptions {
+                term_width: 80,
+                color_support: TermColorSupport::True,
+                first_only: true, // Ignored since the whole file is one chunk
+                ..Default::default()
+            };
+            let mut printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+
+            printer.print(file.clone()).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            assert!(
+                printed.is_empty(),
+                "file without match is not printed by print()"
+            );
+
+            printer.render(&file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            let expected = read_expected_file(&dir.join("render_only.out"));
+            assert_eq!(
+                printed,
+                expected,
+                "got:\n{}\nwant:\n{}",
+                String::from_utf8_lossy(&printed),
+                String::from_utf8_lossy(&expected),
+            );
+        }
+
         macro_rules! uitests {
             ($($input:ident($f:expr),)+) => {
                 $(
@@ -1284,10 +2663,10 @@ mod tests {
                 o.theme = Some("Nord");
             }),
             test_tab_width_2(|o| {
-                o.tab_width = 2;
+                o.tab = TabMode::Expand(2);
             }),
             test_hard_tab(|o| {
-                o.tab_width = 0;
+                o.tab = TabMode::Keep;
             }),
             test_ansi256_colors(|o| {
                 o.color_support = TermColorSupport::Ansi256;
@@ -1342,6 +2721,55 @@ mod tests {
             test_multi_chunks_bg(|o| {
                 o.background_color = true;
             }),
+            test_chunk_headers(|o| {
+                o.chunk_headers = true;
+            }),
+            test_chunk_headers_no_grid(|o| {
+                o.chunk_headers = true;
+                o.grid = false;
+            }),
+            test_separator_blank(|o| {
+                o.separator_style = SeparatorStyle::Blank;
+            }),
+            test_separator_blank_no_grid(|o| {
+                o.separator_style = SeparatorStyle::Blank;
+                o.grid = false;
+            }),
+            test_separator_dots(|o| {
+                o.separator_style = SeparatorStyle::Dots;
+            }),
+            test_separator_width(|o| {
+                o.separator_width = Some(20);
+            }),
+            test_separator_width_no_grid(|o| {
+                o.separator_width = Some(20);
+                o.grid = false;
+            }),
+            test_separator_dots_no_grid(|o| {
+                o.separator_style = SeparatorStyle::Dots;
+                o.grid = false;
+            }),
+            test_line_numbers_right(|o| {
+                o.line_numbers_right = true;
+            }),
+            test_line_numbers_right_no_grid(|o| {
+                o.line_numbers_right = true;
+                o.grid = false;
+            }),
+            test_line_numbers_right_bg(|o| {
+                o.line_numbers_right = true;
+                o.background_color = true;
+            }),
+            test_space_after_match(|o| {
+                o.space_after_match = true;
+            }),
+            test_space_after_match_bg(|o| {
+                o.space_after_match = true;
+                o.background_color = true;
+            }),
+            test_space_after_match_wrap(|o| {
+                o.space_after_match = true;
+            }),
             test_japanese_default(|_| {}),
             test_japanese_background(|o| {
                 o.background_color = true;
@@ -1391,6 +2819,78 @@ mod tests {
             test_first_only(|o| {
                 o.first_only = true;
             }),
+            test_last_only(|o| {
+                o.last_only = true;
+            }),
+            test_gutter_char(|o| {
+                o.gutter_char = Some("┃");
+            }),
+            test_match_marker(|o| {
+                o.match_marker = Some("▶");
+            }),
+            test_match_marker_no_grid(|o| {
+                o.match_marker = Some("*");
+                o.grid = false;
+            }),
+            test_highlight_word(|o| {
+                o.highlight_word = true;
+            }),
+            test_first_region_per_line(|o| {
+                o.first_region_per_line = true;
+            }),
+            test_region_reverse(|o| {
+                o.region_style = RegionStyle::Reverse;
+            }),
+            test_region_reverse_wrap(|o| {
+                o.region_style = RegionStyle::Reverse;
+            }),
+            test_monospace_width(|o| {
+                o.monospace_width = true;
+            }),
+            test_context_fold(|o| {
+                o.context_fold = Some(2);
+            }),
+            test_background_color(|o| {
+                o.background_color = true;
+                o.background_rgb = Some((0x1e, 0x1e, 0x1e));
+            }),
+            test_numbers_only_on_match(|o| {
+                o.line_numbers_on_match_only = true;
+            }),
+            test_numbers_only_on_match_no_grid(|o| {
+                o.line_numbers_on_match_only = true;
+                o.grid = false;
+            }),
+            test_dedup_lines(|o| {
+                o.dedup_lines = true;
+            }),
+            test_no_match_highlight(|o| {
+                o.match_highlight = false;
+            }),
+            test_show_control_chars(|o| {
+                o.show_control_chars = true;
+            }),
+            test_binary_as_text(|o| {
+                o.show_control_chars = true;
+            }),
+            test_minimap(|o| {
+                o.minimap = true;
+            }),
+            test_indent_guides(|o| {
+                o.indent_guides = true;
+            }),
+            test_indent_guides_ascii(|o| {
+                o.indent_guides = true;
+                o.ascii_lines = true;
+            }),
+            test_minimap_bg(|o| {
+                o.minimap = true;
+                o.background_color = true;
+            }),
+            test_max_chunk_width(|o| {
+                o.max_chunk_width = Some(40);
+                o.background_color = true;
+            }),
             test_ascii_lines_grid(|o| {
                 o.ascii_lines = true;
             }),
@@ -1403,8 +2903,124 @@ mod tests {
                 o.background_color = true;
             }),
             test_wrap_between_regions(|_| {}),
+            test_wrap_marker(|o| {
+                o.wrap_marker = true;
+            }),
+            test_wrap_marker_region(|o| {
+                o.wrap_marker = true;
+            }),
             test_wrap_regions_japanese(|_| {}),
+            test_respect_modeline(|o| {
+                o.respect_modeline = true;
+            }),
         );
+
+        #[test]
+        fn test_matches_only() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let mut file = read_chunks(dir.join("matches_only.rs"));
+            // Each matched line is a chunk since --matches-only sets both min and max context to zero
+            file.chunks = file
+                .line_matches
+                .iter()
+                .map(|m| (m.line_number, m.line_number))
+                .collect();
+            run_uitest(file, dir.join("matches_only.out"), |o| {
+                o.matches_only = true;
+            });
+        }
+
+        #[test]
+        fn test_indent_guides_region() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let mut file = read_chunks(dir.join("indent_guides_region.rs"));
+            // Regions start at the beginning of lines so that they include indentation
+            for m in file.line_matches.iter_mut() {
+                m.ranges[0].0 = 0;
+            }
+            run_uitest(file, dir.join("indent_guides_region.out"), |o| {
+                o.indent_guides = true;
+            });
+        }
+
+        #[test]
+        fn test_replace_newline() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let path = dir.join("replace_newline.rs");
+            let contents = fs::read_to_string(&path).unwrap();
+            // Line matches of a multi-line match reported by ripgrep. Line 2 to 4 are joined into one line
+            let lmats = vec![
+                LineMatch {
+                    line_number: 2,
+                    ranges: vec![(13, 27)],
+                    continued: true,
+                },
+                LineMatch {
+                    line_number: 3,
+                    ranges: vec![(0, 11)],
+                    continued: true,
+                },
+                LineMatch::new(4, vec![(0, 12)]),
+                LineMatch::new(5, vec![(14, 34)]),
+            ];
+            let file = File::new(path, lmats, vec![(1, 6)], contents);
+            run_uitest(file, dir.join("replace_newline.out"), |o| {
+                o.replace_newline = true;
+            });
+        }
+
+        fn region_across_chunk() -> File {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let path = dir.join("region_across_chunk.rs");
+            let contents = fs::read_to_string(&path).unwrap();
+            // Line 4 and 5 are out of chunks. The first multi-line match continues to the hidden line and the second
+            // one starts from the hidden line
+            let lmats = vec![
+                LineMatch {
+                    line_number: 2,
+                    ranges: vec![(13, 27)],
+                    continued: true,
+                },
+                LineMatch {
+                    line_number: 3,
+                    ranges: vec![(0, 11)],
+                    continued: true,
+                },
+                LineMatch::new(4, vec![(0, 12)]),
+                LineMatch {
+                    line_number: 5,
+                    ranges: vec![(13, 31)],
+                    continued: true,
+                },
+                LineMatch {
+                    line_number: 6,
+                    ranges: vec![(0, 18)],
+                    continued: true,
+                },
+                LineMatch::new(7, vec![(0, 5)]),
+                LineMatch::new(8, vec![(14, 34)]),
+            ];
+            File::new(path, lmats, vec![(1, 3), (6, 9)], contents)
+        }
+
+        #[test]
+        fn test_region_across_chunk() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            run_uitest(
+                region_across_chunk(),
+                dir.join("region_across_chunk.out"),
+                |_| {},
+            );
+        }
+
+        #[test]
+        fn test_region_across_chunk_replace_newline() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let out = dir.join("region_across_chunk_replace_newline.out");
+            run_uitest(region_across_chunk(), out, |o| {
+                o.replace_newline = true;
+            });
+        }
     }
 
     // Separate module from `ui` since pretty_assertions is too slow for showing diff between byte sequences.
@@ -1461,6 +3077,60 @@ mod tests {
                 o.background_color = true;
             }),
         }
+
+        #[test]
+        fn test_list_theme_names() {
+            let expected = Path::new("testdata")
+                .join("syntect")
+                .join("theme_names.out");
+            let expected = fs::read(expected).unwrap();
+
+            let mut got = vec![];
+            list_theme_names(&mut got).unwrap();
+
+            assert_eq!(
+                expected,
+                got,
+                "expected:\n{}\ngot:\n{}",
+                str::from_utf8(&expected).unwrap(),
+                str::from_utf8(&got).unwrap()
+            );
+        }
+
+        #[test]
+        fn test_preview_theme() {
+            let expected = Path::new("testdata")
+                .join("syntect")
+                .join("theme_preview_nord.out");
+            let expected = fs::read(expected).unwrap();
+
+            let opts = PrinterOptions {
+                term_width: 80,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let mut got = vec![];
+            preview_theme_with_syntaxes(&mut got, "Nord", &opts, &ASSETS.syntax_set).unwrap();
+
+            assert_eq!(
+                expected,
+                got,
+                "expected:\n{}\ngot:\n{}",
+                str::from_utf8(&expected).unwrap(),
+                str::from_utf8(&got).unwrap()
+            );
+        }
+
+        #[test]
+        fn test_preview_unknown_theme() {
+            let opts = PrinterOptions::default();
+            let mut got = vec![];
+            let err = preview_theme_with_syntaxes(&mut got, "Unknown", &opts, &ASSETS.syntax_set)
+                .unwrap_err();
+            let msg = format!("{}", err);
+            assert!(msg.contains("Unknown theme 'Unknown'"), "{:?}", msg);
+            assert!(got.is_empty(), "{:?}", got);
+        }
     }
 
     #[derive(Debug)]
@@ -1520,6 +3190,295 @@ mod tests {
         printer.print(file).unwrap();
     }
 
+    #[test]
+    fn test_print_legend() {
+        let opts = PrinterOptions {
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let out = Mutex::new(vec![]);
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), out, opts);
+        printer.print_legend().unwrap();
+        let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+
+        let lines: Vec<_> = printed.lines().collect();
+        assert_eq!(lines.len(), 3, "{:?}", printed);
+        for (line, names) in lines.iter().zip([
+            ["Matched line:", "Line number:"],
+            ["Matched region:", "Gutter:"],
+        ]) {
+            for name in names {
+                assert!(line.contains(name), "{:?} is not in {:?}", name, line);
+            }
+            assert_eq!(line.matches("\x1b[48;2;").count(), 2, "{:?}", line);
+        }
+        assert_eq!(lines[2], "");
+    }
+
+    #[test]
+    fn test_path_separator_in_header() {
+        #[cfg(not(windows))]
+        let (path, sep, want) = ("dir/sub/foo.rs", '\\', " dir\\sub\\foo.rs");
+        #[cfg(windows)]
+        let (path, sep, want) = ("dir\\sub\\foo.rs", '/', " dir/sub/foo.rs");
+
+        let opts = PrinterOptions {
+            path_separator: Some(sep),
+            ..Default::default()
+        };
+        let file = File::new(
+            PathBuf::from(path),
+            vec![LineMatch::lnum(1)],
+            vec![(1, 1)],
+            "fn main() {}\n".to_string(),
+        );
+        let out = Mutex::new(vec![]);
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), out, opts);
+        printer.print(file).unwrap();
+        let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+        assert!(printed.contains(want), "{:?}", printed);
+    }
+
+    #[test]
+    fn test_strip_prefix_in_header() {
+        for (prefix, want) in [
+            ("dir/sub", " foo.rs"),
+            ("dir", " sub/foo.rs"),
+            ("other", " dir/sub/foo.rs"),
+        ] {
+            let opts = PrinterOptions {
+                strip_prefix: Some(Path::new(prefix)),
+                path_separator: Some('/'),
+                ..Default::default()
+            };
+            let file = File::new(
+                Path::new("dir").join("sub").join("foo.rs"),
+                vec![LineMatch::lnum(1)],
+                vec![(1, 1)],
+                "fn main() {}\n".to_string(),
+            );
+            let out = Mutex::new(vec![]);
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), out, opts);
+            printer.print(file).unwrap();
+            let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+            let header = printed.lines().nth(1).unwrap();
+            assert!(header.contains(&format!("{}\x1b", want)), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn test_highlight_context_matches() {
+        let contents = "fn main() {\n    let x = 1;\n    let x = 2;\n}\n";
+        let print = |context_matches: Vec<LineMatch>| {
+            let mut file = File::new(
+                PathBuf::from("foo.rs"),
+                vec![LineMatch::new(2, vec![(8, 9)])],
+                vec![(1, 4)],
+                contents.to_string(),
+            );
+            file.context_matches = context_matches.into_boxed_slice();
+            let opts = PrinterOptions {
+                term_width: 80,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), Mutex::new(vec![]), opts);
+            printer.print(file).unwrap();
+            String::from_utf8(printer.writer.into_inner().unwrap()).unwrap()
+        };
+
+        let without = print(vec![]);
+        let with = print(vec![LineMatch::new(3, vec![(8, 9)])]);
+        let without: Vec<_> = without.lines().collect();
+        let with: Vec<_> = with.lines().collect();
+        assert_eq!(without.len(), with.len());
+        let diff: Vec<_> = without
+            .iter()
+            .zip(with.iter())
+            .filter(|(l, r)| l != r)
+            .map(|(_, r)| *r)
+            .collect();
+        assert_eq!(diff.len(), 1, "{with:#?}");
+        assert!(diff[0].contains(" 3 "), "{diff:?}");
+    }
+
+    #[test]
+    fn test_render_cache() {
+        let file = |path: &str, contents: &str| {
+            File::new(
+                PathBuf::from(path),
+                vec![LineMatch::lnum(2)],
+                vec![(1, 3)],
+                contents.to_string(),
+            )
+        };
+        let files = [
+            file("a/foo.rs", "fn main() {\n    let x = 1;\n}\n"),
+            file("b/foo.rs", "fn main() {\n    let x = 1;\n}\n"), // Same contents and syntax
+            file("c/foo.txt", "fn main() {\n    let x = 1;\n}\n"), // Same contents but different syntax
+            file("d/foo.rs", "fn main() {\n    let y = 1;\n}\n"),  // Different contents
+            file("e/foo.rs", "fn main() {\n    let x = 1;\n}\n"), // Rendered body of b/foo.rs is reused
+        ];
+
+        let print = |render_cache: bool| {
+            let opts = PrinterOptions {
+                term_width: 80,
+                color_support: TermColorSupport::True,
+                render_cache,
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), Mutex::new(vec![]), opts);
+            for f in files.iter() {
+                printer.print(f.clone()).unwrap();
+            }
+            let cached = printer
+                .cache
+                .as_ref()
+                .map(|c| c.lock().values().filter(|b| b.is_some()).count());
+            let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+            (printed, cached)
+        };
+
+        let (want, cached) = print(false);
+        assert_eq!(cached, None);
+        let (got, cached) = print(true);
+        assert_eq!(want, got);
+        assert_eq!(cached, Some(1)); // Only the body of foo.rs seen twice is kept
+        for path in ["a/foo.rs", "b/foo.rs", "c/foo.txt", "d/foo.rs", "e/foo.rs"] {
+            assert!(got.contains(path), "{path:?} is not in {got:?}");
+        }
+
+        // Cached body is not used for different inputs even if their hashes collide
+        let cache = RenderCache::default();
+        let syntax = ASSETS.syntax_set.find_syntax_by_extension("rs").unwrap();
+        let key = RenderCache::key(&files[0], syntax);
+        assert!(matches!(
+            cache.lookup(key, &files[0], syntax),
+            CacheLookup::Miss { store: false }
+        ));
+        assert!(matches!(
+            cache.lookup(key, &files[0], syntax),
+            CacheLookup::Miss { store: true }
+        ));
+        cache.insert(key, RenderedBody::new(&files[0], syntax, b"body".to_vec()));
+        assert!(matches!(
+            cache.lookup(key, &files[1], syntax),
+            CacheLookup::Hit(_)
+        ));
+        assert!(matches!(
+            cache.lookup(key, &files[3], syntax),
+            CacheLookup::Miss { store: false }
+        ));
+
+        // Cache is not used with --blame since annotations depend on each file
+        let opts = PrinterOptions {
+            render_cache: true,
+            blame: true,
+            ..Default::default()
+        };
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), io::stdout(), opts);
+        assert!(printer.cache.is_none());
+    }
+
+    #[test]
+    fn test_hyperlink_in_header() {
+        let cwd = env::current_dir().unwrap();
+        for path in [PathBuf::from("foo.rs"), cwd.join("foo.rs")] {
+            let opts = PrinterOptions {
+                hyperlink: true,
+                ..Default::default()
+            };
+            let file = File::new(
+                path.clone(),
+                vec![LineMatch::lnum(3)],
+                vec![(2, 4)],
+                "a\nb\nc\nd\n".to_string(),
+            );
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), Mutex::new(vec![]), opts);
+            printer.print(file).unwrap();
+            let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+            let url = file_url(&cwd.join("foo.rs"), None, 2).unwrap();
+            let want = format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, path.display(),);
+            assert!(printed.contains(&want), "path={:?}, {:?}", path, printed);
+        }
+    }
+
+    #[test]
+    fn test_ansi256_escape_sequences() {
+        let opts = PrinterOptions {
+            color_support: TermColorSupport::Ansi256,
+            ..Default::default()
+        };
+        let out = Mutex::new(vec![]);
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), out, opts);
+        printer.print(sample_chunk("src/main.rs")).unwrap();
+        let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+        assert!(printed.contains("\x1b[38;5;"), "{:?}", printed);
+        assert!(!printed.contains("\x1b[38;2;"), "{:?}", printed);
+    }
+
+    #[test]
+    fn test_auto_theme() {
+        for (color_support, auto, default) in [
+            (TermColorSupport::Ansi16, "ansi", "ansi"),
+            (TermColorSupport::Ansi256, "zenburn", "Monokai Extended"),
+            (
+                TermColorSupport::True,
+                "Monokai Extended",
+                "Monokai Extended",
+            ),
+        ] {
+            for (theme, want) in [(Some("auto"), auto), (None, default)] {
+                let opts = PrinterOptions {
+                    theme,
+                    color_support,
+                    ..Default::default()
+                };
+                let printer = SyntectPrinter::new(Vec::<u8>::new(), opts).unwrap();
+                assert!(
+                    ptr::eq(printer.theme(), &printer.themes.themes[want]),
+                    "{color_support:?} with {theme:?} should choose {want:?}",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_themes() {
+        assert!(load_themes(Some("Nord"))
+            .unwrap()
+            .themes
+            .contains_key("Nord"));
+        let themes = load_themes(Some("base16-ocean.dark")).unwrap();
+        assert!(themes.themes.contains_key("base16-ocean.dark"));
+        let err = load_themes(Some("this theme does not exist"))
+            .err()
+            .unwrap();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Unknown theme"), "message={:?}", msg);
+    }
+
+    #[test]
+    fn test_print_to_mutex_writer() {
+        let opts = PrinterOptions::default();
+        let stdout = DummyStdout::default();
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+        printer.print(sample_chunk("README.md")).unwrap();
+        let want = mem::take(printer.writer_mut()).0.into_inner();
+
+        let opts = PrinterOptions::default();
+        let out = Mutex::new(vec![]);
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), out, opts);
+        printer.print(sample_chunk("README.md")).unwrap();
+        let have = printer.writer.into_inner().unwrap();
+
+        assert!(!have.is_empty());
+        assert_eq!(
+            String::from_utf8_lossy(&want),
+            String::from_utf8_lossy(&have)
+        );
+    }
+
     #[test]
     fn test_unknown_theme() {
         let opts = PrinterOptions {
@@ -1560,6 +3519,222 @@ mod tests {
         assert!(!printed.is_empty());
     }
 
+    #[test]
+    fn test_expand_regions_to_words() {
+        let line = "let foobar = foo_baz(foo, bar.baz);\n";
+        for (regions, want) in [
+            (vec![(7, 10)], vec![(4, 10)]),        // "bar" in "foobar"
+            (vec![(4, 7)], vec![(4, 10)]),         // "foo" in "foobar"
+            (vec![(4, 10)], vec![(4, 10)]),        // Whole word is not changed
+            (vec![(5, 6), (7, 8)], vec![(4, 10)]), // Merged after expansion
+            (vec![(8, 16)], vec![(4, 20)]), // Multiple words: "bar = foo" in "foobar = foo_baz"
+            (vec![(10, 13)], vec![(4, 20)]), // " = " is expanded at both edges
+            (vec![(3, 4)], vec![(0, 10)]),  // Space between "let" and "foobar"
+            (vec![(21, 24), (26, 29)], vec![(21, 24), (26, 29)]),
+            (vec![(28, 28)], vec![(28, 28)]), // Empty region is not expanded
+            (vec![(30, 100)], vec![(30, 100)]), // Region exceeding the line
+            (vec![], vec![]),
+        ] {
+            let have = expand_regions_to_words(line, &regions);
+            assert_eq!(have, want, "regions={:?}", regions);
+        }
+
+        let line = "あいうえお";
+        assert_eq!(expand_regions_to_words(line, &[(3, 6)]), vec![(0, 15)]);
+    }
+
+    #[test]
+    fn test_fold_ranges() {
+        let lmats = |lnums: &[u64]| lnums.iter().copied().map(LineMatch::lnum).collect();
+        let file = |lnums: &[u64], chunks| {
+            File::new(PathBuf::from("x.rs"), lmats(lnums), chunks, String::new())
+        };
+        for (lnums, chunks, keep, want) in [
+            (&[2, 13][..], vec![(1, 14)], 2, vec![(5, 10)]),
+            (&[2, 13], vec![(1, 14)], 0, vec![(3, 12)]),
+            (&[2, 13], vec![(1, 14)], 4, vec![(7, 8)]),
+            (&[2, 13], vec![(1, 14)], 5, vec![]), // Only one line would be folded
+            (&[2, 6, 13], vec![(1, 14)], 1, vec![(8, 11)]),
+            (&[2, 13], vec![(1, 4), (11, 14)], 0, vec![]), // Lines between chunks are not folded
+            (&[2], vec![(1, 14)], 0, vec![]), // Context before/after matches are not folded
+            (&[2, 13], vec![(1, 14)], u64::MAX, vec![]),
+        ] {
+            let have = fold_ranges(&file(lnums, chunks.clone()), keep);
+            assert_eq!(
+                have, want,
+                "lnums={lnums:?}, chunks={chunks:?}, keep={keep}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_minimap_glyphs() {
+        for (lnums, lines, rows, want) in [
+            (&[1, 2][..], 4, 2, "# "),
+            (&[1][..], 4, 2, ": "),
+            (&[1][..], 8, 2, ". "),
+            (&[4, 8][..], 8, 4, " : :"),
+            (&[1][..], 2, 4, "##  "), // Lines are repeated when rows are more than lines
+            (&[][..], 4, 2, "  "),
+            (&[1][..], 0, 0, "#"),
+        ] {
+            let lmats: Vec<_> = lnums.iter().copied().map(LineMatch::lnum).collect();
+            let have: String = minimap_glyphs(&lmats, lines, rows, &MINIMAP_ASCII_CHARS)
+                .into_iter()
+                .collect();
+            assert_eq!(have, want, "lnums={lnums:?}, lines={lines}, rows={rows}");
+        }
+    }
+
+    #[test]
+    fn test_civil_date() {
+        for (secs, want) in [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (951782400, (2000, 2, 29)),
+            (951868800, (2000, 3, 1)),
+            (1714953600 + 86399, (2024, 5, 6)),
+            (4102444800, (2100, 1, 1)),
+        ] {
+            assert_eq!(civil_date(secs), want, "secs={secs}");
+        }
+    }
+
+    #[test]
+    fn test_parse_tz_offset() {
+        for (tz, want) in [
+            ("+0000", Some(0)),
+            ("+0900", Some(9 * 3600)),
+            ("-0530", Some(-(5 * 3600 + 30 * 60))),
+            ("0900", None),
+            ("+09", None),
+            ("", None),
+        ] {
+            assert_eq!(parse_tz_offset(tz), want, "tz={tz:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_blame_porcelain() {
+        let porcelain = "\
+1a2b3c4d5e6f1a2b3c4d5e6f1a2b3c4d5e6f1a2b 1 3 2
+author rhysd
+author-mail <rhysd@example.com>
+author-time 1714953600
+author-tz +0900
+summary first commit
+filename foo.rs
+\tfoo();
+1a2b3c4d5e6f1a2b3c4d5e6f1a2b3c4d5e6f1a2b 2 4
+\tbar();
+0000000000000000000000000000000000000000 8 10 1
+author Not Committed Yet
+author-time 1715040000
+author-tz -0100
+summary Version of foo.rs from foo.rs
+previous 1a2b3c4d5e6f1a2b3c4d5e6f1a2b3c4d5e6f1a2b foo.rs
+filename foo.rs
+\tbaz();
+";
+        let blame = Blame::parse(porcelain);
+        let mut lines: Vec<_> = blame.lines.iter().map(|(l, s)| (*l, s.as_str())).collect();
+        lines.sort();
+        let want = vec![
+            (3, "1a2b3c4 2024-05-06 rhysd"),
+            (4, "1a2b3c4 2024-05-06 rhysd"),
+            (10, "0000000 2024-05-06 Not Committed Yet"),
+        ];
+        assert_eq!(lines, want);
+        assert_eq!(blame.width, "0000000 2024-05-06 Not Committed Yet".len());
+
+        let blame = Blame::parse("");
+        assert!(blame.lines.is_empty());
+        assert_eq!(blame.width, 0);
+    }
+
+    #[test]
+    fn test_blame_with_minimap_at_min_width() {
+        // 10 is the minimum value of --term-width
+        let opts = PrinterOptions {
+            term_width: 10,
+            color_support: TermColorSupport::True,
+            minimap: true,
+            blame: true,
+            ..Default::default()
+        };
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), Mutex::new(vec![]), opts);
+        printer.print(sample_chunk("src/broken_pipe.rs")).unwrap();
+        let printed = String::from_utf8(printer.writer.into_inner().unwrap()).unwrap();
+        assert!(printed.contains("src/broken_pipe.rs"), "{printed:?}");
+    }
+
+    #[test]
+    fn test_blame_untracked_file() {
+        let dir = TempDir::new("blame");
+        let path = dir.path().join("untracked.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+        let blame = Blame::load(&path, &[LineMatch::lnum(1)]);
+        assert!(blame.is_none());
+    }
+
+    #[test]
+    fn test_control_picture() {
+        for (c, want) in [
+            ('\r', Some('\u{240d}')),
+            ('\0', Some('\u{2400}')),
+            ('\x1b', Some('\u{241b}')),
+            ('\u{1f}', Some('\u{241f}')),
+            ('\u{7f}', Some('\u{2421}')),
+            ('\t', None),
+            (' ', None),
+            ('a', None),
+            ('\u{80}', None),
+        ] {
+            assert_eq!(control_picture(c), want, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn test_dedup_chunks() {
+        let contents = "foo\nbar\nfoo\nfoo \nfoo\nbar\nfoo\n".to_string();
+        let lmats = [1, 2, 3, 4, 5, 6, 7].map(LineMatch::lnum).to_vec();
+        let chunks = vec![(1, 2), (3, 3), (4, 4), (5, 6), (7, 7)];
+        let file = File::new(PathBuf::from("x.rs"), lmats, chunks, contents);
+        let dedup = DedupChunks::new(&file);
+        assert_eq!(dedup.chunks, vec![(1, 2), (3, 3), (4, 4)]);
+        let lnums: Vec<_> = dedup.line_matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(lnums, vec![1, 2, 3, 4]);
+        assert_eq!(dedup.identical, vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn test_find_modeline_filetype() {
+        for (contents, want) in [
+            ("# vim: set ft=ruby:\nfoo\n", Some("ruby")),
+            ("/* vim: set filetype=python : */\n", Some("python")),
+            ("// vim: ts=2 ft=javascript sw=2\n", Some("javascript")),
+            ("// vim:ft=sh:ts=4\n", Some("sh")),
+            ("# vi: syntax=perl\n", Some("perl")),
+            ("# ex: set syn=lua:\n", Some("lua")),
+            ("vim: set ft=c ft=cpp:\n", Some("cpp")),
+            ("a\nb\nc\nd\ne\nf\n# vim: ft=make\n", Some("make")),
+            ("# vim: ft=make\na\nb\nc\nd\ne\nf\n", Some("make")),
+            ("a\nb\nc\nd\ne\n# vim: ft=make\nf\ng\nh\ni\nj\nk\n", None),
+            ("# nvim: set ft=ruby:\n", None),
+            ("# vim: set ts=2:\n", None),
+            ("# vim: set ft=:\n", None),
+            ("no modeline here\n", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                find_modeline_filetype(contents),
+                want,
+                "contents={:?}",
+                contents
+            );
+        }
+    }
+
     #[test]
     fn test_adjacent_regions() {
         let contents = "this is test\n";
@@ -1567,6 +3742,7 @@ mod tests {
         let lmats = vec![LineMatch {
             line_number: 1,
             ranges,
+            continued: false,
         }];
         let chunks = vec![(1, 1)];
         let file = File::new(
@@ -1612,6 +3788,11 @@ mod tests {
         assert_eq!(&format!("{}", err), "dummy error!", "message={}", err);
     }
 
+    #[test]
+    fn test_no_error_at_broken_pipe_on_list_theme_names() {
+        list_theme_names(ErrorStdoutLock(io::ErrorKind::BrokenPipe)).unwrap();
+    }
+
     #[test]
     fn test_no_error_at_broken_pip_on_list_themes() {
         let opts = PrinterOptions::default();
@@ -1703,4 +3884,38 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_find_syntax_from_modeline() {
+        let tests = [
+            // Modeline takes precedence over file extension, file name, and first line
+            ("foo.rs", "// vim: ft=ruby", true, "Ruby"),
+            ("Containerfile", "# vim: ft=ruby", true, "Ruby"),
+            ("foo", "#!/bin/bash\n# vim: ft=ruby", true, "Ruby"),
+            // Unknown filetype in modeline falls back to the usual detection
+            ("foo.rs", "// vim: ft=foooooooo", true, "Rust"),
+            (
+                "foo",
+                "#!/bin/bash\n# vim: ft=foooooooo",
+                true,
+                "Bourne Again Shell (bash)",
+            ),
+            // Modeline is ignored without --respect-modeline
+            ("foo.rs", "// vim: ft=ruby", false, "Rust"),
+        ];
+
+        for (path, contents, respect_modeline, name) in tests {
+            let opts = PrinterOptions {
+                respect_modeline,
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            let file = File::new(PathBuf::from(path), vec![], vec![], contents.to_string());
+            let syntax = printer.find_syntax(&file);
+            assert_eq!(
+                syntax.name, name,
+                "path={path:?}, contents={contents:?}, respect_modeline={respect_modeline}",
+            );
+        }
+    }
 }
@@ -3,7 +3,8 @@ use crate::grep::GrepMatch;
 use anyhow::Result;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
 
 pub(crate) fn read_matches<S: AsRef<str>>(dir: &Path, input: S) -> Vec<Result<GrepMatch>> {
     let path = dir.join(format!("{}.in", input.as_ref()));
@@ -18,6 +19,7 @@ pub(crate) fn read_matches<S: AsRef<str>>(dir: &Path, input: S) -> Vec<Result<Gr
                 path: path.into(),
                 line_number: idx as u64 + 1,
                 ranges: vec![],
+                continued: false,
             })
         })
         .collect::<Vec<Result<GrepMatch>>>()
@@ -109,3 +111,26 @@ impl Drop for EnvGuard {
         }
     }
 }
+
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("hgrep-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
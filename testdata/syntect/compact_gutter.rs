@@ -0,0 +1,12 @@
+fn main() {
+    let x = 1;
+    println!("*match to this line*");
+    let y = 2;
+    let z = 3;
+    let s = "this is a very long line to be wrapped at the edge of the terminal *match to this line*";
+    println!("*match to this line*");
+    let w = 4;
+    let v = 5;
+    let u = 6;
+    println!("{x} {y} {z} {s} {w} {v} {u} *match to this line*");
+}
@@ -0,0 +1,12 @@
+fn main() {
+    println!("*match to this line*");
+    let a = 1;
+    let b = 2;
+    let c = 3;
+    let d = 4;
+    let e = 5;
+    let f = 6;
+    let g = 7;
+    let h = 8;
+    println!("*match to this line* {}", a + b + c + d + e + f + g + h);
+}
@@ -0,0 +1,35 @@
+fn main() {
+    fn foo() {
+        let x = 1;
+        let y = 2;
+        let z = x + y;
+        println!("*match to this line*");
+        println!("{}", z);
+    }
+
+    foo();
+
+
+    fn bar() {
+        let a = 1;
+        let b = 2;
+        let c = a * b;
+        println!("{}", c);
+        let d = c + 1;
+        println!("*match to this line*");
+    }
+
+    bar();
+
+
+    fn piyo() {
+        let s = "hello";
+        let t = "world";
+        println!("{} {}", s, t);
+    }
+
+    piyo();
+    println!("*match to that line*");
+    foo();
+    bar();
+}
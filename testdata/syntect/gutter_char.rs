@@ -0,0 +1,5 @@
+fn main() {
+    let x = 1;
+    println!("*match to this line* and this line is long enough to be wrapped at the terminal width");
+    println!("{}", x);
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let s = "foo_*match to this line*_bar";
+    let t = "prefix*match to first line*mid*match to second line*suffix";
+    let u = "(*match to this line*)";
+    let v = "あいう*match to this line*えお";
+}
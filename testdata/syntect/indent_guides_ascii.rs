@@ -0,0 +1,12 @@
+mod outer {
+    mod inner {
+        fn nested() {
+            if true {
+                for i in 0..3 {
+                    println!("*match to this line* {}", i);
+                }
+            }
+		// *match to this line* indented with tabs
+        }
+    }
+}
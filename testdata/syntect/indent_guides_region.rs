@@ -0,0 +1,7 @@
+fn main() {
+    if true {
+        // *match to this line*
+        let x = 1;
+            // *match to this line*
+    }
+}
@@ -0,0 +1,24 @@
+fn main() {
+    let x = 1;
+    let y = 2;
+    println!("*match to this line*");
+    let z = 3;
+    let s = "this is a very long line to be wrapped at the edge of the terminal *match to this line*";
+    let v7 = 7;
+    let v8 = 8;
+    let v9 = 9;
+    let v10 = 10;
+    let v11 = 11;
+    let v12 = 12;
+    let v13 = 13;
+    let v14 = 14;
+    let v15 = 15;
+    let v16 = 16;
+    let v17 = 17;
+    let v18 = 18;
+    let v19 = 19;
+    let v20 = 20;
+    println!("{x} {y} {z} {s} *match to this line*");
+    let a = 22;
+    let b = 23;
+}
@@ -0,0 +1,15 @@
+fn main() {
+    fn foo() {
+        println!("*match to this line*");
+    }
+    fn bar() {
+        println!("*match to this line*");
+        println!("*match to next line*");
+    }
+
+    foo();
+    bar();
+    println!("*match to a line*", "*match to b line*");
+    foo();
+    bar();
+}
@@ -0,0 +1,24 @@
+fn main() {
+    println!("*match to this line*");
+    println!("*match to this line*");
+    println!("*match to this line*");
+    println!("*match to this line* and this line is so long that it is wrapped at the right margin of the terminal");
+    let x = 1;
+    let y = 2;
+    let z = x + y;
+    println!("{}", z);
+    let a = vec![x, y, z];
+    println!("{:?}", a);
+    let b = a.len();
+    println!("{}", b);
+    let c = b * 2;
+    println!("{}", c);
+    let d = c + 1;
+    println!("{}", d);
+    let e = d - 1;
+    println!("*match to this line* {}", e);
+    let f = e / 2;
+    println!("{}", f);
+    let g = f % 3;
+    println!("{}", g);
+}
@@ -0,0 +1,5 @@
+fn main() {
+    //                         (width considering line number) 80 cols -> |
+    println!("*match to あ line* {}", &["*match to foo line*", "*match to い line*", "*match to う line*"]);
+    println!("日本語のコメント");
+}
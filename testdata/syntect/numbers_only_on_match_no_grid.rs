@@ -0,0 +1,8 @@
+fn main() {
+    let x = 1;
+    println!("*match to this line* {}", x);
+    let y = 2;
+    println!("*match to this line* {}", y);
+    let z = x + y;
+    println!("{}", z);
+}
@@ -0,0 +1,9 @@
+fn main() {
+    let s = "*match to the
+multi-line
+string line*";
+    let t = "*match to another
+multi-line string
+line*";
+    println!("*match to this line*");
+}
@@ -0,0 +1,4 @@
+fn main() {
+    //                         (width considering line number) 80 cols -> |
+    println!("{}", &["aaaaaaaaaaaaaaaaaaaaaaa", "bbbbbbbbbbb", "*match to this line*", "ccccccccccc"]);
+}
@@ -0,0 +1,10 @@
+// This file is rendered as a whole without searching
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    for (i, arg) in args.iter().enumerate() {
+        println!("{}: {}", i, arg);
+    }
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let s = "*match to the
+multi-line
+string line*";
+    println!("*match to this line*");
+}
@@ -0,0 +1,4 @@
+# vim: set ft=ruby:
+def foo
+  puts "*match to this line*"
+end
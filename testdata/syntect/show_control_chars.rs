@@ -0,0 +1,8 @@
+fn main() {
+    let x = 1;
+    println!("*match to this line* \r {}", x);
+    let y = "[31mred[0m";
+    println!("*match to this line* {}", y);
+    let z = x + 1;
+    println!("{}", z);
+}
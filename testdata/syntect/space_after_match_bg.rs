@@ -0,0 +1,10 @@
+fn main() {
+    let a = "*match to a line*";
+    let b = "*match to b line*";
+    let c = "*match to c line*";
+    println!("{}", a);
+    println!("{}", b);
+    let d = "*match to d line*";
+    println!("{}", c);
+    println!("{}", d);
+}
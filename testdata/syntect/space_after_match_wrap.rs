@@ -0,0 +1,6 @@
+fn main() {
+    let a = "*match to a line*"; let aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa = 1;
+    let b = "*match to b line*";
+    println!("{}", a);
+    println!("{}", b);
+}
@@ -0,0 +1,5 @@
+fn main() {
+    //                         (width considering line number) 80 cols -> |
+    //                                                                                                   (width considering line number) 160 cols -> |
+    println!("*match to this line* {}", &["aaaaaaaaaaaaaaaaaaaaaaaaaa", "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", "cccccccccccccccccccccccccccccc", "ddddddddddddddd"]);
+}
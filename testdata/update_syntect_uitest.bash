@@ -17,7 +17,7 @@ export HGREP_DEFAULT_OPTS=
 HGREP="$(pwd)/target/release/hgrep"
 
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --theme ansi              ./testdata/syntect/ansi16_colors.rs                > ./testdata/syntect/ansi16_colors.out
-env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80              ./testdata/syntect/ansi256_colors.rs               > ./testdata/syntect/ansi256_colors.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --color-scheme-256      ./testdata/syntect/ansi256_colors.rs               > ./testdata/syntect/ansi256_colors.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/background.rs                   > ./testdata/syntect/background.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/default.rs                      > ./testdata/syntect/default.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --tab 0                   ./testdata/syntect/hard_tab.rs                     > ./testdata/syntect/hard_tab.out
@@ -35,7 +35,7 @@ env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-wi
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_twice.rs                   > ./testdata/syntect/wrap_twice.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --no-grid                 ./testdata/syntect/wrap_no_grid.rs                 > ./testdata/syntect/wrap_no_grid.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --theme Nord              ./testdata/syntect/wrap_theme.rs                   > ./testdata/syntect/wrap_theme.out
-env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80              ./testdata/syntect/wrap_ansi256.rs                 > ./testdata/syntect/wrap_ansi256.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --color-scheme-256      ./testdata/syntect/wrap_ansi256.rs                 > ./testdata/syntect/wrap_ansi256.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/wrap_middle_text_bg.rs          > ./testdata/syntect/wrap_middle_text_bg.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/wrap_between_bg.rs              > ./testdata/syntect/wrap_between_bg.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --wrap never              ./testdata/syntect/no_wrap_default.rs              > ./testdata/syntect/no_wrap_default.out
@@ -45,6 +45,15 @@ env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-wi
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/multi_chunks_default.rs         > ./testdata/syntect/multi_chunks_default.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --no-grid                 ./testdata/syntect/multi_chunks_no_grid.rs         > ./testdata/syntect/multi_chunks_no_grid.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/multi_chunks_bg.rs              > ./testdata/syntect/multi_chunks_bg.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --chunk-headers           ./testdata/syntect/chunk_headers.rs                > ./testdata/syntect/chunk_headers.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --chunk-headers --no-grid ./testdata/syntect/chunk_headers_no_grid.rs        > ./testdata/syntect/chunk_headers_no_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --separator-style blank           ./testdata/syntect/separator_blank.rs           > ./testdata/syntect/separator_blank.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --separator-style blank --no-grid ./testdata/syntect/separator_blank_no_grid.rs   > ./testdata/syntect/separator_blank_no_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --separator-style dots            ./testdata/syntect/separator_dots.rs            > ./testdata/syntect/separator_dots.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --separator-style dots --no-grid  ./testdata/syntect/separator_dots_no_grid.rs    > ./testdata/syntect/separator_dots_no_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --space-after-match              ./testdata/syntect/space_after_match.rs         > ./testdata/syntect/space_after_match.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --space-after-match --background ./testdata/syntect/space_after_match_bg.rs      > ./testdata/syntect/space_after_match_bg.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --space-after-match              ./testdata/syntect/space_after_match_wrap.rs    > ./testdata/syntect/space_after_match_wrap.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/japanese_default.rs             > ./testdata/syntect/japanese_default.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/japanese_background.rs          > ./testdata/syntect/japanese_background.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_japanese_after.rs          > ./testdata/syntect/wrap_japanese_after.out
@@ -74,19 +83,50 @@ env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-wi
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_whole_3_lines.rs           > ./testdata/syntect/wrap_whole_3_lines.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_3_lines_emoji.rs           > ./testdata/syntect/wrap_3_lines_emoji.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --first-only              ./testdata/syntect/first_only.rs                   > ./testdata/syntect/first_only.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --last-only               ./testdata/syntect/last_only.rs                    > ./testdata/syntect/last_only.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --ascii-lines             ./testdata/syntect/ascii_lines_grid.rs             > ./testdata/syntect/ascii_lines_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --gutter-char '┃'         ./testdata/syntect/gutter_char.rs                  > ./testdata/syntect/gutter_char.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --match-marker '▶'        ./testdata/syntect/match_marker.rs                 > ./testdata/syntect/match_marker.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --match-marker '*' --no-grid ./testdata/syntect/match_marker_no_grid.rs       > ./testdata/syntect/match_marker_no_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --highlight-word          ./testdata/syntect/highlight_word.rs               > ./testdata/syntect/highlight_word.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --first-region-per-line   ./testdata/syntect/first_region_per_line.rs        > ./testdata/syntect/first_region_per_line.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --region-style reverse      ./testdata/syntect/region_reverse.rs               > ./testdata/syntect/region_reverse.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --region-style reverse      ./testdata/syntect/region_reverse_wrap.rs          > ./testdata/syntect/region_reverse_wrap.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --monospace-width         ./testdata/syntect/monospace_width.rs              > ./testdata/syntect/monospace_width.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --context-fold 2          ./testdata/syntect/context_fold.rs                 > ./testdata/syntect/context_fold.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background-color '#1e1e1e' ./testdata/syntect/background_color.rs           > ./testdata/syntect/background_color.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --numbers-only-on-match   ./testdata/syntect/numbers_only_on_match.rs        > ./testdata/syntect/numbers_only_on_match.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --numbers-only-on-match --no-grid ./testdata/syntect/numbers_only_on_match_no_grid.rs > ./testdata/syntect/numbers_only_on_match_no_grid.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --dedup-lines             ./testdata/syntect/dedup_lines.rs                  > ./testdata/syntect/dedup_lines.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --no-match-highlight      ./testdata/syntect/no_match_highlight.rs           > ./testdata/syntect/no_match_highlight.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --show-control-chars      ./testdata/syntect/show_control_chars.rs           > ./testdata/syntect/show_control_chars.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --minimap                 ./testdata/syntect/minimap.rs                      > ./testdata/syntect/minimap.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --minimap --background    ./testdata/syntect/minimap_bg.rs                   > ./testdata/syntect/minimap_bg.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --indent-guides           ./testdata/syntect/indent_guides.rs                > ./testdata/syntect/indent_guides.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --indent-guides --ascii-lines ./testdata/syntect/indent_guides_ascii.rs      > ./testdata/syntect/indent_guides_ascii.out
+"$HGREP" '^.*\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --indent-guides         ./testdata/syntect/indent_guides_region.rs         > ./testdata/syntect/indent_guides_region.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --max-chunk-width 40 --background ./testdata/syntect/max_chunk_width.rs > ./testdata/syntect/max_chunk_width.out
+"$HGREP" -p syntect --term-width 80 --render-only ./testdata/syntect/render_only.rs > ./testdata/syntect/render_only.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --ascii-lines --no-grid   ./testdata/syntect/ascii_lines_no_grid.rs          > ./testdata/syntect/ascii_lines_no_grid.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/multi_regions.rs                > ./testdata/syntect/multi_regions.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --background              ./testdata/syntect/multi_regions_bg.rs             > ./testdata/syntect/multi_regions_bg.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_between_regions.rs         > ./testdata/syntect/wrap_between_regions.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --wrap-marker             ./testdata/syntect/wrap_marker.rs                  > ./testdata/syntect/wrap_marker.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --wrap-marker             ./testdata/syntect/wrap_marker_region.rs           > ./testdata/syntect/wrap_marker_region.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_accross_regions.rs         > ./testdata/syntect/wrap_accross_regions.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_regions_japanese.rs        > ./testdata/syntect/wrap_regions_japanese.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --matches-only            ./testdata/syntect/matches_only.rs                 > ./testdata/syntect/matches_only.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 -U --multiline-dotall --replace-newline ./testdata/syntect/replace_newline.rs > ./testdata/syntect/replace_newline.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --respect-modeline        ./testdata/syntect/respect_modeline.rs             > ./testdata/syntect/respect_modeline.out
 
 # Test for --list-themes
 "$HGREP" --list-themes -p syntect --term-width 80              > ./testdata/syntect/list_themes_default.out
 "$HGREP" --list-themes -p syntect --term-width 80 --no-grid    > ./testdata/syntect/list_themes_no_grid.out
 "$HGREP" --list-themes -p syntect --term-width 80 --background > ./testdata/syntect/list_themes_background.out
 
+# Test for --theme-preview
+"$HGREP" --theme-preview Nord -p syntect --term-width 80 > ./testdata/syntect/theme_preview_nord.out
+
 # Previews
 cat ./testdata/syntect/ansi16_colors.out
 cat ./testdata/syntect/ansi256_colors.out
@@ -116,6 +156,15 @@ cat ./testdata/syntect/multi_line_numbers.out
 cat ./testdata/syntect/multi_chunks_default.out
 cat ./testdata/syntect/multi_chunks_no_grid.out
 cat ./testdata/syntect/multi_chunks_bg.out
+cat ./testdata/syntect/chunk_headers.out
+cat ./testdata/syntect/chunk_headers_no_grid.out
+cat ./testdata/syntect/separator_blank.out
+cat ./testdata/syntect/separator_blank_no_grid.out
+cat ./testdata/syntect/separator_dots.out
+cat ./testdata/syntect/separator_dots_no_grid.out
+cat ./testdata/syntect/space_after_match.out
+cat ./testdata/syntect/space_after_match_bg.out
+cat ./testdata/syntect/space_after_match_wrap.out
 cat ./testdata/syntect/japanese_default.out
 cat ./testdata/syntect/japanese_background.out
 cat ./testdata/syntect/wrap_japanese_after.out
@@ -145,14 +194,43 @@ cat ./testdata/syntect/whole_line.out
 cat ./testdata/syntect/wrap_whole_3_lines.out
 cat ./testdata/syntect/wrap_3_lines_emoji.out
 cat ./testdata/syntect/first_only.out
+cat ./testdata/syntect/last_only.out
 cat ./testdata/syntect/ascii_lines_grid.out
+cat ./testdata/syntect/gutter_char.out
+cat ./testdata/syntect/match_marker.out
+cat ./testdata/syntect/match_marker_no_grid.out
+cat ./testdata/syntect/highlight_word.out
+cat ./testdata/syntect/first_region_per_line.out
+cat ./testdata/syntect/region_reverse.out
+cat ./testdata/syntect/region_reverse_wrap.out
+cat ./testdata/syntect/monospace_width.out
+cat ./testdata/syntect/context_fold.out
+cat ./testdata/syntect/background_color.out
+cat ./testdata/syntect/numbers_only_on_match.out
+cat ./testdata/syntect/numbers_only_on_match_no_grid.out
+cat ./testdata/syntect/dedup_lines.out
+cat ./testdata/syntect/no_match_highlight.out
+cat ./testdata/syntect/show_control_chars.out
+cat ./testdata/syntect/minimap.out
+cat ./testdata/syntect/minimap_bg.out
+cat ./testdata/syntect/indent_guides.out
+cat ./testdata/syntect/indent_guides_ascii.out
+cat ./testdata/syntect/indent_guides_region.out
+cat ./testdata/syntect/max_chunk_width.out
+cat ./testdata/syntect/render_only.out
 cat ./testdata/syntect/ascii_lines_no_grid.out
 cat ./testdata/syntect/multi_regions.out
 cat ./testdata/syntect/multi_regions_bg.out
 cat ./testdata/syntect/wrap_between_regions.out
+cat ./testdata/syntect/wrap_marker.out
+cat ./testdata/syntect/wrap_marker_region.out
 cat ./testdata/syntect/wrap_accross_regions.out
 cat ./testdata/syntect/wrap_regions_japanese.out
+cat ./testdata/syntect/matches_only.out
+cat ./testdata/syntect/replace_newline.out
+cat ./testdata/syntect/respect_modeline.out
 
 cat ./testdata/syntect/list_themes_default.out
 cat ./testdata/syntect/list_themes_no_grid.out
 cat ./testdata/syntect/list_themes_background.out
+cat ./testdata/syntect/theme_preview_nord.out